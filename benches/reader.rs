@@ -0,0 +1,104 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Criterion, Throughput};
+use lang::reader::{cst, Reader};
+use lang::stx::StxArena;
+
+/// A program of a few thousand lines, with the definitions, comments and
+/// nesting of hand written code.
+fn program_src() -> String {
+    let mut src = String::new();
+
+    for i in 0..1000 {
+        src.push_str(&format!(
+            ";;; Computes the {i}th thing, documented like real code is.\n\
+             (define (compute-{i} xs #:key [scale 1.0])\n\
+             \x20 (let loop ([xs xs] [acc '()])\n\
+             \x20   (cond\n\
+             \x20     [(null? xs) (reverse acc)]\n\
+             \x20     [else (loop (cdr xs) (cons (* scale (car xs) {i}) acc))])))\n\
+             #| an aside |# (display \"compute-{i}: \\\"done\\\"\\n\")\n\n",
+            i = i
+        ));
+    }
+
+    src
+}
+
+/// A large data file: one long list of records holding numbers, strings,
+/// vectors and bytevectors.
+fn data_src() -> String {
+    let mut src = String::from("(\n");
+
+    for i in 0..20_000 {
+        src.push_str(&format!(
+            "  (record {} \"name-{}\" #(1.5 -2 3/4 #xff) #u8({} 0 255) (tags a b c) #t #\\x)\n",
+            i,
+            i,
+            i % 256
+        ));
+    }
+
+    src.push_str(")\n");
+    src
+}
+
+fn read_src(src: &str) -> usize {
+    let forms = Reader::new(src).read_all().unwrap();
+    let count = forms.len();
+    black_box(forms);
+    count
+}
+
+fn read_arena(src: &str) -> usize {
+    let mut arena = StxArena::new();
+    Reader::new(src).read_with(&mut arena).unwrap();
+    let count = arena.roots().len();
+    black_box(arena);
+    count
+}
+
+fn reader_bench(c: &mut Criterion) {
+    let program = program_src();
+    let data = data_src();
+    let corpora = [("program", &program), ("data", &data)];
+
+    let mut group = c.benchmark_group("read_bytes");
+    for (name, src) in corpora.iter() {
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_function(*name, |b| b.iter(|| read_src(src)));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("arena_bytes");
+    for (name, src) in corpora.iter() {
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_function(*name, |b| b.iter(|| read_arena(src)));
+    }
+    group.finish();
+
+    // the program's top-level forms, and the records of the data file
+    let mut group = c.benchmark_group("read_forms");
+    let forms = read_src(&program) as u64;
+    group.throughput(Throughput::Elements(forms));
+    group.bench_function("program", |b| b.iter(|| read_src(&program)));
+    group.throughput(Throughput::Elements(20_000));
+    group.bench_function("data", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&data);
+            black_box(reader.read_datum().unwrap());
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("cst_bytes");
+    for (name, src) in corpora.iter() {
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_function(*name, |b| b.iter(|| black_box(cst::parse(src))));
+    }
+    group.finish();
+}
+
+criterion::criterion_group!(benches, reader_bench);
+criterion::criterion_main!(benches);
@@ -1,7 +1,7 @@
 #[macro_use]
 extern crate criterion;
 
-use criterion::Criterion;
+use criterion::{black_box, Criterion, Throughput};
 use lang::scanner::Scanner;
 
 static SRC: &str = r#"(def long 5.0) ; this will be a very long source
@@ -11,15 +11,46 @@ static SRC: &str = r#"(def long 5.0) ; this will be a very long source
         ; just another comment about the code"#;
 
 fn scan_src(src: &str) {
-    let mut scanner = Scanner::new(SRC);
+    let scanner = Scanner::new(src);
 
     let lexemes = scanner.collect::<Vec<_>>();
+    black_box(lexemes);
+}
+
+fn long_runs_src() -> String {
+    let mut src = String::new();
+
+    for i in 0..2000 {
+        src.push_str(&format!(
+            ";; {} a long comment line that goes on for a while before it ends\n",
+            "x".repeat(i % 60)
+        ));
+        src.push_str(&format!(
+            "(define-something-with-a-long-name value-{} \"{}\")\n",
+            i,
+            "string content ".repeat(i % 20)
+        ));
+        src.push_str(&" ".repeat(i % 40));
+        src.push_str("#| block\n comment |#\n");
+    }
+
+    src
 }
 
 fn scanner_bench(c: &mut Criterion)
 {
     c.bench_function("scan", |b| b.iter(|| scan_src(SRC)));
+
+    let large = SRC.repeat(2000);
+    let long_runs = long_runs_src();
+
+    let mut group = c.benchmark_group("scan_throughput");
+    for (name, src) in [("program", &large), ("long_runs", &long_runs)].iter() {
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_function(*name, |b| b.iter(|| scan_src(src)));
+    }
+    group.finish();
 }
 
 criterion::criterion_group!(benches, scanner_bench);
-criterion::criterion_main!(benches);
\ No newline at end of file
+criterion::criterion_main!(benches);
@@ -0,0 +1,54 @@
+//! Feeds arbitrary bytes through the scanner and every way of reading, none
+//! of which may panic or fail to terminate.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use lang::reader::events::{Delim, ReadEvents};
+use lang::reader::sink::{Flow, ReadSink};
+use lang::reader::{cst, Reader, ReaderOptions};
+use lang::scanner::Scanner;
+use lang::span::Span;
+use lang::stx::Stx;
+
+struct Ignore;
+
+impl<'a> ReadSink<'a> for Ignore {
+    fn open(&mut self, _: Delim, _: Span) -> Flow {
+        Flow::Continue
+    }
+
+    fn atom(&mut self, _: Stx<'a>) -> Flow {
+        Flow::Continue
+    }
+
+    fn close(&mut self, _: Span) -> Flow {
+        Flow::Continue
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let options = ReaderOptions::new().maps(true).sets(true);
+
+    Scanner::from_bytes(data).for_each(drop);
+
+    let _ = Reader::from_slice(data).read_all();
+    let _ = Reader::from_slice(data).read_all_with_recovery();
+    let _ = Reader::from_slice(data)
+        .with_options(options)
+        .read_all_with_recovery();
+    let _ = Reader::from_slice(data).read_interactive();
+    let _ = Reader::from_slice(data)
+        .with_options(options)
+        .read_with(&mut Ignore);
+
+    let mut reader = Reader::from_slice(data);
+    while let Ok(Some(_)) = reader.read_datum() {}
+
+    Reader::from_reader(data).for_each(drop);
+
+    if let Ok(text) = core::str::from_utf8(data) {
+        ReadEvents::new(text).for_each(drop);
+        let _ = cst::parse(text);
+    }
+});
@@ -0,0 +1,189 @@
+//! Structured reports of problems in source, shared by every tool that
+//! shows them.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::slice;
+
+use crate::error::{ReadError, ReadErrorKind};
+use crate::source::FileId;
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A span related to a diagnostic, in the same file as its primary span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A stable identifier for the kind of problem, like
+    /// `ReadErrorKind::code`.
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub file: Option<FileId>,
+    pub labels: Vec<Label>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: String, span: Span) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            span,
+            file: None,
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.labels.push(Label { span, message });
+        self
+    }
+}
+
+impl From<ReadError> for Diagnostic {
+    fn from(err: ReadError) -> Self {
+        let mut diagnostic = Diagnostic::new(
+            Severity::Error,
+            err.kind.code(),
+            err.kind.to_string(),
+            err.span,
+        );
+        diagnostic.file = err.file;
+
+        match err.kind {
+            ReadErrorKind::MismatchedDelimiter { open_span, .. } => {
+                diagnostic.with_label(open_span, "list opened here".to_string())
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+/// Collects diagnostics in the order they were found, see
+/// `Reader::read_all_with_diagnostics`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn into_vec(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
+
+impl Extend<Diagnostic> for Diagnostics {
+    fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, iter: I) {
+        self.diagnostics.extend(iter);
+    }
+}
+
+impl<'d> IntoIterator for &'d Diagnostics {
+    type Item = &'d Diagnostic;
+    type IntoIter = slice::Iter<'d, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::reader::Reader;
+    use crate::scanner::LexemeKind;
+    use alloc::vec;
+
+    #[test]
+    fn test_read_diagnostics() {
+        let mut diagnostics = Diagnostics::new();
+        let forms = Reader::new("(a] b) #\\nwline")
+            .with_file(FileId(3))
+            .read_all_with_diagnostics(&mut diagnostics);
+
+        assert_eq!(forms.len(), 2);
+        assert!(diagnostics.has_errors());
+        assert_eq!(
+            diagnostics.into_vec(),
+            vec![
+                Diagnostic {
+                    severity: Severity::Error,
+                    code: "R0003",
+                    message: "mismatched close delimiter".to_string(),
+                    span: Span::new(2, 3),
+                    file: Some(FileId(3)),
+                    labels: vec![Label {
+                        span: Span::new(0, 1),
+                        message: "list opened here".to_string(),
+                    }],
+                },
+                Diagnostic {
+                    severity: Severity::Error,
+                    code: "R0012",
+                    message: "unknown character name, did you mean `#\\newline`?".to_string(),
+                    span: Span::new(7, 15),
+                    file: Some(FileId(3)),
+                    labels: vec![],
+                },
+            ]
+        );
+
+        let err = ReadError::new(
+            ReadErrorKind::MismatchedDelimiter {
+                open: LexemeKind::LParen,
+                open_span: Span::new(0, 1),
+            },
+            Span::new(2, 3),
+        );
+        assert_eq!(Diagnostic::from(err).file, None);
+    }
+}
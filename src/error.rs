@@ -0,0 +1,203 @@
+use core::fmt;
+
+use crate::scanner::{LexemeKind, ScanErrorKind};
+use crate::source::FileId;
+use crate::span::{FileSpan, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadErrorKind {
+    /// A lexeme the scanner rejected, for reasons with no more specific
+    /// reader error.
+    Lex(ScanErrorKind),
+    /// A close delimiter outside of any list.
+    UnexpectedClose,
+    /// A close delimiter that doesn't match the innermost open one.
+    MismatchedDelimiter {
+        open: LexemeKind,
+        open_span: Span,
+    },
+    UnterminatedList,
+    UnterminatedString,
+    /// A `.` anywhere but before the last item of a list.
+    UnexpectedDot,
+    /// Input ended, or a list closed, where a datum was required.
+    MissingDatum,
+    /// A lexeme the reader has no datum syntax for.
+    UnexpectedToken,
+    BadNumber,
+    InvalidEscape,
+    InvalidChar,
+    /// `#\name` with a name that isn't known, and the known one closest to it
+    /// if any is close.
+    UnknownCharName {
+        suggestion: Option<&'static str>,
+    },
+    /// A bytevector element that isn't an integer from 0 to 255.
+    InvalidByte,
+    /// Lists nested deeper than the reader's limit.
+    TooDeep,
+    /// `#N#` with no `#N=` before it in the same datum.
+    UndefinedLabel,
+    /// `#N=` for a label already defined in the same datum.
+    DuplicateLabel,
+    /// `#N#` inside the datum labelled `N`, which strict reading rejects.
+    CyclicLabel,
+    /// More labels in one datum than the reader's limit.
+    TooManyLabels,
+    /// The last key of a map literal with an odd number of items.
+    MissingMapValue,
+    /// A set literal element equal to an earlier one.
+    DuplicateSetElement,
+    /// Input past one of the limits set with `ReaderOptions`. Reading ends
+    /// there, even when recovering from errors.
+    LimitExceeded(Limit),
+}
+
+/// The limits `ReaderOptions` can set on untrusted input, besides how deeply
+/// lists nest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    /// `ReaderOptions::max_datums`
+    Datums,
+    /// `ReaderOptions::max_total_atoms`
+    TotalAtoms,
+    /// `ReaderOptions::max_string_len`
+    StringLen,
+}
+
+impl Limit {
+    pub fn message(self) -> &'static str {
+        match self {
+            Limit::Datums => "too many datums",
+            Limit::TotalAtoms => "too many atoms",
+            Limit::StringLen => "string is too long",
+        }
+    }
+}
+
+impl ReadErrorKind {
+    pub fn message(self) -> &'static str {
+        match self {
+            ReadErrorKind::Lex(err) => err.message(),
+            ReadErrorKind::UnexpectedClose => "unexpected close delimiter",
+            ReadErrorKind::MismatchedDelimiter { .. } => "mismatched close delimiter",
+            ReadErrorKind::UnterminatedList => "unterminated list",
+            ReadErrorKind::UnterminatedString => "unterminated string",
+            ReadErrorKind::UnexpectedDot => "unexpected `.`",
+            ReadErrorKind::MissingDatum => "expected a datum",
+            ReadErrorKind::UnexpectedToken => "unexpected token",
+            ReadErrorKind::BadNumber => "malformed number",
+            ReadErrorKind::InvalidEscape => "invalid escape sequence",
+            ReadErrorKind::InvalidChar => "invalid character literal",
+            ReadErrorKind::UnknownCharName { .. } => "unknown character name",
+            ReadErrorKind::InvalidByte => "bytevector element is not a byte",
+            ReadErrorKind::TooDeep => "lists are nested too deeply",
+            ReadErrorKind::UndefinedLabel => "reference to an undefined datum label",
+            ReadErrorKind::DuplicateLabel => "datum label is already defined",
+            ReadErrorKind::CyclicLabel => "datum label refers to itself",
+            ReadErrorKind::TooManyLabels => "too many datum labels",
+            ReadErrorKind::MissingMapValue => "map key has no value",
+            ReadErrorKind::DuplicateSetElement => "set element is a duplicate",
+            ReadErrorKind::LimitExceeded(limit) => limit.message(),
+        }
+    }
+}
+
+impl ReadErrorKind {
+    /// A stable identifier for the kind of error, for tools to match on.
+    pub fn code(self) -> &'static str {
+        match self {
+            ReadErrorKind::Lex(_) => "R0001",
+            ReadErrorKind::UnexpectedClose => "R0002",
+            ReadErrorKind::MismatchedDelimiter { .. } => "R0003",
+            ReadErrorKind::UnterminatedList => "R0004",
+            ReadErrorKind::UnterminatedString => "R0005",
+            ReadErrorKind::UnexpectedDot => "R0006",
+            ReadErrorKind::MissingDatum => "R0007",
+            ReadErrorKind::UnexpectedToken => "R0008",
+            ReadErrorKind::BadNumber => "R0009",
+            ReadErrorKind::InvalidEscape => "R0010",
+            ReadErrorKind::InvalidChar => "R0011",
+            ReadErrorKind::UnknownCharName { .. } => "R0012",
+            ReadErrorKind::InvalidByte => "R0013",
+            ReadErrorKind::TooDeep => "R0014",
+            ReadErrorKind::UndefinedLabel => "R0015",
+            ReadErrorKind::DuplicateLabel => "R0016",
+            ReadErrorKind::CyclicLabel => "R0017",
+            ReadErrorKind::TooManyLabels => "R0018",
+            ReadErrorKind::MissingMapValue => "R0019",
+            ReadErrorKind::DuplicateSetElement => "R0020",
+            ReadErrorKind::LimitExceeded(_) => "R0021",
+        }
+    }
+}
+
+impl From<ScanErrorKind> for ReadErrorKind {
+    fn from(err: ScanErrorKind) -> Self {
+        match err {
+            ScanErrorKind::BadRadixDigit | ScanErrorKind::MissingDigits => ReadErrorKind::BadNumber,
+            ScanErrorKind::InvalidEscape => ReadErrorKind::InvalidEscape,
+            ScanErrorKind::InvalidChar => ReadErrorKind::InvalidChar,
+            ScanErrorKind::UnterminatedString => ReadErrorKind::UnterminatedString,
+            err => ReadErrorKind::Lex(err),
+        }
+    }
+}
+
+impl fmt::Display for ReadErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())?;
+
+        if let ReadErrorKind::UnknownCharName {
+            suggestion: Some(name),
+        } = self
+        {
+            write!(f, ", did you mean `#\\{}`?", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Why reading failed and the span it failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadError {
+    pub kind: ReadErrorKind,
+    pub span: Span,
+    /// The file being read, if the reader was given one.
+    pub file: Option<FileId>,
+}
+
+impl ReadError {
+    pub fn new(kind: ReadErrorKind, span: Span) -> Self {
+        ReadError {
+            kind,
+            span,
+            file: None,
+        }
+    }
+
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn file_span(&self) -> Option<FileSpan> {
+        self.file.map(|file| FileSpan::new(file, self.span))
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.kind, self.span.start, self.span.end)?;
+
+        if let ReadErrorKind::MismatchedDelimiter { open_span, .. } = self.kind {
+            write!(f, ", opened at {}..{}", open_span.start, open_span.end)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReadError {}
@@ -1,4 +1,14 @@
-pub mod scanner;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod diagnostics;
 pub mod error;
+pub mod number;
 pub mod reader;
-pub mod stx;
\ No newline at end of file
+pub mod scanner;
+pub mod source;
+pub mod span;
+pub mod stx;
+pub mod symbol;
+pub mod tokens;
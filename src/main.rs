@@ -1,7 +1,59 @@
-use lang::scanner::Scanner;
+use std::{env, fs, process};
+
+use lang::source::SourceManager;
+
+const USAGE: &str = "usage: lang parse [--dump] <file>";
 
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("parse") => parse(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// Reads every datum in a file and reports the errors, printing the trees
+/// read as well with `--dump`.
+fn parse(args: &[String]) {
+    let dump = args.iter().any(|arg| arg == "--dump");
+    let path = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    };
+
+    let mut sources = SourceManager::new();
+    let file = sources.add(path.as_str(), text);
+    let text = sources.get(file).map(|source| source.text()).unwrap_or_default();
+    let (forms, errors) = match sources.reader(file) {
+        Some(mut reader) => reader.read_all_with_recovery(),
+        None => return,
+    };
+
+    if dump {
+        for stx in &forms {
+            print!("{}", stx.dump(text));
+        }
+    }
 
-    let mut _scanner = Scanner::new("Hello world");
+    for err in &errors {
+        eprintln!("{}", sources.error_message(err));
+    }
+    if !errors.is_empty() {
+        process::exit(1);
+    }
 }
@@ -0,0 +1,500 @@
+//! The values of number literals.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+/// Hex float exponents beyond this are rejected rather than building huge
+/// exact values.
+const MAX_EXPONENT: u32 = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Int(i64),
+    /// An integer that doesn't fit in an `i64`.
+    BigInt(BigInt),
+    Float(f64),
+    /// A numerator and a positive denominator. Written rationals are kept
+    /// as written, exact decimals such as `#e1.5` are reduced.
+    Rational(BigInt, BigInt),
+}
+
+impl Number {
+    fn integer(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(value) => Number::Int(value),
+            None => Number::BigInt(value),
+        }
+    }
+
+    fn ratio(numer: BigInt, denom: BigInt) -> Self {
+        if denom.is_one() {
+            Number::integer(numer)
+        } else {
+            Number::Rational(numer, denom)
+        }
+    }
+}
+
+/// Writes the number as a literal that reads back as it. Floats always have
+/// a decimal point, since literals have no exponents.
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(value) => write!(f, "{}", value),
+            Number::BigInt(value) => write!(f, "{}", value),
+            Number::Rational(numer, denom) => write!(f, "{}/{}", numer, denom),
+            Number::Float(value) if value.is_nan() => f.write_str("+nan.0"),
+            Number::Float(value) if value.is_infinite() => {
+                f.write_str(if *value > 0.0 { "+inf.0" } else { "-inf.0" })
+            }
+            Number::Float(value) => {
+                write!(f, "{}", value)?;
+                if value.fract() == 0.0 {
+                    f.write_str(".0")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An arbitrary precision integer, supporting only what reading literals
+/// needs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    /// Little endian base 2^32 digits with no trailing zeros, so zero is
+    /// empty.
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// The magnitude as little endian base 2^32 digits.
+    pub fn limbs(&self) -> &[u32] {
+        &self.limbs
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        let magnitude = match self.limbs[..] {
+            [] => 0,
+            [low] => u64::from(low),
+            [low, high] => u64::from(high) << 32 | u64::from(low),
+            _ => return None,
+        };
+
+        if self.negative {
+            match magnitude {
+                m if m <= i64::MAX as u64 => Some(-(m as i64)),
+                m if m == 1 << 63 => Some(i64::MIN),
+                _ => None,
+            }
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    /// The nearest `f64`, or an infinity when the value is out of range.
+    pub fn to_f64(&self) -> f64 {
+        self.to_f64_scaled(0)
+    }
+
+    /// The value with its lowest `skip` limbs dropped.
+    fn to_f64_scaled(&self, skip: usize) -> f64 {
+        let magnitude = self
+            .limbs
+            .iter()
+            .skip(skip)
+            .rev()
+            .fold(0.0, |acc, &limb| acc * 4_294_967_296.0 + f64::from(limb));
+
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// Reads unsigned `digits` in `radix`, `None` if there are none or one
+    /// isn't valid.
+    fn from_digits(digits: &str, radix: u32) -> Option<BigInt> {
+        let mut value = BigInt::zero();
+
+        if digits.is_empty() || !value.push_digits(digits, radix) {
+            return None;
+        }
+
+        Some(value)
+    }
+
+    /// Appends `digits` to the end of the value, as if they had been written
+    /// after it.
+    fn push_digits(&mut self, digits: &str, radix: u32) -> bool {
+        for ch in digits.chars() {
+            match ch.to_digit(radix) {
+                Some(digit) => self.mul_add_small(radix, digit),
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    fn with_sign(mut self, negative: bool) -> Self {
+        self.negative = negative && !self.is_zero();
+        self
+    }
+
+    fn is_one(&self) -> bool {
+        !self.negative && self.limbs == [1]
+    }
+
+    fn mul_add_small(&mut self, mul: u32, add: u32) {
+        let mut carry = u64::from(add);
+
+        for limb in &mut self.limbs {
+            let product = u64::from(*limb) * u64::from(mul) + carry;
+            *limb = product as u32;
+            carry = product >> 32;
+        }
+
+        if carry != 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    /// Multiplies by `2^exp`.
+    fn mul_pow2(&mut self, mut exp: u32) {
+        while exp > 0 {
+            let step = exp.min(31);
+            self.mul_add_small(1 << step, 0);
+            exp -= step;
+        }
+    }
+
+    /// Divides in place and returns the remainder.
+    fn div_rem_small(&mut self, div: u32) -> u32 {
+        let mut rem = 0;
+
+        for limb in self.limbs.iter_mut().rev() {
+            let acc = u64::from(rem) << 32 | u64::from(*limb);
+            *limb = (acc / u64::from(div)) as u32;
+            rem = (acc % u64::from(div)) as u32;
+        }
+
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+
+        rem
+    }
+
+    fn rem_small(&self, div: u32) -> u32 {
+        self.limbs.iter().rev().fold(0, |rem, &limb| {
+            ((u64::from(rem) << 32 | u64::from(limb)) % u64::from(div)) as u32
+        })
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let magnitude = value.unsigned_abs();
+        let mut limbs = Vec::new();
+
+        if magnitude != 0 {
+            limbs.push(magnitude as u32);
+        }
+        if magnitude >> 32 != 0 {
+            limbs.push((magnitude >> 32) as u32);
+        }
+
+        BigInt {
+            negative: value < 0,
+            limbs,
+        }
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return f.write_str("0");
+        }
+
+        // nine decimal digits at a time, least significant first
+        let mut chunks = Vec::new();
+        let mut rest = self.clone();
+        while !rest.is_zero() {
+            chunks.push(rest.div_rem_small(1_000_000_000));
+        }
+
+        if self.negative {
+            f.write_str("-")?;
+        }
+
+        let mut chunks = chunks.iter().rev();
+        if let Some(first) = chunks.next() {
+            write!(f, "{}", first)?;
+        }
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The value of an `IntLit`, `FloatLit` or `RationalLit`, honoring its radix
+/// and exactness prefixes. `None` if the literal doesn't denote a number,
+/// such as `1/0` or `#e+inf.0`.
+pub fn parse(literal: &str) -> Option<Number> {
+    let mut radix = None;
+    let mut exact = None;
+    let mut body = literal;
+
+    while let Some(rest) = body.strip_prefix('#') {
+        match rest.bytes().next()?.to_ascii_lowercase() {
+            b'x' if radix.is_none() => radix = Some(16),
+            b'o' if radix.is_none() => radix = Some(8),
+            b'b' if radix.is_none() => radix = Some(2),
+            b'd' if radix.is_none() => radix = Some(10),
+            b'e' if exact.is_none() => exact = Some(true),
+            b'i' if exact.is_none() => exact = Some(false),
+            _ => return None,
+        }
+
+        body = &rest[1..];
+    }
+
+    let radix = radix.unwrap_or(10);
+    let (negative, unsigned) = match body.as_bytes().first() {
+        Some(b'-') => (true, &body[1..]),
+        Some(b'+') => (false, &body[1..]),
+        _ => (false, body),
+    };
+
+    let signed = unsigned.len() < body.len();
+    let special = match unsigned {
+        "inf.0" if signed => Some(f64::INFINITY),
+        "nan.0" if signed => Some(f64::NAN),
+        _ => None,
+    };
+
+    if let Some(value) = special {
+        return match exact {
+            Some(true) => None,
+            _ if negative => Some(Number::Float(-value)),
+            _ => Some(Number::Float(value)),
+        };
+    }
+
+    if let Some((numer, denom)) = unsigned.split_once('/') {
+        let numer = BigInt::from_digits(numer, radix)?.with_sign(negative);
+        let denom = BigInt::from_digits(denom, radix)?;
+
+        return match exact {
+            _ if denom.is_zero() => None,
+            Some(false) => Some(Number::Float(ratio_to_f64(&numer, &denom))),
+            _ => Some(Number::ratio(numer, denom)),
+        };
+    }
+
+    let is_float = unsigned.contains('.') || (radix == 16 && unsigned.contains(['p', 'P']));
+    if !is_float {
+        let value = BigInt::from_digits(unsigned, radix)?.with_sign(negative);
+
+        return match exact {
+            Some(false) => Some(Number::Float(value.to_f64())),
+            _ => Some(Number::integer(value)),
+        };
+    }
+
+    if radix == 10 && exact != Some(true) {
+        return body.parse().ok().map(Number::Float);
+    }
+
+    let (numer, denom) = float_ratio(unsigned, radix)?;
+    let numer = numer.with_sign(negative);
+
+    match exact {
+        Some(true) => Some(reduce(numer, denom, radix)),
+        _ => Some(Number::Float(ratio_to_f64(&numer, &denom))),
+    }
+}
+
+/// The exact value of an unsigned float written in `radix`, with a binary
+/// exponent for hex floats.
+fn float_ratio(unsigned: &str, radix: u32) -> Option<(BigInt, BigInt)> {
+    let (mantissa, exponent) = match unsigned.find(['p', 'P']) {
+        Some(p) if radix == 16 => {
+            let exponent: i64 = unsigned[p + 1..].parse().ok()?;
+            (&unsigned[..p], exponent)
+        }
+        _ => (unsigned, 0),
+    };
+
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    let mut numer = BigInt::zero();
+    if !numer.push_digits(whole, radix) || !numer.push_digits(fraction, radix) {
+        return None;
+    }
+
+    let mut denom = BigInt::from(1);
+    for _ in 0..fraction.len() {
+        denom.mul_add_small(radix, 0);
+    }
+
+    let shift = u32::try_from(exponent.unsigned_abs())
+        .ok()
+        .filter(|&shift| shift <= MAX_EXPONENT)?;
+    if exponent < 0 {
+        denom.mul_pow2(shift);
+    } else {
+        numer.mul_pow2(shift);
+    }
+
+    Some((numer, denom))
+}
+
+/// Reduces a ratio whose denominator is a power of `radix` times a power of
+/// two, so only the prime factors of those need dividing out.
+fn reduce(mut numer: BigInt, mut denom: BigInt, radix: u32) -> Number {
+    let primes: &[u32] = if radix == 10 { &[2, 5] } else { &[2] };
+
+    for &prime in primes {
+        while !denom.is_one() && denom.rem_small(prime) == 0 && numer.rem_small(prime) == 0 {
+            numer.div_rem_small(prime);
+            denom.div_rem_small(prime);
+        }
+    }
+
+    Number::ratio(numer, denom)
+}
+
+fn ratio_to_f64(numer: &BigInt, denom: &BigInt) -> f64 {
+    // drop low limbs of both so huge values don't overflow to infinity
+    let skip = numer.limbs.len().max(denom.limbs.len()).saturating_sub(30);
+    numer.to_f64_scaled(skip) / denom.to_f64_scaled(skip)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn big(digits: &str) -> BigInt {
+        match digits.strip_prefix('-') {
+            Some(digits) => BigInt::from_digits(digits, 10).unwrap().with_sign(true),
+            None => BigInt::from_digits(digits, 10).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_int() {
+        assert_eq!(parse("42"), Some(Number::Int(42)));
+        assert_eq!(parse("-17"), Some(Number::Int(-17)));
+        assert_eq!(parse("+5"), Some(Number::Int(5)));
+        assert_eq!(parse("#xff"), Some(Number::Int(255)));
+        assert_eq!(parse("#X-FF"), Some(Number::Int(-255)));
+        assert_eq!(parse("#b1010"), Some(Number::Int(10)));
+        assert_eq!(parse("#o755"), Some(Number::Int(493)));
+        assert_eq!(parse("9223372036854775807"), Some(Number::Int(i64::MAX)));
+        assert_eq!(parse("-9223372036854775808"), Some(Number::Int(i64::MIN)));
+    }
+
+    #[test]
+    fn test_bigint() {
+        assert_eq!(
+            parse("9223372036854775808"),
+            Some(Number::BigInt(big("9223372036854775808")))
+        );
+
+        let digits = "-123456789012345678901234567890123456789";
+        let value = big(digits);
+        assert_eq!(parse(digits), Some(Number::BigInt(value.clone())));
+        assert_eq!(value.to_string(), digits);
+        assert!(value.is_negative());
+        assert!((value.to_f64() + 1.2345678901234568e38).abs() < 1e23);
+
+        assert_eq!(
+            parse("#x10000000000000000").unwrap(),
+            Number::BigInt(BigInt::from_digits("18446744073709551616", 10).unwrap())
+        );
+        assert_eq!(BigInt::from(-5).to_string(), "-5");
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::zero().to_string(), "0");
+        assert_eq!(
+            big("1000000000000000000007").to_string(),
+            "1000000000000000000007"
+        );
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(parse("1.5"), Some(Number::Float(1.5)));
+        assert_eq!(parse("-.5"), Some(Number::Float(-0.5)));
+        assert_eq!(parse("1."), Some(Number::Float(1.0)));
+        assert_eq!(parse("#d2.25"), Some(Number::Float(2.25)));
+        assert_eq!(parse("#x1.8"), Some(Number::Float(1.5)));
+        assert_eq!(parse("#x1.8p3"), Some(Number::Float(12.0)));
+        assert_eq!(parse("#x1p-2"), Some(Number::Float(0.25)));
+        assert_eq!(parse("+inf.0"), Some(Number::Float(f64::INFINITY)));
+        assert_eq!(parse("-inf.0"), Some(Number::Float(f64::NEG_INFINITY)));
+        assert!(matches!(parse("+nan.0"), Some(Number::Float(value)) if value.is_nan()));
+    }
+
+    #[test]
+    fn test_exactness() {
+        assert_eq!(parse("#e1.5"), Some(Number::Rational(big("3"), big("2"))));
+        assert_eq!(
+            parse("#e-2.50"),
+            Some(Number::Rational(big("-5"), big("2")))
+        );
+        assert_eq!(parse("#e1.0"), Some(Number::Int(1)));
+        assert_eq!(parse("#e#x1.8p3"), Some(Number::Int(12)));
+        assert_eq!(
+            parse("#x#e1p-1"),
+            Some(Number::Rational(big("1"), big("2")))
+        );
+        assert_eq!(parse("#i5"), Some(Number::Float(5.0)));
+        assert_eq!(parse("#i1/4"), Some(Number::Float(0.25)));
+        assert_eq!(parse("#e+inf.0"), None);
+        assert_eq!(parse("#e#e1"), None);
+        assert_eq!(parse("#x#x1"), None);
+    }
+
+    #[test]
+    fn test_rational() {
+        assert_eq!(parse("1/3"), Some(Number::Rational(big("1"), big("3"))));
+        assert_eq!(parse("-6/4"), Some(Number::Rational(big("-6"), big("4"))));
+        assert_eq!(parse("#x10/2"), Some(Number::Rational(big("16"), big("2"))));
+        assert_eq!(parse("4/1"), Some(Number::Int(4)));
+        assert_eq!(parse("1/0"), None);
+        assert_eq!(parse("1/"), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("-"), None);
+        assert_eq!(parse("#b2"), None);
+        assert_eq!(parse("#x1p99999999999"), None);
+        assert_eq!(parse("#q1"), None);
+    }
+}
@@ -1,25 +1,2704 @@
-use std::num::NonZeroU32;
+pub mod cst;
+pub mod events;
+pub mod sink;
+#[cfg(feature = "std")]
+pub mod stream;
+pub mod table;
 
-use crate::stx::Stx;
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{OnceCell, RefCell};
+use core::convert::TryFrom;
+use core::hash::Hasher;
+use core::mem;
+use core::str;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use crate::diagnostics::Diagnostics;
+use crate::number::{self, Number};
+use crate::scanner::{
+    fold_identifier, hex_char, Lexeme, LexemeKind, ScanErrorKind, Scanner, ScannerMode,
+    ScannerOptions, CHAR_NAMES,
+};
+use crate::source::{FileId, LineIndex};
+use crate::span::{FileSpan, Span};
+use crate::stx::{Datum, Doc, Fnv, Keyword, LabelRef, Stx, StxKind};
+use crate::symbol::SymbolTable;
+
+pub use self::table::{ReaderMacro, ReaderTable};
+pub use crate::error::{Limit, ReadError, ReadErrorKind};
+
+/// How deeply lists may nest before reading fails with
+/// `ReadErrorKind::TooDeep`, unless changed with `Reader::with_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// How many datum labels one datum may define before reading fails with
+/// `ReadErrorKind::TooManyLabels`, unless changed with
+/// `Reader::with_max_labels`.
+pub const DEFAULT_MAX_LABELS: usize = 1024;
+
+/// What `Reader::read_interactive` found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadOutcome<'a> {
+    Datum(Stx<'a>),
+    /// Only trivia was left.
+    Empty,
+    /// The input ended inside a datum, so whether it reads depends on what
+    /// comes next.
+    NeedMoreInput {
+        /// The open delimiters of the unclosed lists, outermost first.
+        open_delims: Vec<LexemeKind>,
+        in_string: bool,
+        /// The mode the scanner was in where the input ended.
+        mode: ScannerMode,
+    },
+}
+
+/// The syntax a `Reader` accepts beyond the standard, and the limits that
+/// bound the memory and time it spends on untrusted input, see
+/// `Reader::with_options`. No limit is set by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReaderOptions {
+    maps: bool,
+    sets: bool,
+    max_depth: Option<usize>,
+    max_datums: Option<usize>,
+    max_total_atoms: Option<usize>,
+    max_string_len: Option<usize>,
+}
+
+impl ReaderOptions {
+    pub fn new() -> Self {
+        ReaderOptions::default()
+    }
+
+    /// Reads `{k v ...}` as a map of keys to values instead of a list.
+    pub fn maps(mut self, enabled: bool) -> Self {
+        self.maps = enabled;
+        self
+    }
+
+    /// Reads `#{x ...}` as a set. Otherwise it is an `UnexpectedToken`.
+    pub fn sets(mut self, enabled: bool) -> Self {
+        self.sets = enabled;
+        self
+    }
+
+    /// The same as `Reader::with_max_depth`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// How many datums the reader reads in all, at any depth, counting
+    /// lists, vectors, maps and sets as well as their items.
+    pub fn max_datums(mut self, max: usize) -> Self {
+        self.max_datums = Some(max);
+        self
+    }
+
+    /// How many atoms the reader reads in all, counting every datum that
+    /// isn't a list, vector, map or set, commented out or not.
+    pub fn max_total_atoms(mut self, max: usize) -> Self {
+        self.max_total_atoms = Some(max);
+        self
+    }
+
+    /// How many bytes a string may hold once its escapes are decoded.
+    pub fn max_string_len(mut self, max: usize) -> Self {
+        self.max_string_len = Some(max);
+        self
+    }
+}
+
+/// Where a `Reader` stopped between two top-level datums, with how it was
+/// set up and the symbols it read, to carry on from with `Reader::resume`.
+/// Its `ReaderTable` is borrowed rather than kept, so it has to be given to
+/// the resumed reader again.
+#[derive(Debug, Clone)]
+pub struct ReaderState {
+    offset: u32,
+    fold_case: bool,
+    file: Option<FileId>,
+    options: ReaderOptions,
+    scanner_options: ScannerOptions,
+    max_depth: usize,
+    max_labels: usize,
+    strict_labels: bool,
+    doc_comments: bool,
+    symbols: SymbolTable,
+    datums: usize,
+    atoms: usize,
+}
+
+impl ReaderState {
+    /// Byte offset of the input not read yet.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Reader<'a> {
-    line: NonZeroU32,
-    column: u32,
-    src_bytes: &'a[u8],
+    file: Option<FileId>,
+    options: ReaderOptions,
+    table: Option<&'a ReaderTable>,
+    /// Built the first time it is needed, since most readers never are.
+    line_index: OnceCell<LineIndex>,
+    src_bytes: &'a [u8],
+    scanner: Scanner<'a>,
+    /// The lists being read, innermost last.
+    delimiters: Vec<(LexemeKind, Span)>,
+    max_depth: usize,
+    /// Quote prefixes and datum labels enclosing the datum being read,
+    /// which nest like lists.
+    prefixes: usize,
+    /// The datum labels defined so far in the datum being read.
+    labels: Vec<Label<'a>>,
+    max_labels: usize,
+    strict_labels: bool,
+    /// The names of the symbols and keywords read so far.
+    symbols: SymbolTable,
+    doc_comments: bool,
+    /// The doc comments since the last datum, and how many line breaks
+    /// followed them.
+    doc: Option<Doc<'a>>,
+    doc_newlines: u32,
+    recovering: bool,
+    errors: Vec<ReadError>,
+    /// What has been read so far, for the limits in `options`.
+    datums: usize,
+    atoms: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Label<'a> {
+    label: u32,
+    datum: Rc<RefCell<Stx<'a>>>,
+    /// Whether the labelled datum is still being read, so a reference to it
+    /// makes a cycle.
+    reading: bool,
 }
 
 impl<'a> Reader<'a> {
-    pub fn from_slice(src_bytes: &'a[u8]) -> Self {
+    pub fn new(src: &'a str) -> Self {
         Reader {
-            line: unsafe {NonZeroU32::new_unchecked(1)},
-            column: 0,
-            src_bytes
+            file: None,
+            options: ReaderOptions::new(),
+            table: None,
+            line_index: OnceCell::new(),
+            src_bytes: src.as_bytes(),
+            scanner: Scanner::new(src),
+            delimiters: Vec::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            prefixes: 0,
+            labels: Vec::new(),
+            max_labels: DEFAULT_MAX_LABELS,
+            strict_labels: false,
+            symbols: SymbolTable::new(),
+            doc_comments: false,
+            doc: None,
+            doc_newlines: 0,
+            recovering: false,
+            errors: Vec::new(),
+            datums: 0,
+            atoms: 0,
+        }
+    }
+
+    pub fn from_slice(src_bytes: &'a [u8]) -> Self {
+        Reader {
+            file: None,
+            options: ReaderOptions::new(),
+            table: None,
+            line_index: OnceCell::new(),
+            src_bytes,
+            scanner: Scanner::from_bytes(src_bytes),
+            delimiters: Vec::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            prefixes: 0,
+            labels: Vec::new(),
+            max_labels: DEFAULT_MAX_LABELS,
+            strict_labels: false,
+            symbols: SymbolTable::new(),
+            doc_comments: false,
+            doc: None,
+            doc_newlines: 0,
+            recovering: false,
+            errors: Vec::new(),
+            datums: 0,
+            atoms: 0,
+        }
+    }
+
+    /// Reads `src` from byte `offset` on, with spans still counted from the
+    /// start of `src`. The offset should be where a top-level datum ended.
+    pub fn at_offset(src: &'a str, offset: u32) -> Self {
+        let mut reader = Reader::new(src);
+        reader.scanner = Scanner::new(src).starting_at(offset as usize);
+        reader
+    }
+
+    /// Carries on where the reader `state` was taken from stopped, set up
+    /// the same way. `src` may be the same input or a longer one that
+    /// starts with it, as when a REPL appends each line to its history.
+    pub fn resume(src: &'a str, state: ReaderState) -> Self {
+        let options = state.scanner_options.fold_case(state.fold_case);
+        let mut reader = Reader::new(src);
+        reader.scanner = Scanner::new(src)
+            .with_options(options)
+            .starting_at(state.offset as usize);
+        reader.file = state.file;
+        reader.options = state.options;
+        reader.max_depth = state.max_depth;
+        reader.max_labels = state.max_labels;
+        reader.strict_labels = state.strict_labels;
+        reader.doc_comments = state.doc_comments;
+        reader.symbols = state.symbols;
+        reader.datums = state.datums;
+        reader.atoms = state.atoms;
+        reader
+    }
+
+    /// Where reading stopped, for `Reader::resume`. Only meaningful between
+    /// top-level datums.
+    pub fn state(&self) -> ReaderState {
+        ReaderState {
+            offset: self.scanner.position() as u32,
+            fold_case: self.scanner.fold_case(),
+            file: self.file,
+            options: self.options,
+            scanner_options: self.scanner.options(),
+            max_depth: self.max_depth,
+            max_labels: self.max_labels,
+            strict_labels: self.strict_labels,
+            doc_comments: self.doc_comments,
+            symbols: self.symbols.clone(),
+            datums: self.datums,
+            atoms: self.atoms,
+        }
+    }
+
+    /// Reads datums from `inner` as its input arrives, see `StreamReader`.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: std::io::Read>(inner: R) -> stream::StreamReader<'a, R> {
+        stream::StreamReader::new(inner)
+    }
+
+    /// Marks the spans of errors with `file`, so errors from different files
+    /// can be told apart.
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn file(&self) -> Option<FileId> {
+        self.file
+    }
+
+    /// `span` in the file being read, if the reader was given one.
+    pub fn file_span(&self, span: Span) -> Option<FileSpan> {
+        self.file.map(|file| FileSpan::new(file, span))
+    }
+
+    pub fn with_options(mut self, options: ReaderOptions) -> Self {
+        self.options = options;
+        if let Some(max_depth) = options.max_depth {
+            self.max_depth = max_depth;
+        }
+        self
+    }
+
+    /// Scans the input with `options`, for the lexical syntax that is off by
+    /// default such as `:name` keywords. Takes effect only before reading.
+    /// Folding stays on if a `#!fold-case` before a resumed reader's offset
+    /// turned it on.
+    pub fn with_scanner_options(mut self, options: ScannerOptions) -> Self {
+        let fold_case = self.scanner.fold_case();
+        self.scanner = self.scanner.with_options(options);
+        if fold_case && !self.scanner.fold_case() {
+            self.scanner = self.scanner.with_options(options.fold_case(true));
+        }
+        self
+    }
+
+    /// Reads with the reader macros in `table` as well as the built-in
+    /// syntax.
+    pub fn with_table(mut self, table: &'a ReaderTable) -> Self {
+        self.scanner = self.scanner.with_dispatch(table.dispatch());
+        self.table = Some(table);
+        self
+    }
+
+    /// Keeps the `;;;` comments right above a datum as its `Stx::doc`. A
+    /// blank line or another comment in between separates them from it.
+    pub fn with_doc_comments(mut self, enabled: bool) -> Self {
+        self.doc_comments = enabled;
+        self
+    }
+
+    /// Limits how deeply lists may nest. The reader recurses once per level,
+    /// so this bounds its stack use on untrusted input.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Limits how many datum labels one datum may define, which bounds the
+    /// memory they take.
+    pub fn with_max_labels(mut self, max_labels: usize) -> Self {
+        self.max_labels = max_labels;
+        self
+    }
+
+    /// Rejects datum labels that make cyclic structure with
+    /// `ReadErrorKind::CyclicLabel`, instead of reading them into a cyclic
+    /// graph. Shared structure is still allowed.
+    pub fn with_strict_labels(mut self, strict: bool) -> Self {
+        self.strict_labels = strict;
+        self
+    }
+
+    /// Interns symbols and keywords in `symbols`, which may already hold
+    /// the names another reader read.
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// The names of the symbols and keywords read so far.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn into_symbols(self) -> SymbolTable {
+        self.symbols
+    }
+
+    /// The lines of the input, indexed the first time they are asked for.
+    pub fn line_index(&self) -> &LineIndex {
+        self.line_index
+            .get_or_init(|| LineIndex::from_bytes(self.src_bytes))
+    }
+
+    /// Reads the next datum, or returns `None` once only trivia is left.
+    pub fn read_one(&mut self) -> Result<Option<Stx<'a>>, ReadError> {
+        self.delimiters.clear();
+        self.prefixes = 0;
+        self.labels.clear();
+
+        let result = match self.peek_token() {
+            Ok(Some(_)) => self.read_form().map(Some),
+            Ok(None) => Ok(None),
+            Err(err) => Err(err),
+        };
+
+        result.map_err(|err| self.in_file(err))
+    }
+
+    /// Reads the next datum with its spans and delimiters, as macro expansion
+    /// and tooling need. The same as `read_one`.
+    pub fn read_syntax(&mut self) -> Result<Option<Stx<'a>>, ReadError> {
+        self.read_one()
+    }
+
+    /// Reads the next datum as a plain value, which takes less memory to
+    /// hold on to when only the data matters.
+    pub fn read_datum(&mut self) -> Result<Option<Datum<'a>>, ReadError> {
+        let stx = self.read_one()?;
+        // so the labelled datums can be moved out instead of copied
+        self.labels.clear();
+        match stx {
+            Some(stx) => match Strip::default().datum(stx) {
+                Ok(datum) => Ok(Some(datum)),
+                Err(err) => Err(self.in_file(err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the next datum as part of the one being read, for reader macros
+    /// that take the datums following them.
+    pub fn read_subform(&mut self) -> Result<Stx<'a>, ReadError> {
+        self.read_form()
+    }
+
+    /// Describes `err` for a person, with line numbers instead of offsets.
+    pub fn error_message(&self, err: &ReadError) -> String {
+        match err.kind {
+            ReadErrorKind::MismatchedDelimiter { open, open_span } => {
+                let line = self.line_index().line_col(open_span.start).line;
+                let (close, name) = match open {
+                    LexemeKind::LBracket => (']', "bracket"),
+                    LexemeKind::LBrace => ('}', "brace"),
+                    LexemeKind::VectorOpen => (')', "vector"),
+                    LexemeKind::SetOpen => ('}', "set"),
+                    LexemeKind::BytevectorOpen => (')', "bytevector"),
+                    _ => (')', "paren"),
+                };
+
+                format!(
+                    "expected `{}` to close {} opened at line {}",
+                    close, name, line
+                )
+            }
+            kind => {
+                let line = self.line_index().line_col(err.span.start).line;
+                format!("{} at line {}", kind, line)
+            }
+        }
+    }
+
+    /// Reads the next datum like `read_one`, but tells input that ends in
+    /// the middle of a datum apart from input that is wrong. A REPL can
+    /// prompt for another line on `NeedMoreInput` and read the whole entry
+    /// again with a new reader.
+    pub fn read_interactive(&mut self) -> Result<ReadOutcome<'a>, ReadError> {
+        let err = match self.read_one() {
+            Ok(Some(stx)) => return Ok(ReadOutcome::Datum(stx)),
+            Ok(None) => return Ok(ReadOutcome::Empty),
+            Err(err) => err,
+        };
+
+        if self.scanner.position() < self.src_bytes.len() {
+            return Err(err);
+        }
+
+        let mode = match err.kind {
+            ReadErrorKind::UnterminatedString => ScannerMode::String,
+            ReadErrorKind::Lex(ScanErrorKind::UnterminatedHeredoc) => ScannerMode::Heredoc,
+            ReadErrorKind::UnterminatedList
+            | ReadErrorKind::MissingDatum
+            | ReadErrorKind::Lex(ScanErrorKind::UnterminatedBlockComment)
+            | ReadErrorKind::Lex(ScanErrorKind::UnterminatedIdentifier) => ScannerMode::Regular,
+            _ => return Err(err),
+        };
+
+        Ok(ReadOutcome::NeedMoreInput {
+            open_delims: self.delimiters.iter().map(|&(open, _)| open).collect(),
+            in_string: mode != ScannerMode::Regular,
+            mode,
+        })
+    }
+
+    /// Reads every remaining datum, stopping at the first error.
+    pub fn read_all(&mut self) -> Result<Vec<Stx<'a>>, ReadError> {
+        self.collect()
+    }
+
+    /// Reads every remaining datum without stopping at errors. Each error is
+    /// returned and leaves an `StxKind::Error` node where the input failed to
+    /// read; unclosed lists are closed at the end of the input or at a close
+    /// delimiter matching an enclosing list, and unclosed strings at the end
+    /// of the input. Both are marked `Stx::incomplete`.
+    pub fn read_all_with_recovery(&mut self) -> (Vec<Stx<'a>>, Vec<ReadError>) {
+        let mut forms = Vec::new();
+        self.recovering = true;
+
+        loop {
+            match self.read_one() {
+                Ok(Some(stx)) => forms.push(stx),
+                Ok(None) => break,
+                Err(err) => {
+                    self.errors.push(err);
+                    forms.push(Stx::new(StxKind::Error, err.span));
+                }
+            }
+        }
+
+        self.recovering = false;
+        (forms, mem::take(&mut self.errors))
+    }
+
+    /// Reads every remaining datum like `read_all_with_recovery`, reporting
+    /// each error to `diagnostics`.
+    pub fn read_all_with_diagnostics(&mut self, diagnostics: &mut Diagnostics) -> Vec<Stx<'a>> {
+        let (forms, errors) = self.read_all_with_recovery();
+        diagnostics.extend(errors.into_iter().map(Into::into));
+        forms
+    }
+
+    /// The next lexeme that isn't trivia, with datum comments and the
+    /// datums they comment out skipped as well.
+    fn peek_token(&mut self) -> Result<Option<Lexeme<'a>>, ReadError> {
+        // `#;` still waiting for its datum, innermost last. Counted here
+        // instead of recursing so long runs of them can't exhaust the stack.
+        let mut comments = Vec::new();
+
+        while let Some(lex) = self.scanner.peek() {
+            match lex.kind {
+                kind if kind.is_trivia() => {
+                    if self.doc_comments {
+                        self.track_doc(lex);
+                    }
+                }
+                LexemeKind::FoldCaseDirective | LexemeKind::NoFoldCaseDirective => {}
+                LexemeKind::DatumComment => comments.push(lex.span),
+                kind if kind.is_close_delimiter() && !comments.is_empty() => break,
+                _ => match comments.pop() {
+                    Some(_) => {
+                        // labels defined in a commented out datum aren't
+                        // defined after it
+                        let labels = self.labels.len();
+                        self.read_form()?;
+                        self.labels.truncate(labels);
+                        continue;
+                    }
+                    None => {
+                        // doc comments at the end of a list document nothing
+                        if lex.kind.is_close_delimiter() {
+                            self.doc = None;
+                        }
+                        return Ok(Some(lex));
+                    }
+                },
+            }
+
+            self.scanner.next();
+        }
+
+        match comments.pop() {
+            Some(span) => Err(ReadError::new(ReadErrorKind::MissingDatum, span)),
+            None => Ok(None),
+        }
+    }
+
+    fn track_doc(&mut self, lex: Lexeme<'a>) {
+        match lex.kind {
+            LexemeKind::DocComment => match &mut self.doc {
+                Some(doc) if self.doc_newlines <= 1 => {
+                    doc.lines.push(lex.slice);
+                    doc.span.end = lex.span.end;
+                }
+                doc => {
+                    *doc = Some(Doc {
+                        lines: vec![lex.slice],
+                        span: lex.span,
+                    })
+                }
+            },
+            LexemeKind::NewlineLf | LexemeKind::NewlineCr | LexemeKind::NewlineCrlf => {
+                self.doc_newlines += 1;
+                return;
+            }
+            LexemeKind::Whitespace | LexemeKind::Tab => return,
+            _ => self.doc = None,
+        }
+
+        self.doc_newlines = 0;
+    }
+
+    fn read_form(&mut self) -> Result<Stx<'a>, ReadError> {
+        let lex = match self.peek_token()? {
+            Some(lex) => lex,
+            None => return Err(ReadError::new(ReadErrorKind::MissingDatum, self.eof_span())),
+        };
+        self.scanner.next();
+        self.count_datum(lex.span)?;
+
+        let doc = self.doc.take().filter(|_| self.doc_newlines <= 1);
+        let mut stx = self.read_token(lex)?;
+        stx.doc = doc.map(Box::new);
+        Ok(stx)
+    }
+
+    fn read_token(&mut self, lex: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        if let Some(read) = self.table.and_then(|table| table.get(lex)) {
+            return read(self, lex);
+        }
+
+        if quote_name(lex.kind).is_some() {
+            return self.read_quoted(lex);
+        }
+
+        let kind = match lex.kind {
+            LexemeKind::LBrace if self.options.maps => return self.read_map(lex),
+            LexemeKind::LParen
+            | LexemeKind::LBracket
+            | LexemeKind::LBrace
+            | LexemeKind::VectorOpen => return self.read_list(lex),
+            LexemeKind::BytevectorOpen | LexemeKind::LString | LexemeKind::HeredocStart => {
+                return self.read_sized(lex)
+            }
+            LexemeKind::SetOpen => return self.read_set(lex),
+            LexemeKind::DatumLabel => return self.read_labeled(lex),
+            LexemeKind::DatumRef => return self.read_label_ref(lex),
+            LexemeKind::BoolLit => StxKind::Bool(lex.slice == "#t" || lex.slice == "#true"),
+            LexemeKind::IntLit | LexemeKind::FloatLit | LexemeKind::RationalLit => {
+                match number::parse(lex.slice) {
+                    Some(value) => StxKind::Number(value),
+                    None => return Err(ReadError::new(ReadErrorKind::BadNumber, lex.span)),
+                }
+            }
+            LexemeKind::CharLit => match char_value(&lex.slice[2..]) {
+                Some(ch) => StxKind::Char(ch),
+                None => return Err(ReadError::new(ReadErrorKind::InvalidChar, lex.span)),
+            },
+            LexemeKind::Error(ScanErrorKind::InvalidChar) => {
+                return Err(ReadError::new(char_error(&lex.slice[2..]), lex.span))
+            }
+            LexemeKind::Identifier | LexemeKind::QuotedIdentifier => return self.read_symbol(lex),
+            LexemeKind::KeywordLit => return self.read_keyword(lex),
+            LexemeKind::Error(err) => return Err(ReadError::new(err.into(), lex.span)),
+            LexemeKind::Dot => return Err(ReadError::new(ReadErrorKind::UnexpectedDot, lex.span)),
+            kind if kind.is_close_delimiter() => return Err(self.close_error(lex)),
+            _ => return Err(ReadError::new(ReadErrorKind::UnexpectedToken, lex.span)),
+        };
+
+        self.atom(Stx::new(kind, lex.span))
+    }
+
+    /// Counts the datum starting at `span` against
+    /// `ReaderOptions::max_datums`.
+    fn count_datum(&mut self, span: Span) -> Result<(), ReadError> {
+        self.datums += 1;
+
+        match self.options.max_datums {
+            Some(max) if self.datums > max => Err(self.exceeded(Limit::Datums, span)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts `stx` against `ReaderOptions::max_total_atoms`.
+    fn atom(&mut self, stx: Stx<'a>) -> Result<Stx<'a>, ReadError> {
+        self.atoms += 1;
+
+        match self.options.max_total_atoms {
+            Some(max) if self.atoms > max => Err(self.exceeded(Limit::TotalAtoms, stx.span)),
+            _ => Ok(stx),
+        }
+    }
+
+    /// Reads a string or bytevector, then checks it against
+    /// `ReaderOptions::max_string_len` and counts it as an atom.
+    fn read_sized(&mut self, open: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let stx = match open.kind {
+            LexemeKind::BytevectorOpen => self.read_bytevector(open)?,
+            LexemeKind::LString => self.read_string(open)?,
+            _ => self.read_heredoc(open)?,
+        };
+
+        if let (StxKind::String(value), Some(max)) = (&stx.kind, self.options.max_string_len) {
+            if value.len() > max {
+                return Err(self.exceeded(Limit::StringLen, stx.span));
+            }
+        }
+
+        self.atom(stx)
+    }
+
+    /// The error for input past `limit`. The rest of the input is skipped so
+    /// reading ends there.
+    fn exceeded(&mut self, limit: Limit, span: Span) -> ReadError {
+        while self.scanner.next().is_some() {}
+        ReadError::new(ReadErrorKind::LimitExceeded(limit), span)
+    }
+
+    /// Reads an identifier, folded if fold-case is on, and interns its name.
+    fn read_symbol(&mut self, lex: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let name = match lex.kind {
+            // cut short in front of invalid UTF-8 when it lacks the closing bar
+            LexemeKind::QuotedIdentifier => match lex.slice.strip_prefix('|') {
+                Some(name) if name.ends_with('|') => match unescape_bars(&name[..name.len() - 1]) {
+                    Some(name) => name,
+                    None => return Err(ReadError::new(ReadErrorKind::InvalidEscape, lex.span)),
+                },
+                _ => {
+                    let kind = ReadErrorKind::Lex(ScanErrorKind::InvalidBytes);
+                    return Err(ReadError::new(kind, lex.span));
+                }
+            },
+            _ if self.scanner.fold_case() => fold_identifier(lex.slice),
+            _ => Cow::Borrowed(lex.slice),
+        };
+
+        let symbol = self.symbols.intern(&name);
+        let mut stx = Stx::new(StxKind::Symbol(name), lex.span);
+        stx.symbol = Some(symbol);
+        self.atom(stx)
+    }
+
+    /// Reads a keyword, folded if fold-case is on, and interns its name.
+    fn read_keyword(&mut self, lex: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let slice = lex.slice;
+        let name = match slice.strip_prefix("#:").or_else(|| slice.strip_prefix(':')) {
+            Some(name) => name,
+            None => slice.strip_suffix(':').unwrap_or(slice),
+        };
+        let name = match self.scanner.fold_case() {
+            true => fold_identifier(name),
+            false => Cow::Borrowed(name),
+        };
+
+        let symbol = self.symbols.intern(&name);
+        let keyword = Keyword(self.symbols.shared_name(symbol));
+        let mut stx = Stx::new(StxKind::Keyword(keyword), lex.span);
+        stx.symbol = Some(symbol);
+        self.atom(stx)
+    }
+
+    /// The error for a close delimiter that doesn't match the list being
+    /// read, if there is one.
+    fn close_error(&self, close: Lexeme<'a>) -> ReadError {
+        let kind = match self.delimiters.last() {
+            Some(&(open, open_span)) => ReadErrorKind::MismatchedDelimiter { open, open_span },
+            None => ReadErrorKind::UnexpectedClose,
+        };
+
+        ReadError::new(kind, close.span)
+    }
+
+    fn read_list(&mut self, open: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        if self.depth() >= self.max_depth {
+            let end = self.skip_list();
+            return Err(ReadError::new(
+                ReadErrorKind::TooDeep,
+                Span::new(open.span.start, end),
+            ));
+        }
+
+        let close = open.kind.matching_close();
+        let mut items = Vec::new();
+        // the dot and the datum after it
+        let mut tail: Option<(Span, Box<Stx<'a>>)> = None;
+        self.delimiters.push((open.kind, open.span));
+
+        // built in one place so the frame of this recursive call stays small
+        let close_span = loop {
+            let lex = match self.peek_token() {
+                Ok(Some(lex)) => lex,
+                Ok(None) => {
+                    self.record(ReadError::new(ReadErrorKind::UnterminatedList, open.span))?;
+                    break self.eof_span();
+                }
+                Err(err) => {
+                    self.record(err)?;
+                    continue;
+                }
+            };
+
+            if Some(lex.kind) == close {
+                self.scanner.next();
+                self.delimiters.pop();
+                break lex.span;
+            }
+
+            // leave a close meant for an enclosing list to that list
+            if lex.kind.is_close_delimiter() && self.recovering && self.closes_outer(lex.kind) {
+                self.record(self.close_error(lex))?;
+                self.delimiters.pop();
+                break Span::new(lex.span.start, lex.span.start);
+            }
+
+            if !lex.kind.is_close_delimiter() {
+                if let Some((dot, stx)) = tail.take() {
+                    self.record(ReadError::new(ReadErrorKind::UnexpectedDot, dot))?;
+                    items.push(*stx);
+                }
+            }
+
+            if lex.kind == LexemeKind::Dot && self.allows_dot(open.kind) && !items.is_empty() {
+                self.scanner.next();
+                tail = self
+                    .read_tail(lex.span)?
+                    .map(|stx| (lex.span, Box::new(stx)));
+                continue;
+            }
+
+            items.push(self.read_or_recover()?);
+        };
+
+        Ok(list(open, items, tail, close_span))
+    }
+
+    /// Reads the datum after the dot at `dot`, if there is one.
+    fn read_tail(&mut self, dot: Span) -> Result<Option<Stx<'a>>, ReadError> {
+        match self.peek_token()? {
+            Some(next) if !next.kind.is_close_delimiter() => self.read_or_recover().map(Some),
+            _ => {
+                self.record(ReadError::new(ReadErrorKind::MissingDatum, dot))?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether lists opened by `open` may be dotted.
+    fn allows_dot(&self, open: LexemeKind) -> bool {
+        match open {
+            LexemeKind::LParen | LexemeKind::LBracket => true,
+            LexemeKind::LBrace => !self.options.maps,
+            _ => false,
+        }
+    }
+
+    /// Reads `{k v ...}` as a list, then pairs up its items.
+    fn read_map(&mut self, open: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let mut stx = self.read_list(open)?;
+        let items = match mem::replace(&mut stx.kind, StxKind::Error) {
+            StxKind::List(items) => items,
+            kind => {
+                stx.kind = kind;
+                return Ok(stx);
+            }
+        };
+
+        let mut entries = Vec::with_capacity(items.len() / 2);
+        let mut items = items.into_iter();
+        while let Some(key) = items.next() {
+            match items.next() {
+                Some(value) => entries.push((key, value)),
+                None => self.record(ReadError::new(ReadErrorKind::MissingMapValue, key.span))?,
+            }
+        }
+
+        stx.kind = StxKind::Map(entries);
+        Ok(stx)
+    }
+
+    /// Reads `#{x ...}` as a list, then drops the elements equal to earlier
+    /// ones.
+    fn read_set(&mut self, open: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        if !self.options.sets {
+            let end = self.skip_list();
+            let span = Span::new(open.span.start, end);
+            return Err(ReadError::new(ReadErrorKind::UnexpectedToken, span));
+        }
+
+        let mut stx = self.read_list(open)?;
+        let items = match mem::replace(&mut stx.kind, StxKind::Error) {
+            StxKind::List(items) => items,
+            kind => {
+                stx.kind = kind;
+                return Ok(stx);
+            }
+        };
+
+        stx.kind = StxKind::Set(self.set_elements(items)?);
+        Ok(stx)
+    }
+
+    /// Drops the items equal to earlier ones. Only the items whose hashes
+    /// are equal are compared, so large sets don't take quadratic time.
+    fn set_elements(&mut self, items: Vec<Stx<'a>>) -> Result<Vec<Stx<'a>>, ReadError> {
+        let mut elements: Vec<Stx<'a>> = Vec::with_capacity(items.len());
+        let mut by_hash: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+
+        for item in items {
+            // input that failed to read doesn't repeat other such input
+            if matches!(item.kind, StxKind::Error) {
+                elements.push(item);
+                continue;
+            }
+
+            let mut hasher = Fnv::default();
+            item.datum_hash(&mut hasher);
+            let same_hash = by_hash.entry(hasher.finish()).or_default();
+            if same_hash.iter().any(|&i| elements[i].datum_eq(&item)) {
+                self.record(ReadError::new(
+                    ReadErrorKind::DuplicateSetElement,
+                    item.span,
+                ))?;
+            } else {
+                same_hash.push(elements.len());
+                elements.push(item);
+            }
+        }
+
+        Ok(elements)
+    }
+
+    fn read_bytevector(&mut self, open: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let mut bytes = Vec::new();
+        self.delimiters.push((open.kind, open.span));
+
+        loop {
+            let lex = match self.peek_token() {
+                Ok(Some(lex)) => lex,
+                Ok(None) => {
+                    self.record(ReadError::new(ReadErrorKind::UnterminatedList, open.span))?;
+                    let close = self.eof_span();
+                    return Ok(bytevector(open, bytes, close));
+                }
+                Err(err) => {
+                    self.record(err)?;
+                    continue;
+                }
+            };
+
+            if lex.kind == LexemeKind::RParen {
+                self.scanner.next();
+                self.delimiters.pop();
+                return Ok(bytevector(open, bytes, lex.span));
+            }
+
+            if lex.kind.is_close_delimiter() && self.recovering && self.closes_outer(lex.kind) {
+                self.record(self.close_error(lex))?;
+                self.delimiters.pop();
+                let close = Span::new(lex.span.start, lex.span.start);
+                return Ok(bytevector(open, bytes, close));
+            }
+
+            if lex.kind != LexemeKind::IntLit {
+                match self.read_form() {
+                    Ok(stx) => self.record(ReadError::new(ReadErrorKind::InvalidByte, stx.span))?,
+                    Err(err) => self.record(err)?,
+                }
+                continue;
+            }
+
+            self.scanner.next();
+            match parse_byte(lex.slice) {
+                Some(byte) => bytes.push(byte),
+                None => self.record(ReadError::new(ReadErrorKind::InvalidByte, lex.span))?,
+            }
+        }
+    }
+
+    /// Expands `'x` into `(quote x)`, and likewise for the other prefixes.
+    /// A run of prefixes is collected first so it doesn't recurse.
+    fn read_quoted(&mut self, first: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let mut prefixes = vec![first];
+
+        loop {
+            match self.peek_token()? {
+                Some(lex) if quote_name(lex.kind).is_some() => {
+                    self.scanner.next();
+                    prefixes.push(lex);
+                }
+                Some(lex) if !lex.kind.is_close_delimiter() => break,
+                _ => {
+                    let last = prefixes[prefixes.len() - 1];
+                    return Err(ReadError::new(ReadErrorKind::MissingDatum, last.span));
+                }
+            }
+        }
+
+        if self.depth() + prefixes.len() > self.max_depth {
+            let end = self.read_form()?.span.end;
+            return Err(ReadError::new(
+                ReadErrorKind::TooDeep,
+                Span::new(first.span.start, end),
+            ));
+        }
+
+        self.prefixes += prefixes.len();
+        let datum = self.read_form();
+        self.prefixes -= prefixes.len();
+
+        let mut stx = datum?;
+        for prefix in prefixes.into_iter().rev() {
+            let name = quote_name(prefix.kind).unwrap_or_default();
+            let mut symbol = Stx::new(StxKind::Symbol(Cow::Borrowed(name)), prefix.span);
+            symbol.symbol = Some(self.symbols.intern(name));
+            let span = Span::new(prefix.span.start, stx.span.end);
+            stx = Stx::new(StxKind::List(vec![symbol, stx]), span);
+        }
+
+        Ok(stx)
+    }
+
+    /// Reads `#N=datum`. The datum is shared behind an `Rc` before it is
+    /// read, so references inside it can point back to it.
+    fn read_labeled(&mut self, lex: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let label = label_number(lex)?;
+
+        if self.labels.iter().any(|defined| defined.label == label) {
+            return Err(ReadError::new(ReadErrorKind::DuplicateLabel, lex.span));
+        }
+
+        if self.labels.len() >= self.max_labels {
+            return Err(ReadError::new(ReadErrorKind::TooManyLabels, lex.span));
+        }
+
+        match self.peek_token()? {
+            Some(next) if !next.kind.is_close_delimiter() => {}
+            _ => return Err(ReadError::new(ReadErrorKind::MissingDatum, lex.span)),
+        }
+
+        if self.depth() >= self.max_depth {
+            let end = self.read_form()?.span.end;
+            return Err(ReadError::new(
+                ReadErrorKind::TooDeep,
+                Span::new(lex.span.start, end),
+            ));
+        }
+
+        let datum = Rc::new(RefCell::new(Stx::new(StxKind::Error, lex.span)));
+        self.labels.push(Label {
+            label,
+            datum: Rc::clone(&datum),
+            reading: true,
+        });
+
+        self.prefixes += 1;
+        let result = self.read_form();
+        self.prefixes -= 1;
+
+        let stx = result?;
+        let span = Span::new(lex.span.start, stx.span.end);
+        *datum.borrow_mut() = stx;
+
+        if let Some(defined) = self
+            .labels
+            .iter_mut()
+            .find(|defined| defined.label == label)
+        {
+            defined.reading = false;
+        }
+
+        Ok(Stx::new(StxKind::Labeled(label, datum), span))
+    }
+
+    fn read_label_ref(&mut self, lex: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let label = label_number(lex)?;
+
+        let kind = match self.labels.iter().find(|defined| defined.label == label) {
+            None => return Err(ReadError::new(ReadErrorKind::UndefinedLabel, lex.span)),
+            Some(defined) if defined.reading && self.strict_labels => {
+                return Err(ReadError::new(ReadErrorKind::CyclicLabel, lex.span))
+            }
+            Some(defined) => StxKind::LabelRef(LabelRef::new(label, &defined.datum)),
+        };
+
+        Ok(Stx::new(kind, lex.span))
+    }
+
+    fn depth(&self) -> usize {
+        self.delimiters.len() + self.prefixes
+    }
+
+    /// Skips the rest of a list whose open delimiter was just consumed,
+    /// without recursing, and returns where it ended.
+    fn skip_list(&mut self) -> u32 {
+        let mut depth = 1;
+        let mut end = self.eof_span().end;
+
+        for lex in self.scanner.by_ref() {
+            if lex.kind.is_open_delimiter() {
+                depth += 1;
+            } else if lex.kind.is_close_delimiter() {
+                depth -= 1;
+
+                if depth == 0 {
+                    end = lex.span.end;
+                    break;
+                }
+            }
+        }
+
+        end
+    }
+
+    /// Whether `close` matches a list enclosing the innermost one.
+    fn closes_outer(&self, close: LexemeKind) -> bool {
+        let outer = &self.delimiters[..self.delimiters.len().saturating_sub(1)];
+        outer
+            .iter()
+            .any(|(open, _)| open.matching_close() == Some(close))
+    }
+
+    /// Reads a datum, standing in an error node for it when recovering.
+    fn read_or_recover(&mut self) -> Result<Stx<'a>, ReadError> {
+        match self.read_form() {
+            Ok(stx) => Ok(stx),
+            Err(err) => {
+                self.record(err)?;
+                Ok(Stx::new(StxKind::Error, err.span))
+            }
+        }
+    }
+
+    /// Keeps `err` and carries on when recovering, fails otherwise. Going
+    /// past a limit always fails.
+    fn record(&mut self, err: ReadError) -> Result<(), ReadError> {
+        if !self.recovering || matches!(err.kind, ReadErrorKind::LimitExceeded(_)) {
+            return Err(err);
+        }
+
+        self.errors.push(self.in_file(err));
+        Ok(())
+    }
+
+    fn in_file(&self, err: ReadError) -> ReadError {
+        match self.file {
+            Some(file) => err.with_file(file),
+            None => err,
+        }
+    }
+
+    /// Consumes the whole string even when part of it is invalid, so reading
+    /// can carry on after the error. The text is only copied once an escape
+    /// needs decoding.
+    fn read_string(&mut self, open: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let mut error = None;
+        let mut decoded: Option<String> = None;
+
+        while let Some(lex) = self.scanner.next() {
+            match lex.kind {
+                LexemeKind::RString => {
+                    if let Some(error) = error {
+                        return Err(error);
+                    }
+
+                    let value = match decoded {
+                        Some(decoded) => Cow::Owned(decoded),
+                        None => Cow::Borrowed(self.text(Span::new(open.span.end, lex.span.start))),
+                    };
+                    let span = Span::new(open.span.start, lex.span.end);
+                    return Ok(Stx::new(StxKind::String(value), span));
+                }
+                LexemeKind::StringContent
+                | LexemeKind::NewlineLf
+                | LexemeKind::NewlineCr
+                | LexemeKind::NewlineCrlf => {
+                    if let Some(decoded) = &mut decoded {
+                        decoded.push_str(lex.slice);
+                    }
+                }
+                LexemeKind::StringEscape | LexemeKind::StringLineContinuation => {
+                    let before = Span::new(open.span.end, lex.span.start);
+                    let decoded = decoded.get_or_insert_with(|| String::from(self.text(before)));
+
+                    if lex.kind == LexemeKind::StringEscape {
+                        match unescape(lex.slice) {
+                            Some(ch) => decoded.push(ch),
+                            None if error.is_none() => {
+                                error =
+                                    Some(ReadError::new(ReadErrorKind::InvalidEscape, lex.span));
+                            }
+                            None => {}
+                        }
+                    }
+                }
+                LexemeKind::Error(err) if error.is_none() => {
+                    error = Some(ReadError::new(err.into(), lex.span));
+                }
+                _ => {}
+            }
+        }
+
+        // when recovering, the string runs to the end of the input
+        let span = self.span_to_eof(open.span);
+        let unterminated = ReadError::new(ReadErrorKind::UnterminatedString, span);
+        if !self.recovering {
+            return Err(unterminated);
+        }
+        if let Some(error) = error {
+            self.record(error)?;
+        }
+        self.record(unterminated)?;
+
+        let value = match decoded {
+            Some(decoded) => Cow::Owned(decoded),
+            None => Cow::Borrowed(self.text(Span::new(open.span.end, span.end))),
+        };
+        Ok(Stx::new(StxKind::String(value), span).mark_incomplete())
+    }
+
+    fn read_heredoc(&mut self, open: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        while let Some(lex) = self.scanner.next() {
+            match lex.kind {
+                LexemeKind::HeredocContent => {}
+                LexemeKind::HeredocEnd => {
+                    let body = Span::new(open.span.end, lex.span.start);
+                    let span = Span::new(open.span.start, lex.span.end);
+                    return Ok(Stx::new(
+                        StxKind::String(Cow::Borrowed(self.text(body))),
+                        span,
+                    ));
+                }
+                // when recovering, the heredoc runs to the end of the input
+                LexemeKind::Error(ScanErrorKind::UnterminatedHeredoc) => {
+                    self.record(ReadError::new(
+                        ScanErrorKind::UnterminatedHeredoc.into(),
+                        lex.span,
+                    ))?;
+                    let body = Span::new(open.span.end, lex.span.end);
+                    let span = Span::new(open.span.start, lex.span.end);
+                    let value = StxKind::String(Cow::Borrowed(self.text(body)));
+                    return Ok(Stx::new(value, span).mark_incomplete());
+                }
+                LexemeKind::Error(err) => return Err(ReadError::new(err.into(), lex.span)),
+                _ => break,
+            }
+        }
+
+        Err(ReadError::new(
+            ReadErrorKind::UnterminatedString,
+            self.span_to_eof(open.span),
+        ))
+    }
+
+    /// Source text covered by lexemes the scanner accepted, which is always
+    /// valid UTF-8.
+    fn text(&self, span: Span) -> &'a str {
+        str::from_utf8(&self.src_bytes[span.range()]).unwrap_or_default()
+    }
+
+    fn eof_span(&self) -> Span {
+        let len = self.src_bytes.len() as u32;
+        Span::new(len, len)
+    }
+
+    fn span_to_eof(&self, start: Span) -> Span {
+        Span::new(start.start, self.src_bytes.len() as u32)
+    }
+}
+
+/// Yields each datum in turn. Every error consumes the input it was reported
+/// for, so iteration can go on past it.
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<Stx<'a>, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_one().transpose()
+    }
+}
+
+fn parse_byte(slice: &str) -> Option<u8> {
+    match number::parse(slice) {
+        Some(Number::Int(value)) => u8::try_from(value).ok(),
+        _ => None,
+    }
+}
+
+/// The character named after the `#\\` of a `CharLit`.
+fn char_value(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    let first = chars.next()?;
+
+    if chars.as_str().is_empty() {
+        return Some(first);
+    }
+
+    if let Some(&(_, ch)) = CHAR_NAMES.iter().find(|(known, _)| *known == name) {
+        return Some(ch);
+    }
+
+    name.strip_prefix('x').and_then(hex_char)
+}
+
+/// Why the text after `#\\` isn't a character, suggesting a known name
+/// when the text looks like a misspelling of one.
+fn char_error(name: &str) -> ReadErrorKind {
+    let hex = name
+        .strip_prefix('x')
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|ch| ch.is_ascii_hexdigit()));
+    if name.chars().nth(1).is_none() || hex.is_some() {
+        return ReadErrorKind::InvalidChar;
+    }
+
+    let lower = name.to_ascii_lowercase();
+    let suggestion = CHAR_NAMES
+        .iter()
+        .map(|&(known, _)| (edit_distance(&lower, known), known))
+        .filter(|&(distance, _)| distance <= 2 && distance < name.len())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, known)| known);
+
+    ReadErrorKind::UnknownCharName { suggestion }
+}
+
+/// The Levenshtein distance between `a` and `b`, by bytes.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.bytes().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.bytes().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// The character a `StringEscape` stands for, or `None` for a hex escape
+/// whose code point isn't a character.
+fn unescape(escape: &str) -> Option<char> {
+    let ch = match &escape[1..] {
+        "n" => '\n',
+        "t" => '\t',
+        "r" => '\r',
+        "a" => '\x07',
+        "b" => '\x08',
+        "0" => '\0',
+        "\\" => '\\',
+        "\"" => '"',
+        "|" => '|',
+        hex => {
+            let digits = hex
+                .strip_prefix(|ch| ch == 'x' || ch == 'X')
+                .and_then(|digits| digits.strip_suffix(';'))
+                .or_else(|| hex.strip_prefix("u{")?.strip_suffix('}'))?;
+
+            return hex_char(digits);
+        }
+    };
+
+    Some(ch)
+}
+
+/// The name between the bars of `|name|`, with the escapes strings have
+/// decoded. Only copied if there are any.
+fn unescape_bars(body: &str) -> Option<Cow<'_, str>> {
+    if !body.contains('\\') {
+        return Some(Cow::Borrowed(body));
+    }
+
+    let mut name = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(i) = rest.find('\\') {
+        name.push_str(&rest[..i]);
+        let escape = &rest[i..];
+        let len = match escape[1..].chars().next()? {
+            'x' | 'X' => escape.find(';')? + 1,
+            'u' => escape.find('}')? + 1,
+            ch => 1 + ch.len_utf8(),
+        };
+        name.push(unescape(&escape[..len])?);
+        rest = &escape[len..];
+    }
+    name.push_str(rest);
+
+    Some(Cow::Owned(name))
+}
+
+/// The `N` of `#N=` or `#N#`.
+fn label_number(lex: Lexeme<'_>) -> Result<u32, ReadError> {
+    lex.slice[1..lex.slice.len() - 1]
+        .parse()
+        .map_err(|_| ReadError::new(ReadErrorKind::BadNumber, lex.span))
+}
+
+fn quote_name(kind: LexemeKind) -> Option<&'static str> {
+    match kind {
+        LexemeKind::Quote => Some("quote"),
+        LexemeKind::Quasiquote => Some("quasiquote"),
+        LexemeKind::Unquote => Some("unquote"),
+        LexemeKind::UnquoteSplicing => Some("unquote-splicing"),
+        _ => None,
+    }
+}
+
+fn list<'a>(
+    open: Lexeme<'a>,
+    items: Vec<Stx<'a>>,
+    tail: Option<(Span, Box<Stx<'a>>)>,
+    close: Span,
+) -> Stx<'a> {
+    let kind = match (open.kind, tail) {
+        (LexemeKind::VectorOpen, _) => StxKind::Vector(items),
+        (_, Some((_, tail))) => StxKind::DottedList(items, tail),
+        (_, None) => StxKind::List(items),
+    };
+
+    let mut stx =
+        Stx::new(kind, Span::new(open.span.start, close.end)).with_delims(open.span, close);
+    // only a list that was never closed has an empty `close`
+    stx.incomplete = close.is_empty();
+    stx
+}
+
+/// Drops the spans of a datum, sharing the stripped datums of its labels
+/// the way the originals were.
+#[derive(Default)]
+struct Strip<'a> {
+    labels: Vec<(u32, Rc<RefCell<Datum<'a>>>)>,
+}
+
+impl<'a> Strip<'a> {
+    fn datum(&mut self, stx: Stx<'a>) -> Result<Datum<'a>, ReadError> {
+        let datum = match stx.kind {
+            StxKind::Bool(value) => Datum::Bool(value),
+            StxKind::Number(value) => Datum::Number(value),
+            StxKind::Char(ch) => Datum::Char(ch),
+            StxKind::String(text) => Datum::String(text),
+            StxKind::Symbol(name) => Datum::Symbol(name),
+            StxKind::Keyword(keyword) => Datum::Keyword(keyword),
+            StxKind::List(items) => Datum::List(self.all(items)?),
+            StxKind::DottedList(items, tail) => {
+                let items = self.all(items)?;
+                Datum::DottedList(items, Box::new(self.datum(*tail)?))
+            }
+            StxKind::Vector(items) => Datum::Vector(self.all(items)?),
+            StxKind::Map(entries) => Datum::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| Ok((self.datum(key)?, self.datum(value)?)))
+                    .collect::<Result<_, ReadError>>()?,
+            ),
+            StxKind::Set(items) => Datum::Set(self.all(items)?),
+            StxKind::Bytevector(bytes) => Datum::Bytevector(bytes),
+            StxKind::Labeled(label, datum) => {
+                // shared before stripping, so references inside can point to it
+                let shared = Rc::new(RefCell::new(Datum::List(Vec::new())));
+                self.labels.push((label, Rc::clone(&shared)));
+
+                let stx = match Rc::try_unwrap(datum) {
+                    Ok(datum) => datum.into_inner(),
+                    Err(datum) => datum.borrow().clone(),
+                };
+                let value = self.datum(stx)?;
+                *shared.borrow_mut() = value;
+                Datum::Labeled(label, shared)
+            }
+            StxKind::LabelRef(label_ref) => {
+                let label = label_ref.label;
+                match self
+                    .labels
+                    .iter()
+                    .rev()
+                    .find(|(defined, _)| *defined == label)
+                {
+                    Some((_, shared)) => Datum::LabelRef(LabelRef::new(label, shared)),
+                    None => return Err(ReadError::new(ReadErrorKind::UndefinedLabel, stx.span)),
+                }
+            }
+            // only a reader macro can leave one outside of recovery
+            StxKind::Error => return Err(ReadError::new(ReadErrorKind::UnexpectedToken, stx.span)),
+        };
+
+        Ok(datum)
+    }
+
+    fn all(&mut self, items: Vec<Stx<'a>>) -> Result<Vec<Datum<'a>>, ReadError> {
+        items.into_iter().map(|stx| self.datum(stx)).collect()
+    }
+}
+
+fn bytevector<'a>(open: Lexeme<'a>, bytes: Vec<u8>, close: Span) -> Stx<'a> {
+    let span = Span::new(open.span.start, close.end);
+    let mut stx = Stx::new(StxKind::Bytevector(bytes), span).with_delims(open.span, close);
+    stx.incomplete = close.is_empty();
+    stx
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::scanner::{ScanErrorKind, ScannerOptions};
+    use crate::stx::Delims;
+
+    fn read(src: &str) -> Result<Option<Stx<'_>>, ReadError> {
+        Reader::new(src).read_one()
+    }
+
+    fn symbol(name: &str, start: u32) -> Stx<'_> {
+        Stx::new(
+            StxKind::Symbol(Cow::Borrowed(name)),
+            Span::new(start, start + name.len() as u32),
+        )
+    }
+
+    #[test]
+    fn test_read_atoms() {
+        let mut reader =
+            Reader::new("foo 12 -1.5 3/4 #t #false #\\a \"a\\nb\" |odd sym| #<<EOF\nhi\nEOF");
+        let mut kinds = Vec::new();
+        while let Some(stx) = reader.read_one().unwrap() {
+            kinds.push(stx.kind);
+        }
+
+        assert_eq!(
+            kinds,
+            vec![
+                StxKind::Symbol(Cow::Borrowed("foo")),
+                StxKind::Number(Number::Int(12)),
+                StxKind::Number(Number::Float(-1.5)),
+                StxKind::Number(number::parse("3/4").unwrap()),
+                StxKind::Bool(true),
+                StxKind::Bool(false),
+                StxKind::Char('a'),
+                StxKind::String(Cow::Borrowed("a\nb")),
+                StxKind::Symbol(Cow::Borrowed("odd sym")),
+                StxKind::String(Cow::Borrowed("hi\n")),
+            ]
+        );
+
+        let kinds: Vec<_> = Reader::new(r"|a\|b| |a\x41;b| |a\\b| |\u{3bb}\t|")
+            .map(|stx| stx.unwrap().kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                StxKind::Symbol(Cow::Borrowed("a|b")),
+                StxKind::Symbol(Cow::Borrowed("aAb")),
+                StxKind::Symbol(Cow::Borrowed("a\\b")),
+                StxKind::Symbol(Cow::Borrowed("\u{3bb}\t")),
+            ]
+        );
+        assert_eq!(
+            read(r"|a\q|").unwrap_err(),
+            ReadError::new(ReadErrorKind::InvalidEscape, Span::new(0, 5))
+        );
+    }
+
+    #[test]
+    fn test_read_keywords() {
+        let src = "#:foo :foo foo: foo #:";
+        let options = ScannerOptions::new()
+            .leading_colon_keywords(true)
+            .trailing_colon_keywords(true);
+        let forms = Reader::new(src)
+            .with_scanner_options(options)
+            .read_all()
+            .unwrap();
+
+        let keyword = |i: usize| match &forms[i].kind {
+            StxKind::Keyword(keyword) => keyword.clone(),
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(keyword(0).name(), "foo");
+        assert!(keyword(0).ptr_eq(&keyword(1)) && keyword(0).ptr_eq(&keyword(2)));
+        assert_eq!(forms[3].kind, StxKind::Symbol(Cow::Borrowed("foo")));
+        assert_eq!(keyword(4).name(), "");
+        assert_ne!(forms[0].kind, forms[3].kind);
+
+        assert_eq!(
+            Reader::new("#!fold-case #:FOO")
+                .read_one()
+                .unwrap()
+                .unwrap()
+                .kind,
+            StxKind::Keyword(Keyword::new("foo"))
+        );
+    }
+
+    #[test]
+    fn test_read_symbols() {
+        let mut reader = Reader::new("(foo #:foo |foo| 'bar 42) #!fold-case FOO");
+        let forms = reader.read_all().unwrap();
+        let symbols = reader.into_symbols();
+
+        let items = match &forms[0].kind {
+            StxKind::List(items) => items,
+            kind => panic!("{:?}", kind),
+        };
+        let foo = symbols.get("foo");
+        assert!(foo.is_some());
+        assert_eq!(items[0].symbol, foo);
+        assert_eq!(items[1].symbol, foo);
+        assert_eq!(items[2].symbol, foo);
+        assert_eq!(forms[1].symbol, foo);
+        assert_eq!(items[4].symbol, None);
+        assert_eq!(forms[0].symbol, None);
+
+        let quoted = match &items[3].kind {
+            StxKind::List(items) => items,
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(quoted[0].symbol, symbols.get("quote"));
+        assert_eq!(quoted[1].symbol, symbols.get("bar"));
+        assert_eq!(symbols.len(), 3);
+
+        let mut reader = Reader::new("bar baz").with_symbols(symbols);
+        let forms = reader.read_all().unwrap();
+        assert_eq!(forms[0].symbol, reader.symbols().get("bar"));
+        assert_eq!(forms[1].symbol.map(|symbol| symbol.index()), Some(3));
+    }
+
+    #[test]
+    fn test_read_list() {
+        let stx = read(" (a [b] #(c) . d) ").unwrap().unwrap();
+
+        assert_eq!(
+            stx,
+            Stx::new(
+                StxKind::DottedList(
+                    vec![
+                        symbol("a", 2),
+                        Stx::new(StxKind::List(vec![symbol("b", 5)]), Span::new(4, 7))
+                            .with_delims(Span::new(4, 5), Span::new(6, 7)),
+                        Stx::new(StxKind::Vector(vec![symbol("c", 10)]), Span::new(8, 12))
+                            .with_delims(Span::new(8, 10), Span::new(11, 12)),
+                    ],
+                    Box::new(symbol("d", 15)),
+                ),
+                Span::new(1, 17),
+            )
+            .with_delims(Span::new(1, 2), Span::new(16, 17))
+        );
+
+        assert_eq!(read("()").unwrap().unwrap().kind, StxKind::List(vec![]));
+        assert_eq!(read("  ; nothing\n").unwrap(), None);
+    }
+
+    #[test]
+    fn test_delims() {
+        let delims = |src| read(src).unwrap().unwrap().delims;
+        let delims_of = |open: (u32, u32), close: (u32, u32)| {
+            Some(Delims {
+                open: Span::new(open.0, open.1),
+                close: Span::new(close.0, close.1),
+            })
+        };
+
+        assert_eq!(delims("( a )"), delims_of((0, 1), (4, 5)));
+        assert_eq!(delims("#( a )"), delims_of((0, 2), (5, 6)));
+        assert_eq!(delims("#u8( 1 )"), delims_of((0, 4), (7, 8)));
+        assert_eq!(delims("'a"), None);
+        assert_eq!(delims("a"), None);
+
+        let (forms, _) = Reader::new("(a").read_all_with_recovery();
+        assert_eq!(forms[0].delims, delims_of((0, 1), (2, 2)));
+    }
+
+    #[test]
+    fn test_read_datum() {
+        let mut reader = Reader::new("(a \"s\" #(1) . #u8(2)) #0=(b . #0#) c");
+
+        assert_eq!(
+            reader.read_datum().unwrap().unwrap(),
+            Datum::DottedList(
+                vec![
+                    Datum::Symbol(Cow::Borrowed("a")),
+                    Datum::String(Cow::Borrowed("s")),
+                    Datum::Vector(vec![Datum::Number(Number::Int(1))]),
+                ],
+                Box::new(Datum::Bytevector(vec![2])),
+            )
+        );
+
+        match reader.read_datum().unwrap().unwrap() {
+            Datum::Labeled(0, shared) => match &*shared.borrow() {
+                Datum::DottedList(_, tail) => match &**tail {
+                    Datum::LabelRef(label_ref) => {
+                        assert!(Rc::ptr_eq(&label_ref.get().unwrap(), &shared))
+                    }
+                    datum => panic!("{:?}", datum),
+                },
+                datum => panic!("{:?}", datum),
+            },
+            datum => panic!("{:?}", datum),
+        }
+
+        assert_eq!(reader.read_syntax().unwrap().unwrap(), symbol("c", 35));
+        assert_eq!(reader.read_datum().unwrap(), None);
+        assert_eq!(
+            Reader::new(")").read_datum().unwrap_err().kind,
+            ReadErrorKind::UnexpectedClose
+        );
+        assert_eq!(
+            Reader::new("#;#0=a #0#").read_datum(),
+            Err(ReadError::new(
+                ReadErrorKind::UndefinedLabel,
+                Span::new(7, 10)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let src = ";;; Adds two numbers\n;;;   together\n(def (add a b)\n  ;;; the sum\n  (+ a b) ;;; dropped\n)\n\
+                   ;;; header\n\n(x)\n;;; stale\n; plain\ny";
+        let forms = Reader::new(src).with_doc_comments(true).read_all().unwrap();
+
+        let doc = forms[0].doc.as_ref().unwrap();
+        assert_eq!(doc.text(), "Adds two numbers\n  together");
+        assert_eq!(doc.span, Span::new(0, 35));
+        match &forms[0].kind {
+            StxKind::List(items) => {
+                assert_eq!(items[2].doc.as_ref().unwrap().text(), "the sum");
+                assert!(items[1].doc.is_none());
+            }
+            kind => panic!("{:?}", kind),
+        }
+        assert_eq!(forms[1].doc, None);
+        assert_eq!(forms[2].doc, None);
+
+        assert_eq!(read(";;; doc\nx").unwrap().unwrap().doc, None);
+    }
+
+    #[test]
+    fn test_maps() {
+        let read = |src| {
+            Reader::new(src)
+                .with_options(ReaderOptions::new().maps(true))
+                .read_all_with_recovery()
+        };
+
+        let (forms, errors) = read("{a 1 b {}} {}");
+        assert!(errors.is_empty());
+        assert_eq!(
+            forms[0].kind,
+            StxKind::Map(vec![
+                (
+                    symbol("a", 1),
+                    Stx::new(StxKind::Number(Number::Int(1)), Span::new(3, 4))
+                ),
+                (
+                    symbol("b", 5),
+                    Stx::new(StxKind::Map(vec![]), Span::new(7, 9))
+                        .with_delims(Span::new(7, 8), Span::new(8, 9))
+                ),
+            ])
+        );
+        assert_eq!(forms[0].delims.unwrap().close, Span::new(9, 10));
+        assert_eq!(forms[1].kind, StxKind::Map(vec![]));
+
+        let (forms, errors) = read("{a 1 b} {a . b}");
+        assert_eq!(
+            errors,
+            vec![
+                ReadError::new(ReadErrorKind::MissingMapValue, Span::new(5, 6)),
+                ReadError::new(ReadErrorKind::UnexpectedDot, Span::new(11, 12)),
+                ReadError::new(ReadErrorKind::MissingMapValue, Span::new(13, 14)),
+            ]
+        );
+        assert_eq!(
+            forms[0].kind,
+            StxKind::Map(vec![(
+                symbol("a", 1),
+                Stx::new(StxKind::Number(Number::Int(1)), Span::new(3, 4))
+            )])
+        );
+
+        assert_eq!(read("{a}").0[0].kind, StxKind::Map(vec![]));
+        assert_eq!(
+            Reader::new("{a}").read_one().unwrap().unwrap().kind,
+            StxKind::List(vec![symbol("a", 1)])
+        );
+    }
+
+    #[test]
+    fn test_sets() {
+        let read = |src| {
+            Reader::new(src)
+                .with_options(ReaderOptions::new().sets(true))
+                .read_all_with_recovery()
+        };
+
+        let (forms, errors) = read("#{1 a} #{}");
+        assert!(errors.is_empty());
+        assert_eq!(
+            forms[0],
+            Stx::new(
+                StxKind::Set(vec![
+                    Stx::new(StxKind::Number(Number::Int(1)), Span::new(2, 3)),
+                    symbol("a", 4),
+                ]),
+                Span::new(0, 6)
+            )
+            .with_delims(Span::new(0, 2), Span::new(5, 6))
+        );
+        assert_eq!(forms[1].kind, StxKind::Set(vec![]));
+
+        let (forms, errors) = read("#{(a 1) b (a 1) #x1}");
+        assert_eq!(
+            errors,
+            vec![ReadError::new(
+                ReadErrorKind::DuplicateSetElement,
+                Span::new(10, 15)
+            )]
+        );
+        match &forms[0].kind {
+            StxKind::Set(elements) => assert_eq!(elements.len(), 3),
+            kind => panic!("{:?}", kind),
+        }
+
+        let many: String = (0..40_000).map(|i| format!("a{} ", i)).collect();
+        let many = format!("#{{{}a7}}", many);
+        let (forms, errors) = read(&many);
+        assert_eq!(errors.len(), 1);
+        match &forms[0].kind {
+            StxKind::Set(elements) => assert_eq!(elements.len(), 40_000),
+            kind => panic!("{:?}", kind),
+        }
+
+        assert_eq!(
+            read("#{a . b}").1[0],
+            ReadError::new(ReadErrorKind::UnexpectedDot, Span::new(4, 5))
+        );
+        assert_eq!(
+            Reader::new("#{a (b)} c").read_all_with_recovery().1,
+            vec![ReadError::new(
+                ReadErrorKind::UnexpectedToken,
+                Span::new(0, 8)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut reader = Reader::new("#!fold-case (A) B");
+        assert_eq!(
+            reader.read_one().unwrap().unwrap().kind,
+            StxKind::List(vec![symbol("a", 13)])
+        );
+        let state = reader.state();
+        assert_eq!(state.offset(), 15);
+
+        // the input grew in the meantime
+        let mut reader = Reader::resume("#!fold-case (A) B C", state.clone());
+        assert_eq!(reader.read_one().unwrap().unwrap(), symbol("b", 16));
+        assert_eq!(reader.read_one().unwrap().unwrap(), symbol("c", 18));
+        assert_eq!(reader.read_one().unwrap(), None);
+
+        let options = ScannerOptions::new().leading_colon_keywords(true);
+        let mut reader = Reader::resume("#!fold-case (A) B", state).with_scanner_options(options);
+        assert_eq!(reader.read_one().unwrap().unwrap(), symbol("b", 16));
+
+        let file = FileId(3);
+        let mut reader = Reader::new("{a 1} {b 2} :c")
+            .with_options(ReaderOptions::new().maps(true))
+            .with_scanner_options(ScannerOptions::new().leading_colon_keywords(true))
+            .with_doc_comments(true)
+            .with_file(file);
+        reader.read_one().unwrap();
+        let mut reader = Reader::resume("{a 1} {b 2} ;;; doc\n :c", reader.state());
+        let map = reader.read_one().unwrap().unwrap();
+        let two = Stx::new(StxKind::Number(Number::Int(2)), Span::new(9, 10));
+        assert_eq!(map.kind, StxKind::Map(vec![(symbol("b", 7), two)]));
+        let keyword = reader.read_one().unwrap().unwrap();
+        assert!(matches!(keyword.kind, StxKind::Keyword(_)));
+        assert_eq!(keyword.doc.unwrap().text(), "doc");
+        assert_eq!(reader.file(), Some(file));
+        assert_eq!(reader.symbols().len(), 3);
+
+        let mut reader = Reader::at_offset("(a) (b)", 3);
+        assert_eq!(reader.read_one().unwrap().unwrap().span, Span::new(4, 7));
+        assert_eq!(reader.state().offset(), 7);
+    }
+
+    #[test]
+    fn test_read_comments() {
+        let stx = read("(a #;(b c) #| d |# e) ; f").unwrap().unwrap();
+
+        assert_eq!(
+            stx.kind,
+            StxKind::List(vec![symbol("a", 1), symbol("e", 19)])
+        );
+        assert_eq!(read("#;a").unwrap(), None);
+        assert_eq!(
+            read("#!fold-case FOO").unwrap().unwrap().kind,
+            StxKind::Symbol(Cow::Borrowed("foo"))
+        );
+    }
+
+    #[test]
+    fn test_datum_comments() {
+        let kinds = |src| {
+            Reader::new(src)
+                .read_all()
+                .unwrap()
+                .into_iter()
+                .map(|stx| stx.kind)
+                .collect::<Vec<_>>()
+        };
+        let c = || vec![StxKind::Symbol(Cow::Borrowed("c"))];
+
+        assert_eq!(kinds("#;#;a b c"), c());
+        assert_eq!(kinds("#; #; a\n b c"), c());
+        assert_eq!(kinds("#;(a #;b) c"), c());
+        assert_eq!(kinds("#;#;(a) #;b #(d) c"), c());
+        assert_eq!(kinds("#;'a c"), c());
+        assert_eq!(kinds("#;#u8(1) #;; a\n #| b |# d c"), c());
+        assert_eq!(
+            kinds("(#;#;a b c)"),
+            vec![StxKind::List(vec![symbol("c", 9)])]
+        );
+        assert_eq!(
+            kinds("(a . #;b c)"),
+            vec![StxKind::DottedList(
+                vec![symbol("a", 1)],
+                Box::new(symbol("c", 9))
+            )]
+        );
+
+        let error = |src| read(src).unwrap_err();
+        assert_eq!(
+            error("(a #;#;b)"),
+            ReadError::new(ReadErrorKind::MissingDatum, Span::new(3, 5))
+        );
+        assert_eq!(
+            error("#;#;a"),
+            ReadError::new(ReadErrorKind::MissingDatum, Span::new(0, 2))
+        );
+        assert_eq!(
+            error("(#;#0=a #0#)"),
+            ReadError::new(ReadErrorKind::UndefinedLabel, Span::new(8, 11))
+        );
+        assert_eq!(
+            error("#0=(#;#1=a #1#)"),
+            ReadError::new(ReadErrorKind::UndefinedLabel, Span::new(11, 14))
+        );
+        assert_eq!(
+            kinds("#;#0=a #0=b")[0],
+            StxKind::Labeled(0, Rc::new(RefCell::new(symbol("b", 10))))
+        );
+        assert_eq!(
+            error("#;#;#;"),
+            ReadError::new(ReadErrorKind::MissingDatum, Span::new(4, 6))
+        );
+        assert_eq!(
+            error("#;(a"),
+            ReadError::new(ReadErrorKind::UnterminatedList, Span::new(2, 3))
+        );
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let error = |src| read(src).unwrap_err();
+
+        assert_eq!(
+            error("(a b"),
+            ReadError::new(ReadErrorKind::UnterminatedList, Span::new(0, 1))
+        );
+        assert_eq!(
+            error(")"),
+            ReadError::new(ReadErrorKind::UnexpectedClose, Span::new(0, 1))
+        );
+        assert_eq!(
+            error("(a]"),
+            ReadError::new(
+                ReadErrorKind::MismatchedDelimiter {
+                    open: LexemeKind::LParen,
+                    open_span: Span::new(0, 1)
+                },
+                Span::new(2, 3)
+            )
+        );
+        assert_eq!(
+            error("\"ab"),
+            ReadError::new(ReadErrorKind::UnterminatedString, Span::new(0, 3))
+        );
+        assert_eq!(error("(. a)").kind, ReadErrorKind::UnexpectedDot);
+        assert_eq!(error("(a . b c)").kind, ReadErrorKind::UnexpectedDot);
+        assert_eq!(error("(a .)").kind, ReadErrorKind::MissingDatum);
+        assert_eq!(error("(#;)").kind, ReadErrorKind::MissingDatum);
+        assert_eq!(error("\u{1}").kind, ReadErrorKind::UnexpectedToken);
+        assert_eq!(
+            error("#q"),
+            ReadError::new(
+                ReadErrorKind::Lex(ScanErrorKind::UnknownDispatch),
+                Span::new(0, 2)
+            )
+        );
+
+        let mut reader = Reader::new("\"a\\qb\" c");
+        assert_eq!(reader.read_one().unwrap_err().span, Span::new(2, 4));
+        assert_eq!(reader.read_one().unwrap().unwrap(), symbol("c", 7));
+    }
+
+    #[test]
+    fn test_read_all() {
+        let forms = Reader::new("(a) b ; c\n").read_all().unwrap();
+        assert_eq!(
+            forms,
+            vec![
+                Stx::new(StxKind::List(vec![symbol("a", 1)]), Span::new(0, 3))
+                    .with_delims(Span::new(0, 1), Span::new(2, 3)),
+                symbol("b", 4),
+            ]
+        );
+
+        assert_eq!(Reader::new("").read_all(), Ok(vec![]));
+        assert_eq!(
+            Reader::new("a ) b").read_all(),
+            Err(ReadError::new(
+                ReadErrorKind::UnexpectedClose,
+                Span::new(2, 3)
+            ))
+        );
+
+        let results = Reader::new("a ) b").collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            vec![
+                Ok(symbol("a", 0)),
+                Err(ReadError::new(
+                    ReadErrorKind::UnexpectedClose,
+                    Span::new(2, 3)
+                )),
+                Ok(symbol("b", 4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_error_kinds() {
+        let error = |src| read(src).unwrap_err();
+
+        assert_eq!(
+            error("#b12"),
+            ReadError::new(ReadErrorKind::BadNumber, Span::new(0, 4))
+        );
+        assert_eq!(error("#x").kind, ReadErrorKind::BadNumber);
+        assert_eq!(error("\"\\q\"").kind, ReadErrorKind::InvalidEscape);
+        assert_eq!(
+            error("#\\bogus").kind,
+            ReadErrorKind::UnknownCharName { suggestion: None }
+        );
+        assert_eq!(
+            error("#<<EOF\nx").kind,
+            ReadErrorKind::Lex(ScanErrorKind::UnterminatedHeredoc)
+        );
+
+        assert_eq!(error("(a").to_string(), "unterminated list at 0..1");
+        assert_eq!(
+            ReadErrorKind::Lex(ScanErrorKind::UnknownDispatch).to_string(),
+            "unknown character after `#`"
+        );
+
+        let boxed: Box<dyn std::error::Error> = Box::new(error(")"));
+        assert_eq!(boxed.to_string(), "unexpected close delimiter at 0..1");
+    }
+
+    #[test]
+    fn test_mismatched_delimiter() {
+        let src = "(a\n  [b\n   c)\n)";
+        let mut reader = Reader::new(src);
+
+        let err = reader.read_one().unwrap_err();
+        assert_eq!(
+            err,
+            ReadError::new(
+                ReadErrorKind::MismatchedDelimiter {
+                    open: LexemeKind::LBracket,
+                    open_span: Span::new(5, 6)
+                },
+                Span::new(12, 13)
+            )
+        );
+        // only indexed once a message needs a line number
+        assert!(reader.line_index.get().is_none());
+        assert_eq!(
+            reader.error_message(&err),
+            "expected `]` to close bracket opened at line 2"
+        );
+
+        let err = reader.read_one().unwrap_err();
+        assert_eq!(err.kind, ReadErrorKind::UnexpectedClose);
+        assert_eq!(
+            reader.error_message(&err),
+            "unexpected close delimiter at line 4"
+        );
+
+        let err = read("#(a . b}").unwrap_err();
+        assert_eq!(err.kind, ReadErrorKind::UnexpectedDot);
+        let err = read("(a . b}").unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ReadErrorKind::MismatchedDelimiter {
+                open: LexemeKind::LParen,
+                ..
+            }
+        ));
+        assert_eq!(read("{a [b (c)]}").unwrap().unwrap().span, Span::new(0, 11));
+    }
+
+    #[test]
+    fn test_recovery() {
+        let src = "(a ]) b) (c (d\n(e . f g) \"x\\q\" (h";
+        let (forms, errors) = Reader::new(src).read_all_with_recovery();
+
+        let kinds = errors.iter().map(|err| err.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ReadErrorKind::MismatchedDelimiter {
+                    open: LexemeKind::LParen,
+                    open_span: Span::new(0, 1)
+                },
+                ReadErrorKind::UnexpectedClose,
+                ReadErrorKind::UnexpectedDot,
+                ReadErrorKind::InvalidEscape,
+                ReadErrorKind::UnterminatedList,
+                ReadErrorKind::UnterminatedList,
+                ReadErrorKind::UnterminatedList,
+            ]
+        );
+
+        assert_eq!(
+            forms[0],
+            Stx::new(
+                StxKind::List(vec![
+                    symbol("a", 1),
+                    Stx::new(StxKind::Error, Span::new(3, 4))
+                ]),
+                Span::new(0, 5)
+            )
+            .with_delims(Span::new(0, 1), Span::new(4, 5))
+        );
+        assert_eq!(forms[1], symbol("b", 6));
+        assert_eq!(forms[2], Stx::new(StxKind::Error, Span::new(7, 8)));
+        assert_eq!(forms.len(), 4);
+        assert_eq!(forms[3].span, Span::new(9, src.len() as u32));
+        let eof = Span::new(src.len() as u32, src.len() as u32);
+        assert_eq!(forms[3].delims.unwrap().close, eof);
+
+        match &forms[3].kind {
+            StxKind::List(items) => {
+                assert_eq!(items[0], symbol("c", 10));
+                match &items[1].kind {
+                    StxKind::List(inner) => {
+                        assert_eq!(inner.len(), 4);
+                        assert_eq!(
+                            inner[1].kind,
+                            StxKind::List(vec![symbol("e", 16), symbol("f", 20), symbol("g", 22)])
+                        );
+                        assert_eq!(inner[2].kind, StxKind::Error);
+                    }
+                    kind => panic!("{:?}", kind),
+                }
+            }
+            kind => panic!("{:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_recovery_closes_enclosing_list() {
+        let (forms, errors) = Reader::new("[(a] b").read_all_with_recovery();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            forms,
+            vec![
+                Stx::new(
+                    StxKind::List(vec![Stx::new(
+                        StxKind::List(vec![symbol("a", 2)]),
+                        Span::new(1, 3)
+                    )
+                    .with_delims(Span::new(1, 2), Span::new(3, 3))
+                    .mark_incomplete()]),
+                    Span::new(0, 4)
+                )
+                .with_delims(Span::new(0, 1), Span::new(3, 4)),
+                symbol("b", 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recovery_incomplete() {
+        let (forms, errors) = Reader::new("(a \"b\\tc").read_all_with_recovery();
+
+        assert_eq!(
+            errors,
+            vec![
+                ReadError::new(ReadErrorKind::UnterminatedString, Span::new(3, 8)),
+                ReadError::new(ReadErrorKind::UnterminatedList, Span::new(0, 1)),
+            ]
+        );
+        assert_eq!(
+            forms,
+            vec![Stx::new(
+                StxKind::List(vec![
+                    symbol("a", 1),
+                    Stx::new(StxKind::String(Cow::Borrowed("b\tc")), Span::new(3, 8))
+                        .mark_incomplete(),
+                ]),
+                Span::new(0, 8)
+            )
+            .with_delims(Span::new(0, 1), Span::new(8, 8))
+            .mark_incomplete()]
+        );
+
+        let (forms, errors) = Reader::new("#<<EOF\nab").read_all_with_recovery();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            forms,
+            vec![Stx::new(StxKind::String(Cow::Borrowed("ab")), Span::new(0, 9)).mark_incomplete()]
+        );
+
+        let (forms, _) = Reader::new("(a) \"b\"").read_all_with_recovery();
+        assert!(forms.iter().all(|stx| !stx.incomplete));
+        assert_eq!(
+            read("\"a\\q"),
+            Err(ReadError::new(
+                ReadErrorKind::UnterminatedString,
+                Span::new(0, 4)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let deep = "(".repeat(100_000) + &")".repeat(100_000);
+
+        let err = Reader::new(&deep).read_one().unwrap_err();
+        assert_eq!(
+            err,
+            ReadError::new(
+                ReadErrorKind::TooDeep,
+                Span::new(DEFAULT_MAX_DEPTH as u32, deep.len() as u32 - 256)
+            )
+        );
+
+        let err = Reader::new("([\")\" (b)]) c")
+            .with_max_depth(1)
+            .read_one()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ReadError::new(ReadErrorKind::TooDeep, Span::new(1, 10))
+        );
+
+        let (forms, errors) = Reader::new("(((a)))")
+            .with_max_depth(2)
+            .read_all_with_recovery();
+        assert_eq!(
+            errors[0],
+            ReadError::new(ReadErrorKind::TooDeep, Span::new(2, 5))
+        );
+        assert_eq!(forms.len(), 1);
+        assert!(Reader::new("(((a)))").with_max_depth(3).read_one().is_ok());
+    }
+
+    #[test]
+    fn test_limits() {
+        let read_with = |src, options| Reader::new(src).with_options(options).read_all();
+        let exceeded = |limit, start, end| {
+            Err(ReadError::new(
+                ReadErrorKind::LimitExceeded(limit),
+                Span::new(start, end),
+            ))
+        };
+
+        let options = ReaderOptions::new().max_datums(2);
+        assert_eq!(read_with("a b c", options), exceeded(Limit::Datums, 4, 5));
+        assert_eq!(read_with("a b", options).unwrap().len(), 2);
+        assert_eq!(read_with("(a)", options).unwrap().len(), 1);
+
+        // lists count at any depth, even empty ones
+        let options = options.max_total_atoms(10).maps(true).sets(true);
+        let lists = format!("({})", "() ".repeat(1_000_000));
+        assert_eq!(read_with(&lists, options), exceeded(Limit::Datums, 4, 5));
+        assert_eq!(
+            read_with("#(#{} {})", options),
+            exceeded(Limit::Datums, 6, 7)
+        );
+
+        let options = ReaderOptions::new().max_total_atoms(3);
+        assert_eq!(
+            read_with("(a (b #u8(1)) c)", options),
+            exceeded(Limit::TotalAtoms, 14, 15)
+        );
+        assert_eq!(read_with("#;a b c", options).unwrap().len(), 2);
+        assert_eq!(
+            read_with("#;a #;b c d", options),
+            exceeded(Limit::TotalAtoms, 10, 11)
+        );
+
+        let options = ReaderOptions::new().max_string_len(3);
+        assert_eq!(
+            read_with("\"abcd\"", options),
+            exceeded(Limit::StringLen, 0, 6)
+        );
+        assert!(read_with("\"a\\nb\" #<<E\nab\nE", options).is_ok());
+
+        let options = ReaderOptions::new().max_depth(1);
+        assert_eq!(
+            read_with("((a))", options).unwrap_err().kind,
+            ReadErrorKind::TooDeep
+        );
+
+        // recovering stops at a limit instead of reading on
+        let options = ReaderOptions::new().max_total_atoms(1);
+        let (forms, errors) = Reader::new("(a b (c)) d")
+            .with_options(options)
+            .read_all_with_recovery();
+        assert_eq!(
+            errors,
+            vec![ReadError::new(
+                ReadErrorKind::LimitExceeded(Limit::TotalAtoms),
+                Span::new(3, 4)
+            )]
+        );
+        assert_eq!(forms, vec![Stx::new(StxKind::Error, Span::new(3, 4))]);
+    }
+
+    #[test]
+    fn test_long_datum_comment_run() {
+        let src = "#;".repeat(100_000) + &"a ".repeat(100_000) + "b";
+
+        assert_eq!(
+            read(&src).unwrap().unwrap().kind,
+            StxKind::Symbol(Cow::Borrowed("b"))
+        );
+    }
+
+    #[test]
+    fn test_quote_sugar() {
+        let quoted = |name, start, sugar_end, end, datum| {
+            let sugar = Stx::new(
+                StxKind::Symbol(Cow::Borrowed(name)),
+                Span::new(start, sugar_end),
+            );
+            Stx::new(StxKind::List(vec![sugar, datum]), Span::new(start, end))
+        };
+
+        assert_eq!(
+            read("'a").unwrap().unwrap(),
+            quoted("quote", 0, 1, 2, symbol("a", 1))
+        );
+        assert_eq!(
+            read("`(,a ,@b)").unwrap().unwrap(),
+            quoted(
+                "quasiquote",
+                0,
+                1,
+                9,
+                Stx::new(
+                    StxKind::List(vec![
+                        quoted("unquote", 2, 3, 4, symbol("a", 3)),
+                        quoted("unquote-splicing", 5, 7, 8, symbol("b", 7)),
+                    ]),
+                    Span::new(1, 9),
+                )
+                .with_delims(Span::new(1, 2), Span::new(8, 9)),
+            )
+        );
+        assert_eq!(
+            read("' #;x ' y").unwrap().unwrap(),
+            quoted("quote", 0, 1, 9, quoted("quote", 6, 7, 9, symbol("y", 8)))
+        );
+
+        assert_eq!(
+            read("(a ')").unwrap_err(),
+            ReadError::new(ReadErrorKind::MissingDatum, Span::new(3, 4))
+        );
+        assert_eq!(read("'").unwrap_err().kind, ReadErrorKind::MissingDatum);
+    }
+
+    #[test]
+    fn test_quote_depth() {
+        let src = "'".repeat(100_000) + "a";
+        assert_eq!(
+            read(&src).unwrap_err(),
+            ReadError::new(ReadErrorKind::TooDeep, Span::new(0, 100_001))
+        );
+
+        let mut reader = Reader::new("''(a) ('b)").with_max_depth(2);
+        assert_eq!(reader.read_one().unwrap_err().kind, ReadErrorKind::TooDeep);
+        assert!(reader.read_one().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_read_strings() {
+        let string = |src| match read(src).unwrap().unwrap().kind {
+            StxKind::String(value) => value,
+            kind => panic!("{:?}", kind),
+        };
+
+        assert!(matches!(
+            string("\"plain text\""),
+            Cow::Borrowed("plain text")
+        ));
+        assert!(matches!(
+            string("#<<EOF\na\\n\nEOF"),
+            Cow::Borrowed("a\\n\n")
+        ));
+
+        let decoded = string(r#""tab\t quote\" back\\ bar\| \x41;\u{1F600}\a\b\0\r\n""#);
+        assert!(matches!(decoded, Cow::Owned(_)));
+        assert_eq!(
+            decoded,
+            "tab\t quote\" back\\ bar| A\u{1F600}\x07\x08\0\r\n"
+        );
+
+        assert_eq!(string("\"a \\\n    b\""), "a b");
+        assert_eq!(string("\"\\tA\nB\r\nC\""), "\tA\nB\r\nC");
+        assert_eq!(string("\"\\x3bb;!\""), "\u{3bb}!");
+
+        assert_eq!(
+            read("\"a\\xD800;b\"").unwrap_err(),
+            ReadError::new(ReadErrorKind::InvalidEscape, Span::new(2, 9))
+        );
+        assert_eq!(
+            read("\"\\u{110000}\"").unwrap_err(),
+            ReadError::new(ReadErrorKind::InvalidEscape, Span::new(1, 11))
+        );
+    }
+
+    #[test]
+    fn test_read_chars() {
+        let chars: Vec<_> = Reader::new("#\\a #\\( #\\  #\\λ #\\space #\\nul #\\x41 #\\x #\\x3bb")
+            .map(|stx| stx.unwrap().kind)
+            .collect();
+
+        assert_eq!(
+            chars,
+            vec![
+                StxKind::Char('a'),
+                StxKind::Char('('),
+                StxKind::Char(' '),
+                StxKind::Char('λ'),
+                StxKind::Char(' '),
+                StxKind::Char('\0'),
+                StxKind::Char('A'),
+                StxKind::Char('x'),
+                StxKind::Char('\u{3bb}'),
+            ]
+        );
+
+        let error = |src| read(src).unwrap_err();
+        assert_eq!(
+            error("(#\\spcae)"),
+            ReadError::new(
+                ReadErrorKind::UnknownCharName {
+                    suggestion: Some("space")
+                },
+                Span::new(1, 8)
+            )
+        );
+        assert_eq!(
+            error("#\\Newline").kind,
+            ReadErrorKind::UnknownCharName {
+                suggestion: Some("newline")
+            }
+        );
+        assert_eq!(error("#\\xD800").kind, ReadErrorKind::InvalidChar);
+        assert_eq!(error("#\\").kind, ReadErrorKind::InvalidChar);
+
+        let mut reader = Reader::new("\n#\\tba");
+        let err = reader.read_one().unwrap_err();
+        assert_eq!(
+            reader.error_message(&err),
+            "unknown character name, did you mean `#\\tab`? at line 2"
+        );
+    }
+
+    #[test]
+    fn test_read_numbers() {
+        let number = |src| match read(src).unwrap().unwrap().kind {
+            StxKind::Number(value) => value,
+            kind => panic!("{:?}", kind),
+        };
+
+        assert_eq!(number("#x-1f"), Number::Int(-31));
+        assert_eq!(number("#e1.25"), number::parse("5/4").unwrap());
+        assert_eq!(number("#i#b101"), Number::Float(5.0));
+        match number("123456789012345678901234567890") {
+            Number::BigInt(value) => {
+                assert_eq!(value.to_string(), "123456789012345678901234567890")
+            }
+            value => panic!("{:?}", value),
         }
+
+        assert_eq!(
+            read("(a 1/0)").unwrap_err(),
+            ReadError::new(ReadErrorKind::BadNumber, Span::new(3, 6))
+        );
+        assert_eq!(read("#e#e1").unwrap_err().kind, ReadErrorKind::BadNumber);
     }
 
-    pub fn read_one() -> Option<Stx>
-    {
-        None
+    #[test]
+    fn test_bytevector() {
+        assert_eq!(
+            read("#u8(0 255 7 #xff #b10)").unwrap().unwrap(),
+            Stx::new(
+                StxKind::Bytevector(vec![0, 255, 7, 255, 2]),
+                Span::new(0, 22)
+            )
+            .with_delims(Span::new(0, 4), Span::new(21, 22))
+        );
+        assert_eq!(
+            read("#u8()").unwrap().unwrap().kind,
+            StxKind::Bytevector(vec![])
+        );
+
+        let error = |src| read(src).unwrap_err();
+        assert_eq!(
+            error("#u8(1 256)"),
+            ReadError::new(ReadErrorKind::InvalidByte, Span::new(6, 9))
+        );
+        assert_eq!(
+            error("#u8(#i1)"),
+            ReadError::new(ReadErrorKind::InvalidByte, Span::new(4, 7))
+        );
+        assert_eq!(
+            error("#u8(-1)"),
+            ReadError::new(ReadErrorKind::InvalidByte, Span::new(4, 6))
+        );
+        assert_eq!(
+            error("#u8(1 (2))"),
+            ReadError::new(ReadErrorKind::InvalidByte, Span::new(6, 9))
+        );
+        assert_eq!(
+            error("#u8(a"),
+            ReadError::new(ReadErrorKind::InvalidByte, Span::new(4, 5))
+        );
+        assert_eq!(error("#u8(1").kind, ReadErrorKind::UnterminatedList);
+        assert_eq!(
+            error("#u8(1]").kind,
+            ReadErrorKind::MismatchedDelimiter {
+                open: LexemeKind::BytevectorOpen,
+                open_span: Span::new(0, 4)
+            }
+        );
+
+        let (forms, errors) = Reader::new("#u8(1 300 x 2) 3").read_all_with_recovery();
+        assert_eq!(
+            forms,
+            vec![
+                Stx::new(StxKind::Bytevector(vec![1, 2]), Span::new(0, 14))
+                    .with_delims(Span::new(0, 4), Span::new(13, 14)),
+                Stx::new(StxKind::Number(Number::Int(3)), Span::new(15, 16)),
+            ]
+        );
+        assert_eq!(
+            errors,
+            vec![
+                ReadError::new(ReadErrorKind::InvalidByte, Span::new(6, 9)),
+                ReadError::new(ReadErrorKind::InvalidByte, Span::new(10, 11)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_datum_labels() {
+        let stx = read("(#0=(a) #0#)").unwrap().unwrap();
+        let items = match stx.kind {
+            StxKind::List(items) => items,
+            kind => panic!("{:?}", kind),
+        };
+
+        let shared = match &items[0].kind {
+            StxKind::Labeled(0, shared) => Rc::clone(shared),
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(items[0].span, Span::new(1, 7));
+        assert_eq!(
+            *shared.borrow(),
+            Stx::new(StxKind::List(vec![symbol("a", 5)]), Span::new(4, 7))
+                .with_delims(Span::new(4, 5), Span::new(6, 7))
+        );
+        match &items[1].kind {
+            StxKind::LabelRef(label_ref) => {
+                assert_eq!(label_ref.label, 0);
+                assert!(Rc::ptr_eq(&label_ref.get().unwrap(), &shared));
+            }
+            kind => panic!("{:?}", kind),
+        }
+
+        // the tail of the list is the list itself
+        let stx = read("#1=(a . #1#)").unwrap().unwrap();
+        if let StxKind::Labeled(_, shared) = &stx.kind {
+            match &shared.borrow().kind {
+                StxKind::DottedList(_, tail) => match &tail.kind {
+                    StxKind::LabelRef(label_ref) => {
+                        assert!(Rc::ptr_eq(&label_ref.get().unwrap(), shared))
+                    }
+                    kind => panic!("{:?}", kind),
+                },
+                kind => panic!("{:?}", kind),
+            }
+        } else {
+            panic!("{:?}", stx);
+        }
+    }
+
+    #[test]
+    fn test_datum_label_errors() {
+        let error = |src| read(src).unwrap_err();
+
+        assert_eq!(
+            error("(#0# #0=a)"),
+            ReadError::new(ReadErrorKind::UndefinedLabel, Span::new(1, 4))
+        );
+        assert_eq!(
+            error("(#0=a #0=b)"),
+            ReadError::new(ReadErrorKind::DuplicateLabel, Span::new(6, 9))
+        );
+        assert_eq!(error("(#0=)").kind, ReadErrorKind::MissingDatum);
+        assert_eq!(error("#99999999999=a").kind, ReadErrorKind::BadNumber);
+
+        // labels are scoped to the datum they appear in
+        let mut reader = Reader::new("#0=a #0#");
+        assert!(reader.read_one().is_ok());
+        assert_eq!(
+            reader.read_one().unwrap_err().kind,
+            ReadErrorKind::UndefinedLabel
+        );
+
+        let strict = |src| Reader::new(src).with_strict_labels(true).read_one();
+        assert_eq!(
+            strict("#0=(a #0#)").unwrap_err(),
+            ReadError::new(ReadErrorKind::CyclicLabel, Span::new(6, 9))
+        );
+        assert!(strict("(#1=(b) #1#)").is_ok());
+
+        let mut reader = Reader::new("(#0=a #1=b #2=c)").with_max_labels(2);
+        assert_eq!(
+            reader.read_one().unwrap_err(),
+            ReadError::new(ReadErrorKind::TooManyLabels, Span::new(11, 14))
+        );
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let read = |src| Reader::from_slice(src).read_all().unwrap_err().kind;
+
+        assert_eq!(
+            read(b"|a\xff|"),
+            ReadErrorKind::Lex(ScanErrorKind::InvalidBytes)
+        );
+        assert_eq!(
+            read(b"\xff"),
+            ReadErrorKind::Lex(ScanErrorKind::InvalidBytes)
+        );
+    }
+
+    #[test]
+    fn test_read_interactive() {
+        let outcome = |src| Reader::new(src).read_interactive();
+        let need_more = |open_delims: &[LexemeKind], mode| {
+            Ok(ReadOutcome::NeedMoreInput {
+                open_delims: open_delims.to_vec(),
+                in_string: mode != ScannerMode::Regular,
+                mode,
+            })
+        };
+
+        assert_eq!(
+            outcome("(a)"),
+            Ok(ReadOutcome::Datum(
+                Stx::new(StxKind::List(vec![symbol("a", 1)]), Span::new(0, 3))
+                    .with_delims(Span::new(0, 1), Span::new(2, 3))
+            ))
+        );
+        assert_eq!(outcome(" ; a\n"), Ok(ReadOutcome::Empty));
+        assert_eq!(
+            outcome("(a [b"),
+            need_more(
+                &[LexemeKind::LParen, LexemeKind::LBracket],
+                ScannerMode::Regular
+            )
+        );
+        assert_eq!(
+            outcome("(a \"b\n"),
+            need_more(&[LexemeKind::LParen], ScannerMode::String)
+        );
+        assert_eq!(outcome("#<<EOF\nab"), need_more(&[], ScannerMode::Heredoc));
+        assert_eq!(outcome("#| a"), need_more(&[], ScannerMode::Regular));
+        assert_eq!(
+            outcome("(a #;"),
+            need_more(&[LexemeKind::LParen], ScannerMode::Regular)
+        );
+        assert_eq!(
+            outcome("(a . "),
+            need_more(&[LexemeKind::LParen], ScannerMode::Regular)
+        );
+
+        assert_eq!(
+            outcome("(a #;)").unwrap_err().kind,
+            ReadErrorKind::MissingDatum
+        );
+        assert_eq!(outcome("(a]").unwrap_err().span, Span::new(2, 3));
+        assert_eq!(
+            outcome("(#q").unwrap_err().kind.to_string(),
+            "unknown character after `#`"
+        );
     }
 }
@@ -0,0 +1,662 @@
+//! A lossless concrete syntax tree that keeps every lexeme, trivia
+//! included, so the source can be reproduced exactly.
+//!
+//! Green nodes are immutable, position independent and shared through `Rc`.
+//! `SyntaxNode` and `SyntaxToken` are cheap views that add absolute offsets
+//! and parent links on top of them.
+
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::scanner::{LexemeKind, Scanner, ScannerOptions};
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CstKind {
+    /// The whole source.
+    Root,
+    /// `(...)`, `[...]` or `{...}`.
+    List,
+    Vector,
+    Bytevector,
+    Set,
+    String,
+    Heredoc,
+    /// `'`, `` ` ``, `,` or `,@` and the datum after it.
+    Quoted,
+    /// `#;` and the datum it comments out.
+    DatumComment,
+    /// A close delimiter that closes nothing.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreenToken {
+    kind: LexemeKind,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: LexemeKind, text: &str) -> Self {
+        GreenToken {
+            kind,
+            text: String::from(text),
+        }
+    }
+
+    pub fn kind(&self) -> LexemeKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn width(&self) -> u32 {
+        self.text.len() as u32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(GreenToken),
+}
+
+impl GreenElement {
+    pub fn width(&self) -> u32 {
+        match self {
+            GreenElement::Node(node) => node.width(),
+            GreenElement::Token(token) => token.width(),
+        }
+    }
+
+    fn write_text(&self, out: &mut String) {
+        match self {
+            GreenElement::Node(node) => node.write_text(out),
+            GreenElement::Token(token) => out.push_str(&token.text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreenNode {
+    kind: CstKind,
+    width: u32,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: CstKind, children: Vec<GreenElement>) -> Self {
+        let width = children.iter().map(GreenElement::width).sum();
+
+        GreenNode {
+            kind,
+            width,
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> CstKind {
+        self.kind
+    }
+
+    /// Length of the source text below this node in bytes.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    fn write_text(&self, out: &mut String) {
+        let mut stack = vec![self.children.iter()];
+
+        while let Some(children) = stack.last_mut() {
+            match children.next() {
+                Some(GreenElement::Node(node)) => stack.push(node.children.iter()),
+                Some(GreenElement::Token(token)) => out.push_str(&token.text),
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Frees nested nodes one at a time rather than recursively, so dropping a
+/// very deep tree can't overflow the stack.
+impl Drop for GreenNode {
+    fn drop(&mut self) {
+        let mut children = mem::take(&mut self.children);
+
+        while let Some(child) = children.pop() {
+            if let GreenElement::Node(node) = child {
+                if let Ok(mut node) = Rc::try_unwrap(node) {
+                    children.append(&mut node.children);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NodeData {
+    green: Rc<GreenNode>,
+    offset: u32,
+    parent: Option<SyntaxNode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> Self {
+        SyntaxNode(Rc::new(NodeData {
+            green,
+            offset: 0,
+            parent: None,
+        }))
+    }
+
+    pub fn kind(&self) -> CstKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.0.offset, self.0.offset + self.0.green.width)
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.0.green.write_text(&mut text);
+        text
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        let mut offset = self.0.offset;
+
+        self.0.green.children.iter().map(move |child| {
+            let start = offset;
+            offset += child.width();
+
+            match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    offset: start,
+                    parent: Some(self.clone()),
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: green.clone(),
+                    offset: start,
+                    parent: self.clone(),
+                }),
+            }
+        })
+    }
+
+    pub fn child_nodes(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        self.children().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green: GreenToken,
+    offset: u32,
+    parent: SyntaxNode,
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> LexemeKind {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.green.width())
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxElement {
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxElement::Node(node) => node.span(),
+            SyntaxElement::Token(token) => token.span(),
+        }
+    }
+}
+
+pub fn parse(src: &str) -> SyntaxNode {
+    parse_with_options(src, ScannerOptions::new())
+}
+
+/// Builds the tree with an explicit stack, so nesting depth is bounded only
+/// by memory. Unclosed nodes are closed at the end of the input.
+pub fn parse_with_options(src: &str, options: ScannerOptions) -> SyntaxNode {
+    let (root, _) = build(src, options);
+    SyntaxNode::new_root(Rc::new(GreenNode::new(CstKind::Root, root)))
+}
+
+/// Replaces the text at `span` with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(span: Span, text: impl Into<String>) -> Self {
+        TextEdit {
+            span,
+            text: text.into(),
+        }
+    }
+}
+
+/// Parses the text `edit` makes of the text of `old`, a root returned by
+/// `parse`. Only the top-level elements from the whitespace before the edit
+/// to the whitespace after it are read again, the others are shared with
+/// `old`. Where the edit reaches further, like an opened string or list, the
+/// whole text is read again.
+///
+/// Panics if `edit.span` is out of bounds of the old text or not on
+/// character boundaries.
+pub fn reparse(old: &SyntaxNode, edit: &TextEdit) -> SyntaxNode {
+    reparse_with_options(old, edit, ScannerOptions::new())
+}
+
+/// The same as `reparse`, for a root returned by `parse_with_options` with
+/// `options`.
+pub fn reparse_with_options(
+    old: &SyntaxNode,
+    edit: &TextEdit,
+    options: ScannerOptions,
+) -> SyntaxNode {
+    let children = old.green().children();
+    let mut spans = Vec::with_capacity(children.len());
+    let mut offset = 0;
+    for child in children {
+        spans.push(Span::new(offset, offset + child.width()));
+        offset += child.width();
+    }
+
+    // the elements the edit touches, then up to whitespace on both sides
+    let Span { start, end } = edit.span;
+    let mut lo = spans
+        .iter()
+        .position(|span| span.end >= start)
+        .unwrap_or(children.len());
+    let mut hi = spans
+        .iter()
+        .rposition(|span| span.start <= end)
+        .map_or(lo, |i| i + 1)
+        .max(lo);
+    while lo > 0 && !is_boundary(&children[lo], &children[lo - 1]) {
+        lo -= 1;
+    }
+    while hi < children.len() && !is_boundary(&children[hi - 1], &children[hi]) {
+        hi += 1;
+    }
+
+    let region_start = spans.get(lo).map_or(0, |span| span.start);
+    let mut text = String::new();
+    for child in &children[lo..hi] {
+        child.write_text(&mut text);
+    }
+    let range = (start - region_start) as usize..(end - region_start) as usize;
+    text.replace_range(range, &edit.text);
+
+    let (elements, separate) = build(&text, options);
+    if !separate {
+        let mut text = old.text();
+        text.replace_range(edit.span.range(), &edit.text);
+        return parse_with_options(&text, options);
+    }
+
+    let mut root = Vec::with_capacity(children.len() - (hi - lo) + elements.len());
+    root.extend_from_slice(&children[..lo]);
+    root.extend(elements);
+    root.extend_from_slice(&children[hi..]);
+    SyntaxNode::new_root(Rc::new(GreenNode::new(CstKind::Root, root)))
+}
+
+/// Whether text read again can end with `inner` where `outer` follows it,
+/// or start with it where `outer` comes before it.
+fn is_boundary(inner: &GreenElement, outer: &GreenElement) -> bool {
+    is_space(outer) && !is_trivia(inner)
+}
+
+fn is_space(element: &GreenElement) -> bool {
+    matches!(element, GreenElement::Token(token) if is_space_kind(token.kind))
+}
+
+fn is_trivia(element: &GreenElement) -> bool {
+    matches!(element, GreenElement::Token(token) if token.kind.is_trivia())
+}
+
+fn is_space_kind(kind: LexemeKind) -> bool {
+    matches!(
+        kind,
+        LexemeKind::Whitespace
+            | LexemeKind::Tab
+            | LexemeKind::NewlineLf
+            | LexemeKind::NewlineCr
+            | LexemeKind::NewlineCrlf
+    )
+}
+
+/// The top-level elements of `src`, and whether it ends between datums with
+/// no lexeme at either end that text around it could join up with.
+fn build(src: &str, options: ScannerOptions) -> (Vec<GreenElement>, bool) {
+    let mut builder = Builder {
+        stack: Vec::new(),
+        root: Vec::new(),
+    };
+    let mut first = None;
+    let mut last = None;
+
+    for lex in Scanner::new(src).with_options(options) {
+        let token = GreenToken::new(lex.kind, lex.slice);
+        first.get_or_insert(lex.kind);
+        last = Some(lex.kind);
+
+        match lex.kind {
+            _ if builder.in_text() => {
+                let closes = matches!(
+                    (builder.top_kind(), lex.kind),
+                    (Some(CstKind::String), LexemeKind::RString)
+                        | (Some(CstKind::Heredoc), LexemeKind::HeredocEnd)
+                );
+                builder.token(token);
+
+                let heredoc_failed =
+                    lex.kind.is_error() && builder.top_kind() == Some(CstKind::Heredoc);
+
+                if closes || heredoc_failed {
+                    builder.finish();
+                    builder.finish_prefixes();
+                }
+            }
+            LexemeKind::LParen | LexemeKind::LBracket | LexemeKind::LBrace => {
+                builder.start(CstKind::List, token)
+            }
+            LexemeKind::VectorOpen => builder.start(CstKind::Vector, token),
+            LexemeKind::BytevectorOpen => builder.start(CstKind::Bytevector, token),
+            LexemeKind::SetOpen => builder.start(CstKind::Set, token),
+            LexemeKind::LString => builder.start(CstKind::String, token),
+            LexemeKind::HeredocStart => builder.start(CstKind::Heredoc, token),
+            LexemeKind::Quote
+            | LexemeKind::Quasiquote
+            | LexemeKind::Unquote
+            | LexemeKind::UnquoteSplicing => builder.start(CstKind::Quoted, token),
+            LexemeKind::DatumComment => builder.start(CstKind::DatumComment, token),
+            kind if kind.is_close_delimiter() => builder.close(token),
+            kind if kind.is_trivia() => builder.token(token),
+            _ => {
+                builder.token(token);
+                builder.finish_prefixes();
+            }
+        }
+    }
+
+    let separate = builder.stack.is_empty()
+        && matches!(first, Some(kind) if !is_space_kind(kind))
+        && matches!(last, Some(kind) if !kind.is_trivia() && !kind.is_error());
+
+    while !builder.stack.is_empty() {
+        builder.finish();
+    }
+
+    (builder.root, separate)
+}
+
+struct Builder {
+    /// Unfinished nodes, each with its open lexeme kind and children.
+    stack: Vec<(CstKind, LexemeKind, Vec<GreenElement>)>,
+    root: Vec<GreenElement>,
+}
+
+impl Builder {
+    fn top_kind(&self) -> Option<CstKind> {
+        self.stack.last().map(|(kind, _, _)| *kind)
+    }
+
+    fn in_text(&self) -> bool {
+        matches!(
+            self.top_kind(),
+            Some(CstKind::String) | Some(CstKind::Heredoc)
+        )
+    }
+
+    fn children(&mut self) -> &mut Vec<GreenElement> {
+        match self.stack.last_mut() {
+            Some((_, _, children)) => children,
+            None => &mut self.root,
+        }
+    }
+
+    fn start(&mut self, kind: CstKind, open: GreenToken) {
+        self.stack
+            .push((kind, open.kind, vec![GreenElement::Token(open)]));
+    }
+
+    fn token(&mut self, token: GreenToken) {
+        self.children().push(GreenElement::Token(token));
+    }
+
+    fn finish(&mut self) {
+        if let Some((kind, _, children)) = self.stack.pop() {
+            let node = GreenNode::new(kind, children);
+            self.children().push(GreenElement::Node(Rc::new(node)));
+        }
+    }
+
+    /// Closes the prefixes waiting on the datum that was just completed.
+    fn finish_prefixes(&mut self) {
+        while let Some(CstKind::Quoted) | Some(CstKind::DatumComment) = self.top_kind() {
+            self.finish();
+        }
+    }
+
+    fn close(&mut self, token: GreenToken) {
+        let matches = |open: LexemeKind| open.matching_close() == Some(token.kind);
+
+        if !self.stack.iter().any(|(_, open, _)| matches(*open)) {
+            let node = GreenNode::new(CstKind::Error, vec![GreenElement::Token(token)]);
+            self.children().push(GreenElement::Node(Rc::new(node)));
+            return;
+        }
+
+        while let Some((_, open, _)) = self.stack.last() {
+            if matches(*open) {
+                break;
+            }
+            self.finish();
+        }
+
+        self.token(token);
+        self.finish();
+        self.finish_prefixes();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn shape(node: &SyntaxNode) -> String {
+        let mut out = alloc::format!("{:?}[", node.kind());
+
+        for child in node.children() {
+            match child {
+                SyntaxElement::Node(node) => out.push_str(&shape(&node)),
+                SyntaxElement::Token(token) if token.kind().is_trivia() => continue,
+                SyntaxElement::Token(token) => out.push_str(token.text()),
+            }
+            out.push(' ');
+        }
+
+        out.push(']');
+        out
+    }
+
+    #[test]
+    fn test_lossless() {
+        let sources = [
+            "(define (f x) ; comment\n  (+ x 1))\n",
+            "#(1 2) #u8(3) '(a . b) `(c ,d ,@e) #;(skip me) \"str\\n\"",
+            "(unclosed [mismatch) ) \"unterminated",
+            "#<<EOF\nbody\nEOF\n#| block |# ' ",
+        ];
+
+        for src in &sources {
+            let root = parse(src);
+            assert_eq!(root.text(), *src);
+            assert_eq!(root.span(), Span::new(0, src.len() as u32));
+        }
+    }
+
+    #[test]
+    fn test_structure() {
+        let root = parse("(a 'b #;c) ) #(1)");
+
+        assert_eq!(
+            shape(&root),
+            "Root[List[( a Quoted[' b ] DatumComment[#; c ] ) ] Error[) ] Vector[#( 1 ) ] ]"
+        );
+
+        let list = root.child_nodes().next().unwrap();
+        assert_eq!(list.span(), Span::new(0, 10));
+        let quoted = list.child_nodes().next().unwrap();
+        assert_eq!(quoted.span(), Span::new(3, 5));
+        assert_eq!(quoted.parent().unwrap().span(), list.span());
+
+        let tokens = quoted
+            .children()
+            .filter_map(|child| match child {
+                SyntaxElement::Token(token) => Some((token.kind(), token.span())),
+                SyntaxElement::Node(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (LexemeKind::Quote, Span::new(3, 4)),
+                (LexemeKind::Identifier, Span::new(4, 5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced() {
+        let root = parse("([a) \"s");
+
+        assert_eq!(shape(&root), "Root[List[( List[[ a ] ) ] String[\" s ] ]");
+        assert_eq!(root.text(), "([a) \"s");
+    }
+
+    #[test]
+    fn test_reparse() {
+        let src = "(a b)\n(c d)\n(e f)\n";
+        let old = parse(src);
+        let new = reparse(&old, &TextEdit::new(Span::new(9, 10), "x y"));
+
+        assert_eq!(new.text(), "(a b)\n(c x y)\n(e f)\n");
+        assert_eq!(new.green(), parse(&new.text()).green());
+
+        let nodes = |root: &SyntaxNode| {
+            root.child_nodes()
+                .map(|node| node.green().clone())
+                .collect::<Vec<_>>()
+        };
+        let (old_nodes, new_nodes) = (nodes(&old), nodes(&new));
+        assert!(Rc::ptr_eq(&old_nodes[0], &new_nodes[0]));
+        assert!(!Rc::ptr_eq(&old_nodes[1], &new_nodes[1]));
+        assert!(Rc::ptr_eq(&old_nodes[2], &new_nodes[2]));
+    }
+
+    #[test]
+    fn test_reparse_matches_parse() {
+        let src = "(a \"s\" #;b)\r\n'c ;d\n#|e|# [f] #\\g";
+        let inserts = [
+            "", "(", ")", "\"", ";", " ", "\n", "\r", "x", "#|", "|#", "#;", "#\\",
+        ];
+        let old = parse(src);
+
+        for start in 0..=src.len() {
+            for end in start..=(start + 2).min(src.len()) {
+                for insert in &inserts {
+                    let mut text = String::from(src);
+                    text.replace_range(start..end, insert);
+                    let span = Span::new(start as u32, end as u32);
+                    let new = reparse(&old, &TextEdit::new(span, *insert));
+
+                    assert_eq!(new.green(), parse(&text).green(), "{:?}", text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reparse_with_options() {
+        let options = ScannerOptions::new()
+            .leading_colon_keywords(true)
+            .block_comments(false);
+        let src = "(a :b)\n(c)\n";
+        let old = parse_with_options(src, options);
+        let edits = [
+            TextEdit::new(Span::new(9, 9), " :d"),
+            // a list left open reads the whole text again
+            TextEdit::new(Span::new(0, 0), "("),
+            TextEdit::new(Span::new(7, 7), "#|"),
+        ];
+
+        for edit in &edits {
+            let mut text = String::from(src);
+            text.replace_range(edit.span.range(), &edit.text);
+            let new = reparse_with_options(&old, edit, options);
+
+            assert_eq!(new.text(), text);
+            assert_eq!(new.green(), parse_with_options(&text, options).green());
+            assert_ne!(new.green(), parse(&text).green());
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let src = "(".repeat(50_000) + &")".repeat(50_000);
+        let root = parse(&src);
+
+        assert_eq!(root.green().width(), src.len() as u32);
+        assert_eq!(root.text(), src);
+    }
+}
@@ -0,0 +1,465 @@
+//! A pull parser that reports the structure of the input as a flat series of
+//! events instead of building a tree, so its memory use grows only with the
+//! nesting depth.
+
+use alloc::vec::Vec;
+
+use crate::error::{ReadError, ReadErrorKind};
+use crate::reader::quote_name;
+use crate::scanner::{Lexeme, LexemeKind, Scanner, ScannerOptions};
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Delim {
+    Paren,
+    Bracket,
+    Brace,
+    Vector,
+    Bytevector,
+    Set,
+}
+
+impl Delim {
+    pub fn from_open(kind: LexemeKind) -> Option<Delim> {
+        match kind {
+            LexemeKind::LParen => Some(Delim::Paren),
+            LexemeKind::LBracket => Some(Delim::Bracket),
+            LexemeKind::LBrace => Some(Delim::Brace),
+            LexemeKind::VectorOpen => Some(Delim::Vector),
+            LexemeKind::BytevectorOpen => Some(Delim::Bytevector),
+            LexemeKind::SetOpen => Some(Delim::Set),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadEvent<'a> {
+    /// The open delimiter of a list or vector. Quote sugar opens a `Paren`
+    /// list with an empty span at its prefix, followed by an `Identifier`
+    /// atom naming it, like `quote`, that spans the prefix.
+    StartList(Delim, Span),
+    /// Any datum that isn't a list. A string or heredoc is a single
+    /// `StringLit` lexeme covering the whole literal, and a `.` inside a list
+    /// is a `Dot` atom.
+    Atom(Lexeme<'a>),
+    /// The close delimiter of the innermost list, or the end of the input
+    /// for a list that was never closed. Lists standing for quote sugar end
+    /// with an empty span where their datum ends.
+    EndList(Span),
+    Error(ReadError),
+}
+
+/// Yields the events of every datum in the input. Trivia, directives and
+/// commented out datums are skipped. Every `StartList` is matched by an
+/// `EndList`, even when the input is unbalanced, and reading goes on after
+/// an error. Datum labels are rejected with `ReadErrorKind::UnexpectedToken`
+/// like `Reader::read_with` does, since shared structure can't be told as a
+/// series of events.
+#[derive(Debug, Clone)]
+pub struct ReadEvents<'a> {
+    src: &'a str,
+    scanner: Scanner<'a>,
+    /// The lists being read, and the quote sugar waiting for its datum,
+    /// innermost last.
+    open: Vec<(LexemeKind, Span)>,
+    /// `#;` still waiting for its datum, with the depth it was found at.
+    comments: Vec<(usize, Span)>,
+    /// An event to yield before reading on.
+    pending: Option<ReadEvent<'a>>,
+}
+
+impl<'a> ReadEvents<'a> {
+    pub fn new(src: &'a str) -> Self {
+        ReadEvents::with_options(src, ScannerOptions::new())
+    }
+
+    pub fn with_options(src: &'a str, options: ScannerOptions) -> Self {
+        ReadEvents {
+            src,
+            scanner: Scanner::new(src).with_options(options),
+            open: Vec::new(),
+            comments: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// How many lists enclose the next event.
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    /// The next event, commented out ones included.
+    fn step(&mut self) -> Option<ReadEvent<'a>> {
+        while let Some(lex) = self.scanner.peek() {
+            // the close is read again once the quote sugar has ended
+            if lex.kind.is_close_delimiter() {
+                if let Some(span) = self.open_quote() {
+                    self.open.pop();
+                    let end = Span::new(lex.span.start, lex.span.start);
+                    self.pending = Some(ReadEvent::EndList(end));
+                    return Some(error(ReadErrorKind::MissingDatum, span));
+                }
+            }
+
+            self.scanner.next();
+            match lex.kind {
+                kind if kind.is_trivia() => {}
+                LexemeKind::FoldCaseDirective | LexemeKind::NoFoldCaseDirective => {}
+                LexemeKind::DatumComment => self.comments.push((self.open.len(), lex.span)),
+                _ => return Some(self.event(lex)),
+            }
+        }
+
+        let eof = Span::new(self.src.len() as u32, self.src.len() as u32);
+
+        if let Some((open, span)) = self.open.pop() {
+            self.pending = Some(ReadEvent::EndList(eof));
+            let kind = match quote_name(open) {
+                Some(_) => ReadErrorKind::MissingDatum,
+                None => ReadErrorKind::UnterminatedList,
+            };
+            return Some(error(kind, span));
+        }
+
+        let (_, span) = self.comments.pop()?;
+        self.comments.clear();
+        Some(error(ReadErrorKind::MissingDatum, span))
+    }
+
+    fn event(&mut self, lex: Lexeme<'a>) -> ReadEvent<'a> {
+        if let Some(delim) = Delim::from_open(lex.kind) {
+            self.open.push((lex.kind, lex.span));
+            return ReadEvent::StartList(delim, lex.span);
+        }
+
+        if let Some(name) = quote_name(lex.kind) {
+            self.open.push((lex.kind, lex.span));
+            self.pending = Some(ReadEvent::Atom(Lexeme {
+                kind: LexemeKind::Identifier,
+                slice: name,
+                span: lex.span,
+            }));
+            let start = Span::new(lex.span.start, lex.span.start);
+            return ReadEvent::StartList(Delim::Paren, start);
+        }
+
+        match lex.kind {
+            kind if kind.is_close_delimiter() => match self.open.last() {
+                Some(&(open, _)) if open.matching_close() == Some(kind) => {
+                    self.open.pop();
+                    ReadEvent::EndList(lex.span)
+                }
+                Some(&(open, open_span)) => ReadEvent::Error(ReadError::new(
+                    ReadErrorKind::MismatchedDelimiter { open, open_span },
+                    lex.span,
+                )),
+                None => error(ReadErrorKind::UnexpectedClose, lex.span),
+            },
+            LexemeKind::LString => self.string(lex),
+            LexemeKind::HeredocStart => self.heredoc(lex),
+            LexemeKind::Dot => match self.open.last() {
+                Some(&(LexemeKind::LParen, _))
+                | Some(&(LexemeKind::LBracket, _))
+                | Some(&(LexemeKind::LBrace, _)) => ReadEvent::Atom(lex),
+                _ => error(ReadErrorKind::UnexpectedDot, lex.span),
+            },
+            LexemeKind::BoolLit
+            | LexemeKind::IntLit
+            | LexemeKind::FloatLit
+            | LexemeKind::RationalLit
+            | LexemeKind::CharLit
+            | LexemeKind::Identifier
+            | LexemeKind::QuotedIdentifier
+            | LexemeKind::KeywordLit => ReadEvent::Atom(lex),
+            LexemeKind::Error(err) => error(err.into(), lex.span),
+            _ => error(ReadErrorKind::UnexpectedToken, lex.span),
+        }
+    }
+
+    /// Consumes the whole string even when part of it is invalid, like
+    /// `Reader` does.
+    fn string(&mut self, open: Lexeme<'a>) -> ReadEvent<'a> {
+        let mut first_error = None;
+
+        while let Some(lex) = self.scanner.next() {
+            match lex.kind {
+                LexemeKind::RString => {
+                    return match first_error {
+                        Some(err) => ReadEvent::Error(err),
+                        None => self.string_atom(open.span, lex.span),
+                    };
+                }
+                LexemeKind::Error(err) if first_error.is_none() => {
+                    first_error = Some(ReadError::new(err.into(), lex.span));
+                }
+                _ => {}
+            }
+        }
+
+        self.unterminated_string(open.span)
+    }
+
+    fn heredoc(&mut self, open: Lexeme<'a>) -> ReadEvent<'a> {
+        while let Some(lex) = self.scanner.next() {
+            match lex.kind {
+                LexemeKind::HeredocContent => {}
+                LexemeKind::HeredocEnd => return self.string_atom(open.span, lex.span),
+                LexemeKind::Error(err) => return error(err.into(), lex.span),
+                _ => break,
+            }
+        }
+
+        self.unterminated_string(open.span)
+    }
+
+    fn string_atom(&self, open: Span, close: Span) -> ReadEvent<'a> {
+        let span = Span::new(open.start, close.end);
+
+        ReadEvent::Atom(Lexeme {
+            kind: LexemeKind::StringLit,
+            slice: &self.src[span.range()],
+            span,
+        })
+    }
+
+    fn unterminated_string(&self, open: Span) -> ReadEvent<'a> {
+        let span = Span::new(open.start, self.src.len() as u32);
+        error(ReadErrorKind::UnterminatedString, span)
+    }
+
+    /// The prefix of the quote sugar waiting for the next datum, if any.
+    fn open_quote(&self) -> Option<Span> {
+        match self.open.last() {
+            Some(&(open, span)) if quote_name(open).is_some() => Some(span),
+            _ => None,
+        }
+    }
+
+    /// Ends the quote sugar waiting for the datum `event` completes, after
+    /// yielding `event`.
+    fn end_quote(&mut self, event: &ReadEvent<'a>) {
+        let end = match event {
+            ReadEvent::Atom(lex) if lex.kind == LexemeKind::Dot => return,
+            // the name of the quote itself
+            ReadEvent::Atom(lex) if self.open_quote() == Some(lex.span) => return,
+            ReadEvent::Atom(lex) => lex.span.end,
+            ReadEvent::EndList(span) => span.end,
+            _ => return,
+        };
+
+        if self.open_quote().is_some() {
+            self.open.pop();
+            self.pending = Some(ReadEvent::EndList(Span::new(end, end)));
+        }
+    }
+}
+
+impl<'a> Iterator for ReadEvents<'a> {
+    type Item = ReadEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let event = match self.pending.take() {
+                Some(event) => event,
+                None => self.step()?,
+            };
+
+            let &(depth, comment) = match self.comments.last() {
+                Some(comment) => comment,
+                None => {
+                    self.end_quote(&event);
+                    return Some(event);
+                }
+            };
+
+            match event {
+                ReadEvent::Error(_) => return Some(event),
+                // the list closed before the commented out datum
+                ReadEvent::EndList(_) if self.open.len() < depth => {
+                    let open = self.open.len();
+                    self.comments.retain(|&(depth, _)| depth <= open);
+                    self.pending = Some(event);
+                    return Some(error(ReadErrorKind::MissingDatum, comment));
+                }
+                ReadEvent::StartList(..) => {}
+                ReadEvent::Atom(_) | ReadEvent::EndList(_) => {
+                    if self.open.len() == depth {
+                        self.comments.pop();
+                    } else {
+                        self.end_quote(&event);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn error<'a>(kind: ReadErrorKind, span: Span) -> ReadEvent<'a> {
+    ReadEvent::Error(ReadError::new(kind, span))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec;
+
+    /// The events as a compact string, atoms by their text.
+    fn events(src: &str) -> String {
+        let mut out = String::new();
+
+        for event in ReadEvents::new(src) {
+            match event {
+                ReadEvent::StartList(delim, _) => out.push_str(&format!("{:?}[ ", delim)),
+                ReadEvent::Atom(lex) => {
+                    out.push_str(lex.slice);
+                    out.push(' ');
+                }
+                ReadEvent::EndList(_) => out.push_str("] "),
+                ReadEvent::Error(err) => out.push_str(&format!("<{:?}> ", err.kind)),
+            }
+        }
+
+        out
+    }
+
+    #[test]
+    fn test_events() {
+        assert_eq!(
+            events("(a [b . c] #(1 \"s t\")) {d} #u8(2) #<<EOF\nhi\nEOF"),
+            "Paren[ a Bracket[ b . c ] Vector[ 1 \"s t\" ] ] Brace[ d ] Bytevector[ 2 ] \
+             #<<EOF\nhi\nEOF "
+        );
+
+        let all: Vec<_> = ReadEvents::new(" (x) ").collect();
+        assert_eq!(
+            all,
+            vec![
+                ReadEvent::StartList(Delim::Paren, Span::new(1, 2)),
+                ReadEvent::Atom(Lexeme {
+                    kind: LexemeKind::Identifier,
+                    slice: "x",
+                    span: Span::new(2, 3),
+                }),
+                ReadEvent::EndList(Span::new(3, 4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quote_sugar() {
+        assert_eq!(
+            events("'a `(b ,c ,@d)"),
+            "Paren[ quote a ] Paren[ quasiquote Paren[ b Paren[ unquote c ] \
+             Paren[ unquote-splicing d ] ] ] "
+        );
+        assert_eq!(
+            events("''a '\"s\" (x . 'y)"),
+            "Paren[ quote Paren[ quote a ] ] Paren[ quote \"s\" ] Paren[ x . Paren[ quote y ] ] "
+        );
+        assert_eq!(events("'#;a b #;'c d"), "Paren[ quote b ] d ");
+        assert_eq!(
+            events("(a ') '"),
+            "Paren[ a Paren[ quote <MissingDatum> ] ] Paren[ quote <MissingDatum> ] "
+        );
+        assert_eq!(
+            events("(' . a)"),
+            "Paren[ Paren[ quote <UnexpectedDot> a ] ] "
+        );
+
+        let all: Vec<_> = ReadEvents::new(" 'x").collect();
+        assert_eq!(
+            all,
+            vec![
+                ReadEvent::StartList(Delim::Paren, Span::new(1, 1)),
+                ReadEvent::Atom(Lexeme {
+                    kind: LexemeKind::Identifier,
+                    slice: "quote",
+                    span: Span::new(1, 2),
+                }),
+                ReadEvent::Atom(Lexeme {
+                    kind: LexemeKind::Identifier,
+                    slice: "x",
+                    span: Span::new(2, 3),
+                }),
+                ReadEvent::EndList(Span::new(3, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            events("#0=(a #0#) b"),
+            "<UnexpectedToken> Paren[ a <UnexpectedToken> ] b "
+        );
+        assert_eq!(events("#;#0=a b"), "<UnexpectedToken> b ");
+    }
+
+    #[test]
+    fn test_datum_comments() {
+        assert_eq!(events("a #;(b (c)) d"), "a d ");
+        assert_eq!(events("(a #;#;b c d) #;\"s\" e"), "Paren[ a d ] e ");
+        assert_eq!(events("(a #;)"), "Paren[ a <MissingDatum> ] ");
+        assert_eq!(events("#;(a #;)"), "<MissingDatum> ");
+        assert_eq!(events("a #;"), "a <MissingDatum> ");
+        assert_eq!(events("#!fold-case A ; b\n"), "A ");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(events("(a"), "Paren[ a <UnterminatedList> ] ");
+        assert_eq!(events("a) b"), "a <UnexpectedClose> b ");
+        assert_eq!(
+            events(". #(.)"),
+            "<UnexpectedDot> Vector[ <UnexpectedDot> ] "
+        );
+        assert_eq!(
+            events("\"a\\qb\" c \"d"),
+            "<InvalidEscape> c <UnterminatedString> "
+        );
+
+        let all: Vec<_> = ReadEvents::new("(a]").collect();
+        assert_eq!(
+            all[2],
+            ReadEvent::Error(ReadError::new(
+                ReadErrorKind::MismatchedDelimiter {
+                    open: LexemeKind::LParen,
+                    open_span: Span::new(0, 1),
+                },
+                Span::new(2, 3),
+            ))
+        );
+        assert_eq!(
+            all[3],
+            ReadEvent::Error(ReadError::new(
+                ReadErrorKind::UnterminatedList,
+                Span::new(0, 1)
+            ))
+        );
+        assert_eq!(all[4], ReadEvent::EndList(Span::new(3, 3)));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let depth = 100_000;
+        let mut src = String::new();
+        (0..depth).for_each(|_| src.push('('));
+        (0..depth).for_each(|_| src.push(')'));
+
+        let mut events = ReadEvents::new(&src);
+        let mut max_depth = 0;
+        let mut count = 0;
+
+        while let Some(event) = events.next() {
+            assert!(!matches!(event, ReadEvent::Error(_)));
+            max_depth = max_depth.max(events.depth());
+            count += 1;
+        }
+
+        assert_eq!(max_depth, depth);
+        assert_eq!(count, 2 * depth);
+    }
+}
@@ -0,0 +1,397 @@
+//! Reading into callbacks, for building other data structures than `Stx`
+//! without the tree in between.
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+use crate::error::{ReadError, ReadErrorKind};
+use crate::number::Number;
+use crate::reader::events::Delim;
+use crate::reader::{parse_byte, quote_name, Reader};
+use crate::scanner::{Lexeme, LexemeKind};
+use crate::span::Span;
+use crate::stx::{Stx, StxKind};
+
+/// Whether reading should go on after a `ReadSink` callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+/// Receives what `Reader::read_with` reads, in source order.
+pub trait ReadSink<'a> {
+    /// The open delimiter of a list, vector, bytevector, map or set. Quote
+    /// sugar opens a `Paren` list with an empty span at its prefix.
+    fn open(&mut self, delim: Delim, span: Span) -> Flow;
+
+    /// A datum that isn't a list, decoded as `Reader::read_one` would. A
+    /// reader macro's datum arrives here whole, even if it is a list.
+    fn atom(&mut self, atom: Stx<'a>) -> Flow;
+
+    /// The `.` of a dotted list, before its tail.
+    fn dot(&mut self, span: Span) -> Flow {
+        let _ = span;
+        Flow::Continue
+    }
+
+    /// The close delimiter of the innermost list. Lists standing for quote
+    /// sugar close with an empty span where their datum ends.
+    fn close(&mut self, span: Span) -> Flow;
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Frame<'a> {
+    List {
+        open: Lexeme<'a>,
+        items: usize,
+        /// The dot and how many datums followed it.
+        dot: Option<(Span, usize)>,
+    },
+    /// Quote sugar waiting for its datum.
+    Quote(Span),
+}
+
+macro_rules! flow {
+    ($call:expr) => {
+        if $call == Flow::Stop {
+            return Ok(Flow::Stop);
+        }
+    };
+}
+
+impl<'a> Reader<'a> {
+    /// Reads every remaining datum into `sink`, stopping early at the first
+    /// error or when the sink asks to. Maps and sets arrive as their
+    /// elements, unchecked, and datum labels are rejected since shared
+    /// structure can't be told as a series of callbacks.
+    pub fn read_with<S: ReadSink<'a>>(&mut self, sink: &mut S) -> Result<Flow, ReadError> {
+        self.delimiters.clear();
+        self.read_into(sink).map_err(|err| self.in_file(err))
+    }
+
+    fn read_into<S: ReadSink<'a>>(&mut self, sink: &mut S) -> Result<Flow, ReadError> {
+        let mut frames: Vec<Frame<'a>> = Vec::new();
+
+        loop {
+            let lex = match self.peek_token()? {
+                Some(lex) => lex,
+                None => return self.end_of_input(&frames),
+            };
+
+            if lex.kind.is_close_delimiter() {
+                self.close_frame(&frames, lex)?;
+                self.scanner.next();
+                frames.pop();
+                self.delimiters.pop();
+                flow!(sink.close(lex.span));
+                flow!(finish(&mut frames, sink, lex.span.end));
+                continue;
+            }
+
+            if let Some(Frame::List {
+                dot: Some((dot, 1)),
+                ..
+            }) = frames.last()
+            {
+                return Err(ReadError::new(ReadErrorKind::UnexpectedDot, *dot));
+            }
+
+            if lex.kind == LexemeKind::Dot {
+                self.scanner.next();
+                match frames.last_mut() {
+                    Some(Frame::List { open, items, dot }) if *items > 0 && dot.is_none() => {
+                        if !self.allows_dot(open.kind) {
+                            return Err(ReadError::new(ReadErrorKind::UnexpectedDot, lex.span));
+                        }
+                        *dot = Some((lex.span, 0));
+                    }
+                    _ => return Err(ReadError::new(ReadErrorKind::UnexpectedDot, lex.span)),
+                }
+                flow!(sink.dot(lex.span));
+                continue;
+            }
+
+            let has_macro = self.table.and_then(|table| table.get(lex)).is_some();
+
+            if !has_macro && (quote_name(lex.kind).is_some() || lex.kind.is_open_delimiter()) {
+                flow!(self.open_frame(&mut frames, lex, sink)?);
+                continue;
+            }
+
+            let atom = match (frames.last(), lex.kind) {
+                (Some(Frame::List { open, .. }), kind)
+                    if open.kind == LexemeKind::BytevectorOpen && !has_macro =>
+                {
+                    self.scanner.next();
+                    match parse_byte(lex.slice).filter(|_| kind == LexemeKind::IntLit) {
+                        Some(byte) => Stx::new(StxKind::Number(Number::Int(byte.into())), lex.span),
+                        None => return Err(ReadError::new(ReadErrorKind::InvalidByte, lex.span)),
+                    }
+                }
+                (_, LexemeKind::DatumLabel) | (_, LexemeKind::DatumRef) if !has_macro => {
+                    return Err(ReadError::new(ReadErrorKind::UnexpectedToken, lex.span));
+                }
+                _ => {
+                    self.scanner.next();
+                    self.count_datum(lex.span)?;
+                    self.read_token(lex)?
+                }
+            };
+
+            let end = atom.span.end;
+            flow!(sink.atom(atom));
+            flow!(finish(&mut frames, sink, end));
+        }
+    }
+
+    fn open_frame<S: ReadSink<'a>>(
+        &mut self,
+        frames: &mut Vec<Frame<'a>>,
+        lex: Lexeme<'a>,
+        sink: &mut S,
+    ) -> Result<Flow, ReadError> {
+        if frames.len() >= self.max_depth {
+            return Err(ReadError::new(ReadErrorKind::TooDeep, lex.span));
+        }
+        if let Some(Frame::List { open, .. }) = frames.last() {
+            if open.kind == LexemeKind::BytevectorOpen {
+                return Err(ReadError::new(ReadErrorKind::InvalidByte, lex.span));
+            }
+        }
+        if lex.kind == LexemeKind::SetOpen && !self.options.sets {
+            return Err(ReadError::new(ReadErrorKind::UnexpectedToken, lex.span));
+        }
+        self.scanner.next();
+        self.count_datum(lex.span)?;
+
+        if let Some(name) = quote_name(lex.kind) {
+            let start = Span::new(lex.span.start, lex.span.start);
+            let mut symbol = Stx::new(StxKind::Symbol(Cow::Borrowed(name)), lex.span);
+            symbol.symbol = Some(self.symbols.intern(name));
+            frames.push(Frame::Quote(lex.span));
+            flow!(sink.open(Delim::Paren, start));
+            flow!(sink.atom(symbol));
+            return Ok(Flow::Continue);
+        }
+
+        let delim = Delim::from_open(lex.kind).unwrap_or(Delim::Paren);
+        frames.push(Frame::List {
+            open: lex,
+            items: 0,
+            dot: None,
+        });
+        self.delimiters.push((lex.kind, lex.span));
+        Ok(sink.open(delim, lex.span))
+    }
+
+    /// Checks that `close` may end the innermost frame.
+    fn close_frame(&self, frames: &[Frame<'a>], close: Lexeme<'a>) -> Result<(), ReadError> {
+        match frames.last() {
+            Some(Frame::Quote(span)) => Err(ReadError::new(ReadErrorKind::MissingDatum, *span)),
+            Some(Frame::List {
+                dot: Some((dot, 0)),
+                ..
+            }) => Err(ReadError::new(ReadErrorKind::MissingDatum, *dot)),
+            Some(Frame::List { open, .. }) if open.kind.matching_close() == Some(close.kind) => {
+                Ok(())
+            }
+            _ => Err(self.close_error(close)),
+        }
+    }
+
+    fn end_of_input(&self, frames: &[Frame<'a>]) -> Result<Flow, ReadError> {
+        match frames.last() {
+            None => Ok(Flow::Continue),
+            Some(Frame::Quote(span)) => Err(ReadError::new(ReadErrorKind::MissingDatum, *span)),
+            Some(Frame::List { open, .. }) => {
+                Err(ReadError::new(ReadErrorKind::UnterminatedList, open.span))
+            }
+        }
+    }
+}
+
+/// Closes the quote sugar waiting for the datum that ended at `end`, and
+/// counts it as an item of its list.
+fn finish<'a, S: ReadSink<'a>>(frames: &mut Vec<Frame<'a>>, sink: &mut S, end: u32) -> Flow {
+    while let Some(Frame::Quote(_)) = frames.last() {
+        frames.pop();
+        if sink.close(Span::new(end, end)) == Flow::Stop {
+            return Flow::Stop;
+        }
+    }
+
+    match frames.last_mut() {
+        Some(Frame::List {
+            dot: Some((_, after)),
+            ..
+        }) => *after += 1,
+        Some(Frame::List { items, .. }) => *items += 1,
+        _ => {}
+    }
+
+    Flow::Continue
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::reader::{Limit, ReaderOptions};
+    use alloc::format;
+    use alloc::string::String;
+
+    /// Writes what it receives back out, with atoms in debug form.
+    #[derive(Default)]
+    struct Echo {
+        out: String,
+        stop_after: Option<usize>,
+    }
+
+    impl Echo {
+        fn flow(&mut self) -> Flow {
+            match &mut self.stop_after {
+                Some(0) => Flow::Stop,
+                Some(n) => {
+                    *n -= 1;
+                    Flow::Continue
+                }
+                None => Flow::Continue,
+            }
+        }
+    }
+
+    impl<'a> ReadSink<'a> for Echo {
+        fn open(&mut self, delim: Delim, _: Span) -> Flow {
+            self.out.push_str(&format!("{:?}[ ", delim));
+            self.flow()
+        }
+
+        fn atom(&mut self, atom: Stx<'a>) -> Flow {
+            self.out.push_str(&format!("{:?} ", atom.kind));
+            self.flow()
+        }
+
+        fn dot(&mut self, _: Span) -> Flow {
+            self.out.push_str(". ");
+            self.flow()
+        }
+
+        fn close(&mut self, _: Span) -> Flow {
+            self.out.push_str("] ");
+            self.flow()
+        }
+    }
+
+    fn echo(src: &str) -> Result<String, ReadError> {
+        let mut sink = Echo::default();
+        Reader::new(src).read_with(&mut sink)?;
+        Ok(sink.out)
+    }
+
+    #[test]
+    fn test_read_with() {
+        assert_eq!(
+            echo("(a \"b\" . #\\c) #u8(1) 'x #;(y) z").unwrap(),
+            "Paren[ Symbol(\"a\") String(\"b\") . Char('c') ] Bytevector[ Number(Int(1)) ] \
+             Paren[ Symbol(\"quote\") Symbol(\"x\") ] Symbol(\"z\") "
+        );
+        assert_eq!(
+            echo("`(,@a)").unwrap(),
+            "Paren[ Symbol(\"quasiquote\") Paren[ Paren[ Symbol(\"unquote-splicing\") \
+             Symbol(\"a\") ] ] ] "
+        );
+        assert_eq!(echo("").unwrap(), "");
+        assert_eq!(
+            echo("#;#;a 'b (#;#(c) d)").unwrap(),
+            "Paren[ Symbol(\"d\") ] "
+        );
+
+        let mut sink = Echo::default();
+        let options = ReaderOptions::new().sets(true);
+        Reader::new("#{1 1}")
+            .with_options(options)
+            .read_with(&mut sink)
+            .unwrap();
+        assert_eq!(sink.out, "Set[ Number(Int(1)) Number(Int(1)) ] ");
+    }
+
+    #[test]
+    fn test_read_with_errors() {
+        let error = |src| echo(src).unwrap_err();
+
+        assert_eq!(
+            error("(a"),
+            ReadError::new(ReadErrorKind::UnterminatedList, Span::new(0, 1))
+        );
+        assert_eq!(
+            error("(a . b c)"),
+            ReadError::new(ReadErrorKind::UnexpectedDot, Span::new(3, 4))
+        );
+        assert_eq!(
+            error("(a .)"),
+            ReadError::new(ReadErrorKind::MissingDatum, Span::new(3, 4))
+        );
+        assert_eq!(
+            error("#(. a)"),
+            ReadError::new(ReadErrorKind::UnexpectedDot, Span::new(2, 3))
+        );
+        assert_eq!(
+            error("(')"),
+            ReadError::new(ReadErrorKind::MissingDatum, Span::new(1, 2))
+        );
+        assert_eq!(
+            error("#u8(256)"),
+            ReadError::new(ReadErrorKind::InvalidByte, Span::new(4, 7))
+        );
+        assert_eq!(error("#0=a").kind, ReadErrorKind::UnexpectedToken);
+        assert_eq!(
+            error("(a]").kind,
+            ReadErrorKind::MismatchedDelimiter {
+                open: LexemeKind::LParen,
+                open_span: Span::new(0, 1),
+            }
+        );
+
+        let deep = "(".repeat(10);
+        let err = Reader::new(&deep)
+            .with_max_depth(4)
+            .read_with(&mut Echo::default())
+            .unwrap_err();
+        assert_eq!(err, ReadError::new(ReadErrorKind::TooDeep, Span::new(4, 5)));
+
+        let options = ReaderOptions::new().max_datums(3);
+        let read = |src| {
+            Reader::new(src)
+                .with_options(options)
+                .read_with(&mut Echo::default())
+        };
+        assert_eq!(read("(a) b"), Ok(Flow::Continue));
+        assert_eq!(read("#u8(1 2 3)"), Ok(Flow::Continue));
+        assert_eq!(
+            read("(() () ())"),
+            Err(ReadError::new(
+                ReadErrorKind::LimitExceeded(Limit::Datums),
+                Span::new(7, 8)
+            ))
+        );
+        assert_eq!(
+            read("'(a b)"),
+            Err(ReadError::new(
+                ReadErrorKind::LimitExceeded(Limit::Datums),
+                Span::new(4, 5)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_read_with_stop() {
+        let mut sink = Echo {
+            stop_after: Some(2),
+            ..Echo::default()
+        };
+        let mut reader = Reader::new("(a b c) d");
+
+        assert_eq!(reader.read_with(&mut sink), Ok(Flow::Stop));
+        assert_eq!(sink.out, "Paren[ Symbol(\"a\") Symbol(\"b\") ");
+    }
+}
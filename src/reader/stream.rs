@@ -0,0 +1,533 @@
+//! Reading datums from an `io::Read` as its bytes arrive.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::error::{ReadError, ReadErrorKind};
+use crate::reader::{Reader, ReaderOptions, ReaderTable};
+use crate::scanner::ScannerOptions;
+use crate::span::Span;
+use crate::stx::{Stx, StxKind};
+
+/// How much is read from the underlying reader at least at a time.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    Read(ReadError),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(err) => err.fmt(f),
+            StreamError::Read(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Io(err) => Some(err),
+            StreamError::Read(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(err: io::Error) -> Self {
+        StreamError::Io(err)
+    }
+}
+
+impl From<ReadError> for StreamError {
+    fn from(err: ReadError) -> Self {
+        StreamError::Read(err)
+    }
+}
+
+/// A complete top-level datum, holding on to its own text and to how the
+/// stream was read.
+#[derive(Debug, Clone)]
+pub struct StreamDatum<'t> {
+    text: String,
+    start: u32,
+    options: ReaderOptions,
+    scanner_options: ScannerOptions,
+    table: Option<&'t ReaderTable>,
+}
+
+impl StreamDatum<'_> {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Byte offset of the datum in the stream.
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// Reads the datum from its text, the way the stream read it. Spans are
+    /// relative to `text`; add `start` to them for offsets in the stream.
+    pub fn stx(&self) -> Stx<'_> {
+        let mut reader = Reader::new(&self.text)
+            .with_options(self.options)
+            .with_scanner_options(self.scanner_options);
+        if let Some(table) = self.table {
+            reader = reader.with_table(table);
+        }
+
+        match reader.read_one() {
+            Ok(Some(stx)) => stx,
+            _ => Stx::new(StxKind::Error, Span::new(0, self.text.len() as u32)),
+        }
+    }
+}
+
+/// Yields each top-level datum as soon as enough input has arrived to know
+/// it is complete. Errors are yielded as they are found and reading goes on
+/// after them, like iterating a `Reader`. The limits in `ReaderOptions`
+/// count the whole stream, and going past one ends it.
+#[derive(Debug)]
+pub struct StreamReader<'t, R> {
+    inner: R,
+    /// Input that hasn't been read as a datum yet, after the first
+    /// `consumed` bytes. Those are only dropped before reading more, so
+    /// reading each datum doesn't move the rest of the buffer.
+    buf: Vec<u8>,
+    consumed: usize,
+    /// Offset of `buf` in the stream.
+    offset: u32,
+    options: ReaderOptions,
+    scanner_options: ScannerOptions,
+    table: Option<&'t ReaderTable>,
+    /// Whether identifiers are folded where `buf` is read from on, once a
+    /// datum has been read.
+    fold_case: Option<bool>,
+    /// What has been read so far, for the limits in `options`.
+    datums: usize,
+    atoms: usize,
+    /// What was read from `buf` and not yielded yet.
+    ready: VecDeque<Result<StreamDatum<'t>, ReadError>>,
+    /// Whether `buf` has been read as far as it can be without more input.
+    scanned: bool,
+    eof: bool,
+    /// Set once a limit is exceeded, which ends the stream.
+    done: bool,
+}
+
+impl<'t, R: io::Read> StreamReader<'t, R> {
+    pub fn new(inner: R) -> Self {
+        StreamReader {
+            inner,
+            buf: Vec::new(),
+            consumed: 0,
+            offset: 0,
+            options: ReaderOptions::new(),
+            scanner_options: ScannerOptions::new(),
+            table: None,
+            fold_case: None,
+            datums: 0,
+            atoms: 0,
+            ready: VecDeque::new(),
+            scanned: false,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// The same as `Reader::with_options`.
+    pub fn with_options(mut self, options: ReaderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The same as `Reader::with_scanner_options`.
+    pub fn with_scanner_options(mut self, options: ScannerOptions) -> Self {
+        self.scanner_options = options;
+        self
+    }
+
+    /// The same as `Reader::with_table`.
+    pub fn with_table(mut self, table: &'t ReaderTable) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads at least as much as is buffered already, so a datum spanning
+    /// many chunks is only rescanned a logarithmic number of times.
+    fn fill(&mut self) -> io::Result<()> {
+        self.buf.drain(..self.consumed);
+        self.offset += self.consumed as u32;
+        self.consumed = 0;
+        self.scanned = false;
+
+        let len = self.buf.len();
+        self.buf.resize(len + len.max(CHUNK_SIZE), 0);
+
+        loop {
+            match self.inner.read(&mut self.buf[len..]) {
+                Ok(n) => {
+                    self.buf.truncate(len + n);
+                    self.eof = n == 0;
+                    return Ok(());
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buf.truncate(len);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Reads every datum and error the buffered input is enough to tell
+    /// into `ready`, with one reader over all of it.
+    fn scan(&mut self) {
+        self.scanned = true;
+
+        // a character split between reads isn't invalid yet
+        let end = match self.eof {
+            true => self.buf.len(),
+            false => complete_utf8_len(&self.buf),
+        };
+        let buf = &self.buf[self.consumed..end];
+        let base = self.offset + self.consumed as u32;
+        let len = buf.len() as u32;
+
+        let options = match self.fold_case {
+            Some(fold_case) => self.scanner_options.fold_case(fold_case),
+            None => self.scanner_options,
+        };
+        let mut reader = Reader::from_slice(buf)
+            .with_options(self.options)
+            .with_scanner_options(options);
+        if let Some(table) = self.table {
+            reader = reader.with_table(table);
+        }
+        reader.datums = self.datums;
+        reader.atoms = self.atoms;
+
+        let mut read_to = 0;
+        loop {
+            let result = reader.read_one();
+            let position = reader.scanner.position() as u32;
+
+            let (outcome, consumed) = match result {
+                Ok(Some(stx)) if self.eof || stx.span.end < len || is_closed(&stx, buf) => {
+                    let datum = StreamDatum {
+                        text: String::from_utf8_lossy(&buf[stx.span.range()]).into_owned(),
+                        start: base + stx.span.start,
+                        options: self.options,
+                        scanner_options: self.scanner_options.fold_case(reader.scanner.fold_case()),
+                        table: self.table,
+                    };
+                    (Ok(datum), stx.span.end)
+                }
+                // stopping short of the end means more input can't change it
+                Err(err) if self.eof || position < len || is_limit(&err) => {
+                    (Err(shift(err, base)), position.max(err.span.end))
+                }
+                _ => break,
+            };
+
+            self.done = matches!(&outcome, Err(err) if is_limit(err));
+            self.ready.push_back(outcome);
+            self.fold_case = Some(reader.scanner.fold_case());
+            self.datums = reader.datums;
+            self.atoms = reader.atoms;
+            read_to = consumed;
+
+            if self.done {
+                break;
+            }
+            // the error reached past where the reader stopped
+            if consumed > position {
+                self.scanned = false;
+                break;
+            }
+        }
+
+        self.consumed += read_to as usize;
+    }
+}
+
+impl<'t, R: io::Read> Iterator for StreamReader<'t, R> {
+    type Item = Result<StreamDatum<'t>, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(outcome) = self.ready.pop_front() {
+                return Some(outcome.map_err(StreamError::Read));
+            }
+
+            if self.done {
+                return None;
+            }
+
+            if !self.scanned {
+                self.scan();
+                continue;
+            }
+
+            if self.eof {
+                return None;
+            }
+
+            if let Err(err) = self.fill() {
+                return Some(Err(StreamError::Io(err)));
+            }
+        }
+    }
+}
+
+fn is_limit(err: &ReadError) -> bool {
+    matches!(err.kind, ReadErrorKind::LimitExceeded(_))
+}
+
+/// Whether `stx` ends in a close delimiter or the quote closing a string,
+/// so no further input can extend it.
+fn is_closed(stx: &Stx<'_>, buf: &[u8]) -> bool {
+    let last = buf[..stx.span.end as usize].last();
+
+    match &stx.kind {
+        // quote sugar ends with its datum
+        StxKind::List(items) if stx.delims.is_none() => {
+            items.last().is_some_and(|item| is_closed(item, buf))
+        }
+        StxKind::Labeled(_, datum) => is_closed(&datum.borrow(), buf),
+        StxKind::List(_)
+        | StxKind::DottedList(..)
+        | StxKind::Vector(_)
+        | StxKind::Map(_)
+        | StxKind::Set(_)
+        | StxKind::Bytevector(_) => stx.delims.is_some() && !stx.incomplete,
+        StxKind::String(_) => last == Some(&b'"'),
+        _ => false,
+    }
+}
+
+/// How much of `buf` is left once a UTF-8 sequence cut short at its end is
+/// taken off.
+fn complete_utf8_len(buf: &[u8]) -> usize {
+    let len = buf.len();
+    for back in 1..=len.min(3) {
+        let byte = buf[len - back];
+        if byte & 0xc0 == 0x80 {
+            continue;
+        }
+
+        let needed = match byte {
+            0xf0..=0xff => 4,
+            0xe0..=0xef => 3,
+            0xc0..=0xdf => 2,
+            _ => 1,
+        };
+        return if needed > back { len - back } else { len };
+    }
+
+    len
+}
+
+fn shift(err: ReadError, offset: u32) -> ReadError {
+    let span = |span: Span| Span::new(span.start + offset, span.end + offset);
+
+    let kind = match err.kind {
+        ReadErrorKind::MismatchedDelimiter { open, open_span } => {
+            ReadErrorKind::MismatchedDelimiter {
+                open,
+                open_span: span(open_span),
+            }
+        }
+        kind => kind,
+    };
+
+    ReadError {
+        kind,
+        span: span(err.span),
+        file: err.file,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::scanner::LexemeKind;
+
+    /// Hands out its input a few bytes at a time, like a slow pipe.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(self.1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn read(src: &str, chunk: usize) -> Vec<Result<(String, u32), ReadError>> {
+        Reader::from_reader(Trickle(src.as_bytes(), chunk))
+            .map(|datum| match datum {
+                Ok(datum) => Ok((datum.text().to_string(), datum.start())),
+                Err(StreamError::Read(err)) => Err(err),
+                Err(StreamError::Io(err)) => panic!("{}", err),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stream() {
+        let src = "(a (b)) ; c\n\"s t\" 123 #;(x) [y . z] abc";
+
+        for chunk in 1..8 {
+            assert_eq!(
+                read(src, chunk),
+                vec![
+                    Ok(("(a (b))".to_string(), 0)),
+                    Ok(("\"s t\"".to_string(), 12)),
+                    Ok(("123".to_string(), 18)),
+                    Ok(("[y . z]".to_string(), 28)),
+                    Ok(("abc".to_string(), 36)),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_prefixes() {
+        let src = "'abc `xyz #0=sym #0=(a . #0#) '(b) aλb λ 'λλ";
+
+        for chunk in 1..5 {
+            assert_eq!(
+                read(src, chunk),
+                vec![
+                    Ok(("'abc".to_string(), 0)),
+                    Ok(("`xyz".to_string(), 5)),
+                    Ok(("#0=sym".to_string(), 10)),
+                    Ok(("#0=(a . #0#)".to_string(), 17)),
+                    Ok(("'(b)".to_string(), 30)),
+                    Ok(("aλb".to_string(), 35)),
+                    Ok(("λ".to_string(), 40)),
+                    Ok(("'λλ".to_string(), 43)),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        for chunk in 1..4 {
+            assert_eq!(
+                read("a ) (b]", chunk),
+                vec![
+                    Ok(("a".to_string(), 0)),
+                    Err(ReadError::new(
+                        ReadErrorKind::UnexpectedClose,
+                        Span::new(2, 3)
+                    )),
+                    Err(ReadError::new(
+                        ReadErrorKind::MismatchedDelimiter {
+                            open: crate::scanner::LexemeKind::LParen,
+                            open_span: Span::new(4, 5),
+                        },
+                        Span::new(6, 7)
+                    )),
+                ]
+            );
+            assert_eq!(
+                read("(a", chunk),
+                vec![Err(ReadError::new(
+                    ReadErrorKind::UnterminatedList,
+                    Span::new(0, 1)
+                ))]
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_options() {
+        let mut table = ReaderTable::new();
+        table.register(LexemeKind::KeywordLit, |_, lex| {
+            Ok(Stx::new(StxKind::Symbol("kw".into()), lex.span))
+        });
+        let src = "{a #{1}} :k (#!fold-case X)";
+
+        for chunk in 1..5 {
+            let forms: Vec<_> = Reader::from_reader(Trickle(src.as_bytes(), chunk))
+                .with_options(ReaderOptions::new().maps(true).sets(true))
+                .with_scanner_options(ScannerOptions::new().leading_colon_keywords(true))
+                .with_table(&table)
+                .map(|datum| datum.unwrap())
+                .collect();
+
+            let kinds: Vec<_> = forms.iter().map(|datum| datum.stx().kind).collect();
+            assert!(matches!(&kinds[0], StxKind::Map(entries) if entries.len() == 1));
+            assert_eq!(kinds[1], StxKind::Symbol("kw".into()));
+            assert!(matches!(&kinds[2], StxKind::List(items)
+                if items[0].kind == StxKind::Symbol("x".into())));
+            assert_eq!(forms.len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_stream_limits() {
+        for chunk in 1..4 {
+            let options = ReaderOptions::new().max_datums(4);
+            let all: Vec<_> = Reader::from_reader(Trickle(b"a (b c) d e", chunk))
+                .with_options(options)
+                .map(|datum| datum.map(|datum| datum.start()))
+                .collect();
+
+            assert_eq!(all.len(), 3);
+            assert_eq!(all[0].as_ref().unwrap(), &0);
+            assert_eq!(all[1].as_ref().unwrap(), &2);
+            match &all[2] {
+                Err(StreamError::Read(err)) => assert_eq!(
+                    err,
+                    &ReadError::new(
+                        ReadErrorKind::LimitExceeded(crate::error::Limit::Datums),
+                        Span::new(8, 9)
+                    )
+                ),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_many_datums() {
+        let src = "a ".repeat(100_000);
+        let mut count = 0;
+        for datum in Reader::from_reader(src.as_bytes()) {
+            assert_eq!(datum.unwrap().start(), 2 * count);
+            count += 1;
+        }
+        assert_eq!(count, 100_000);
+    }
+
+    #[test]
+    fn test_stream_datum() {
+        let mut reader = Reader::from_reader(Trickle(b"#!fold-case (FOO)", 2));
+        let datum = reader.next().unwrap().unwrap();
+
+        assert_eq!(datum.start(), 12);
+        assert_eq!(
+            datum.stx().kind,
+            StxKind::List(vec![Stx::new(
+                StxKind::Symbol("foo".into()),
+                Span::new(1, 4)
+            )])
+        );
+        assert!(reader.next().is_none());
+    }
+}
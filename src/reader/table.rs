@@ -0,0 +1,189 @@
+//! Reader macros, which let embedders extend the syntax the reader accepts.
+
+use alloc::vec::Vec;
+
+use crate::error::ReadError;
+use crate::reader::Reader;
+use crate::scanner::{DispatchFn, DispatchTable, Lexeme, LexemeKind};
+use crate::stx::Stx;
+
+/// Reads the datum starting with `lex`, which the reader has consumed
+/// already. `Reader::read_subform` reads any datums that follow as part of
+/// it.
+pub type ReaderMacro = for<'a> fn(&mut Reader<'a>, Lexeme<'a>) -> Result<Stx<'a>, ReadError>;
+
+/// The reader macros a `Reader` uses, see `Reader::with_table`.
+#[derive(Debug, Clone, Default)]
+pub struct ReaderTable {
+    dispatch: DispatchTable,
+    /// Macros for `#` followed by a character, scanned by `dispatch`.
+    dispatch_macros: Vec<(u8, ReaderMacro)>,
+    /// Macros replacing how lexemes of a kind are read.
+    macros: Vec<(LexemeKind, ReaderMacro)>,
+}
+
+impl ReaderTable {
+    pub fn new() -> Self {
+        ReaderTable::default()
+    }
+
+    /// Reads `#` followed by `ch` with `read`, once `scan` has found where
+    /// the lexeme ends. Where `scan` returns `None` the built-in syntax is
+    /// read instead. Registering a character again replaces its macro.
+    pub fn register_dispatch(&mut self, ch: u8, scan: DispatchFn, read: ReaderMacro) -> &mut Self {
+        self.dispatch.register(ch, scan);
+
+        match self.dispatch_macros.iter_mut().find(|(key, _)| *key == ch) {
+            Some(entry) => entry.1 = read,
+            None => self.dispatch_macros.push((ch, read)),
+        }
+
+        self
+    }
+
+    /// Reads every lexeme of `kind` with `read` instead of the built-in way.
+    /// Registering a kind again replaces its macro.
+    pub fn register(&mut self, kind: LexemeKind, read: ReaderMacro) -> &mut Self {
+        match self.macros.iter_mut().find(|(key, _)| *key == kind) {
+            Some(entry) => entry.1 = read,
+            None => self.macros.push((kind, read)),
+        }
+
+        self
+    }
+
+    /// The macro reading `lex`, if one is registered for it.
+    pub fn get(&self, lex: Lexeme<'_>) -> Option<ReaderMacro> {
+        if let LexemeKind::Dispatch(_) = lex.kind {
+            let ch = *lex.slice.as_bytes().get(1)?;
+            return self
+                .dispatch_macros
+                .iter()
+                .find(|(key, _)| *key == ch)
+                .map(|(_, read)| *read);
+        }
+
+        self.macros
+            .iter()
+            .find(|(key, _)| *key == lex.kind)
+            .map(|(_, read)| *read)
+    }
+
+    pub fn dispatch(&self) -> &DispatchTable {
+        &self.dispatch
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::error::ReadErrorKind;
+    use crate::scanner::DispatchMatch;
+    use crate::span::Span;
+    use crate::stx::StxKind;
+    use alloc::borrow::Cow;
+    use alloc::vec;
+
+    fn scan_regex(src: &[u8]) -> Option<DispatchMatch> {
+        if !src.starts_with(b"rx\"") {
+            return None;
+        }
+
+        let close = src[3..].iter().position(|ch| *ch == b'"')?;
+        Some(DispatchMatch {
+            len: close + 4,
+            tag: 0,
+        })
+    }
+
+    /// `#rx"a+"` reads as `(regex "a+")`.
+    fn read_regex<'a>(_: &mut Reader<'a>, lex: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let name = Span::new(lex.span.start, lex.span.start + 3);
+        let pattern = Span::new(lex.span.start + 3, lex.span.end);
+
+        Ok(Stx::new(
+            StxKind::List(vec![
+                Stx::new(StxKind::Symbol(Cow::Borrowed("regex")), name),
+                Stx::new(
+                    StxKind::String(Cow::Borrowed(&lex.slice[4..lex.slice.len() - 1])),
+                    pattern,
+                ),
+            ]),
+            lex.span,
+        ))
+    }
+
+    fn scan_maybe(_: &[u8]) -> Option<DispatchMatch> {
+        Some(DispatchMatch { len: 1, tag: 1 })
+    }
+
+    /// `#?x` reads as `(maybe x)`.
+    fn read_maybe<'a>(reader: &mut Reader<'a>, lex: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        let datum = reader.read_subform()?;
+        let span = Span::new(lex.span.start, datum.span.end);
+        let name = Stx::new(StxKind::Symbol(Cow::Borrowed("maybe")), lex.span);
+
+        Ok(Stx::new(StxKind::List(vec![name, datum]), span))
+    }
+
+    fn read_keyword<'a>(_: &mut Reader<'a>, lex: Lexeme<'a>) -> Result<Stx<'a>, ReadError> {
+        Err(ReadError::new(ReadErrorKind::UnexpectedToken, lex.span))
+    }
+
+    fn table() -> ReaderTable {
+        let mut table = ReaderTable::new();
+        table
+            .register_dispatch(b'r', scan_regex, read_regex)
+            .register_dispatch(b'?', scan_maybe, read_maybe)
+            .register(LexemeKind::KeywordLit, read_keyword);
+        table
+    }
+
+    #[test]
+    fn test_reader_macros() {
+        let table = table();
+        let read = |src| {
+            Reader::new(src)
+                .with_table(&table)
+                .read_one()
+                .unwrap()
+                .unwrap()
+                .kind
+        };
+
+        assert_eq!(
+            read("#rx\"a+\""),
+            StxKind::List(vec![
+                Stx::new(StxKind::Symbol(Cow::Borrowed("regex")), Span::new(0, 3)),
+                Stx::new(StxKind::String(Cow::Borrowed("a+")), Span::new(3, 7)),
+            ])
+        );
+        assert_eq!(
+            read("(#? #;y x)"),
+            StxKind::List(vec![Stx::new(
+                StxKind::List(vec![
+                    Stx::new(StxKind::Symbol(Cow::Borrowed("maybe")), Span::new(1, 3)),
+                    Stx::new(StxKind::Symbol(Cow::Borrowed("x")), Span::new(8, 9)),
+                ]),
+                Span::new(1, 9),
+            )])
+        );
+        assert_eq!(read("#t"), StxKind::Bool(true));
+
+        assert_eq!(
+            Reader::new("#:k").with_table(&table).read_one(),
+            Err(ReadError::new(
+                ReadErrorKind::UnexpectedToken,
+                Span::new(0, 3)
+            ))
+        );
+        assert_eq!(
+            Reader::new("#rest")
+                .with_table(&table)
+                .read_one()
+                .unwrap_err()
+                .kind,
+            ReadErrorKind::Lex(crate::scanner::ScanErrorKind::UnknownDispatch)
+        );
+    }
+}
@@ -1,14 +1,31 @@
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
 use core::str;
-use std::slice::Iter;
+
+use memchr::{memchr, memchr2, memchr3};
+use unicode_xid::UnicodeXID;
+
+use crate::span::Span;
+use crate::tokens::{Token, TokenBuffer};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LexemeKind {
     Whitespace,
     Tab,
     NewlineLf,
     NewlineCr,
     NewlineCrlf,
+    /// A run of whitespace and newlines, see `ScannerOptions::compress_trivia`.
+    Trivia,
     Comment,
+    DocComment,
+    BlockComment,
+    DatumComment,
 
     LParen,
     RParen,
@@ -16,375 +33,1618 @@ pub enum LexemeKind {
     RBracket,
     LBrace,
     RBrace,
+    VectorOpen,
+    BytevectorOpen,
+    /// `#{`, closed by `}`.
+    SetOpen,
+
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+    Dot,
+    /// `#N=`, labelling the datum after it.
+    DatumLabel,
+    /// `#N#`, referring to a labelled datum.
+    DatumRef,
 
     Identifier,
+    QuotedIdentifier,
 
     IntLit,
     FloatLit,
+    RationalLit,
     CharLit,
     StringLit,
     BoolLit,
     KeywordLit,
 
-    UnterminatedString,
-    InvalidNumberSign,
+    FoldCaseDirective,
+    NoFoldCaseDirective,
+
+    Error(ScanErrorKind),
+    /// Input that no other rule accepts, such as control characters.
+    Unknown,
 
     LString,
     RString,
     StringContent,
+    StringEscape,
+    StringLineContinuation,
+
+    HeredocStart,
+    HeredocContent,
+    HeredocEnd,
+
+    /// Produced by a handler registered in a `DispatchTable`, tagged with
+    /// the value that handler returned.
+    Dispatch(u16),
+}
+
+impl LexemeKind {
+    /// Whitespace, newlines and comments, which carry no meaning for the
+    /// reader. `DatumComment` is not trivia since it affects the next datum.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            LexemeKind::Whitespace
+                | LexemeKind::Tab
+                | LexemeKind::NewlineLf
+                | LexemeKind::NewlineCr
+                | LexemeKind::NewlineCrlf
+                | LexemeKind::Trivia
+                | LexemeKind::Comment
+                | LexemeKind::DocComment
+                | LexemeKind::BlockComment
+        )
+    }
+
+    pub fn is_open_delimiter(self) -> bool {
+        self.matching_close().is_some()
+    }
+
+    pub fn is_close_delimiter(self) -> bool {
+        matches!(
+            self,
+            LexemeKind::RParen | LexemeKind::RBracket | LexemeKind::RBrace
+        )
+    }
+
+    pub fn matching_close(self) -> Option<LexemeKind> {
+        match self {
+            LexemeKind::LParen | LexemeKind::VectorOpen | LexemeKind::BytevectorOpen => {
+                Some(LexemeKind::RParen)
+            }
+            LexemeKind::LBracket => Some(LexemeKind::RBracket),
+            LexemeKind::LBrace | LexemeKind::SetOpen => Some(LexemeKind::RBrace),
+            _ => None,
+        }
+    }
+
+    pub fn is_error(self) -> bool {
+        matches!(self, LexemeKind::Error(_) | LexemeKind::Unknown)
+    }
+
+    pub fn error(self) -> Option<ScanErrorKind> {
+        match self {
+            LexemeKind::Error(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Why a lexeme was rejected, carried by `LexemeKind::Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScanErrorKind {
+    UnknownDispatch,
+    InvalidBoolean,
+    BadRadixDigit,
+    MissingDigits,
+    InvalidChar,
+    InvalidEscape,
+    InvalidIdentifier,
+    InvalidBytes,
+    MissingHeredocTerminator,
+    UnterminatedString,
+    UnterminatedBlockComment,
+    UnterminatedIdentifier,
+    UnterminatedHeredoc,
+}
+
+impl ScanErrorKind {
+    pub fn message(self) -> &'static str {
+        match self {
+            ScanErrorKind::UnknownDispatch => "unknown character after `#`",
+            ScanErrorKind::InvalidBoolean => "invalid boolean literal",
+            ScanErrorKind::BadRadixDigit => "digit is not valid for the literal's radix",
+            ScanErrorKind::MissingDigits => "number literal has no digits",
+            ScanErrorKind::InvalidChar => "unknown character name",
+            ScanErrorKind::InvalidEscape => "invalid escape sequence",
+            ScanErrorKind::InvalidIdentifier => "identifier contains disallowed characters",
+            ScanErrorKind::InvalidBytes => "invalid UTF-8",
+            ScanErrorKind::MissingHeredocTerminator => "heredoc has no terminator",
+            ScanErrorKind::UnterminatedString => "unterminated string",
+            ScanErrorKind::UnterminatedBlockComment => "unterminated block comment",
+            ScanErrorKind::UnterminatedIdentifier => "unterminated `|` identifier",
+            ScanErrorKind::UnterminatedHeredoc => "unterminated heredoc",
+        }
+    }
+}
+
+impl fmt::Display for ScanErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScannerMode {
     Regular,
     String,
+    Heredoc,
+}
+
+pub const MAX_MODE_DEPTH: usize = 8;
+
+/// The stack of nested scanning contexts, with `Regular` always at the
+/// bottom. Fixed capacity keeps it `Copy` so checkpoints stay cheap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeStack {
+    modes: [ScannerMode; MAX_MODE_DEPTH],
+    depth: usize,
+}
+
+impl ModeStack {
+    pub fn new() -> Self {
+        ModeStack {
+            modes: [ScannerMode::Regular; MAX_MODE_DEPTH],
+            depth: 1,
+        }
+    }
+
+    pub fn current(&self) -> ScannerMode {
+        self.modes[self.depth - 1]
+    }
+
+    /// Number of entries including the `Regular` base, so never zero.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns `false` without changing anything once `MAX_MODE_DEPTH` is
+    /// reached.
+    pub fn push(&mut self, mode: ScannerMode) -> bool {
+        if self.depth == MAX_MODE_DEPTH {
+            return false;
+        }
+
+        self.modes[self.depth] = mode;
+        self.depth += 1;
+        true
+    }
+
+    /// The `Regular` base is never popped.
+    pub fn pop(&mut self) -> Option<ScannerMode> {
+        if self.depth == 1 {
+            return None;
+        }
+
+        self.depth -= 1;
+        Some(self.modes[self.depth])
+    }
+}
+
+impl Default for ModeStack {
+    fn default() -> Self {
+        ModeStack::new()
+    }
 }
 
+/// With the `serde` feature a lexeme serializes as its kind, text and span.
+/// Deserializing borrows the text, so it needs a format that can hand out
+/// unescaped strings, such as JSON text without escape sequences.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Lexeme<'a> {
     pub kind: LexemeKind,
+    #[cfg_attr(feature = "serde", serde(rename = "text", borrow))]
     pub slice: &'a str,
+    pub span: Span,
+}
+
+/// The radix of an `IntLit` or `FloatLit`, taken from its `#x`, `#o`, `#b`
+/// or `#d` prefix.
+pub fn literal_radix(slice: &str) -> u32 {
+    match slice.as_bytes() {
+        [b'#', b'x', ..] | [b'#', b'X', ..] => 16,
+        [b'#', b'o', ..] | [b'#', b'O', ..] => 8,
+        [b'#', b'b', ..] | [b'#', b'B', ..] => 2,
+        _ => 10,
+    }
+}
+
+/// Case folds an identifier for use while `Scanner::fold_case` is on.
+pub fn fold_identifier(ident: &str) -> Cow<'_, str> {
+    if ident.chars().any(|ch| ch.is_uppercase()) {
+        Cow::Owned(ident.to_lowercase())
+    } else {
+        Cow::Borrowed(ident)
+    }
+}
+
+/// Names accepted after `#\\` in character literals.
+pub const CHAR_NAMES: &[(&str, char)] = &[
+    ("alarm", '\u{7}'),
+    ("backspace", '\u{8}'),
+    ("delete", '\u{7f}'),
+    ("escape", '\u{1b}'),
+    ("linefeed", '\n'),
+    ("newline", '\n'),
+    ("nul", '\0'),
+    ("null", '\0'),
+    ("page", '\u{c}'),
+    ("return", '\r'),
+    ("space", ' '),
+    ("tab", '\t'),
+];
+
+/// Decodes the part of a hex character literal following `x`.
+pub fn hex_char(digits: &str) -> Option<char> {
+    if digits.is_empty() || digits.len() > 6 || !digits.bytes().all(|ch| ch.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(char::from_u32)
 }
 
 fn is_newline_start(ch: u8) -> bool {
     ch == b'\r' || ch == b'\n'
 }
 
-fn is_atmosphere_start(ch: u8) -> bool {
-    match ch {
-        b' ' | b'\t' | b';' => true,
-        x => is_newline_start(x),
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0xf0..=0xff => 4,
+        0xe0..=0xef => 3,
+        0xc0..=0xdf => 2,
+        _ => 1,
+    }
+}
+
+fn is_utf8_continuation(ch: u8) -> bool {
+    ch & 0xc0 == 0x80
+}
+
+fn decode_char(bytes: &[u8]) -> Option<char> {
+    let len = utf8_len(*bytes.first()?);
+    let encoded = bytes.get(..len)?;
+
+    str::from_utf8(encoded).ok()?.chars().next()
+}
+
+fn is_xid_identifier(ident: &str) -> bool {
+    ident.chars().enumerate().all(|(i, ch)| {
+        ch.is_ascii() || (i == 0 && ch.is_xid_start()) || (i != 0 && ch.is_xid_continue())
+    })
+}
+
+const fn delimiter_table() -> [bool; 256] {
+    let mut table = [false; 256];
+    let delimiters = b"()[]{}\"| \t;\r\n";
+
+    let mut i = 0;
+    while i < delimiters.len() {
+        table[delimiters[i] as usize] = true;
+        i += 1;
     }
+
+    // control characters are never part of an atom, they lex as `Unknown`
+    let mut ch = 0;
+    while ch < 0x20 {
+        table[ch] = true;
+        ch += 1;
+    }
+    table[0x7f] = true;
+
+    table
 }
 
+static DELIMITERS: [bool; 256] = delimiter_table();
+
 fn is_delimiter(ch: u8) -> bool {
-    match ch {
-        b'(' | b')' | b'[' | b']' | b'{' | b'}' | b'"' => true,
-        x => is_atmosphere_start(x),
+    DELIMITERS[ch as usize]
+}
+
+/// Length of the run of `byte` at the start of `bytes`, compared eight
+/// bytes at a time.
+fn run_len(bytes: &[u8], byte: u8) -> usize {
+    let pattern = u64::from_ne_bytes([byte; 8]);
+    let mut chunks = bytes.chunks_exact(8);
+    let mut len = 0;
+
+    for chunk in &mut chunks {
+        let mut word = [0; 8];
+        word.copy_from_slice(chunk);
+        let diff = u64::from_le_bytes(word) ^ pattern;
+
+        if diff != 0 {
+            return len + diff.trailing_zeros() as usize / 8;
+        }
+
+        len += 8;
+    }
+
+    len + chunks
+        .remainder()
+        .iter()
+        .take_while(|ch| **ch == byte)
+        .count()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DispatchMatch {
+    /// Bytes consumed after the `#`, including the dispatch character.
+    pub len: usize,
+    pub tag: u16,
+}
+
+/// Receives the input following a `#`, starting with the dispatch
+/// character, and returns `None` to fall back to the built-in syntax.
+pub type DispatchFn = fn(&[u8]) -> Option<DispatchMatch>;
+
+/// Custom handlers for characters following `#`, consulted before the
+/// built-in `#` syntax.
+#[derive(Debug, Clone, Default)]
+pub struct DispatchTable {
+    handlers: Vec<(u8, DispatchFn)>,
+}
+
+impl DispatchTable {
+    pub fn new() -> Self {
+        DispatchTable::default()
+    }
+
+    /// Registering a character again replaces its previous handler.
+    pub fn register(&mut self, ch: u8, handler: DispatchFn) -> &mut Self {
+        match self.handlers.iter_mut().find(|(key, _)| *key == ch) {
+            Some(entry) => entry.1 = handler,
+            None => self.handlers.push((ch, handler)),
+        }
+
+        self
+    }
+
+    pub fn get(&self, ch: u8) -> Option<DispatchFn> {
+        self.handlers
+            .iter()
+            .find(|(key, _)| *key == ch)
+            .map(|(_, handler)| *handler)
+    }
+}
+
+/// A saved scanner position, restored with `Scanner::rewind`. Only valid
+/// for the scanner it was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint<'a> {
+    pos: usize,
+    modes: ModeStack,
+    heredoc_terminator: &'a [u8],
+    fold_case: bool,
+}
+
+/// Which line endings count as newlines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewlinePolicy {
+    /// `\n`, `\r\n` and a lone `\r`.
+    Any,
+    /// Only `\n`. Outside of strings and heredocs a `\r` lexes as `Unknown`.
+    Lf,
+}
+
+/// The dialect accepted by a `Scanner`, see `Scanner::with_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScannerOptions {
+    newlines: NewlinePolicy,
+    leading_colon_keywords: bool,
+    trailing_colon_keywords: bool,
+    block_comments: bool,
+    heredocs: bool,
+    leading_dot_floats: bool,
+    fold_case: bool,
+    compress_trivia: bool,
+    strict: bool,
+}
+
+impl ScannerOptions {
+    pub fn new() -> Self {
+        ScannerOptions {
+            newlines: NewlinePolicy::Any,
+            leading_colon_keywords: false,
+            trailing_colon_keywords: false,
+            block_comments: true,
+            heredocs: true,
+            leading_dot_floats: true,
+            fold_case: false,
+            compress_trivia: false,
+            strict: false,
+        }
+    }
+
+    pub fn newlines(mut self, policy: NewlinePolicy) -> Self {
+        self.newlines = policy;
+        self
+    }
+
+    /// Also lexes `:foo` as a `KeywordLit`. A lone `:` stays an identifier.
+    pub fn leading_colon_keywords(mut self, enabled: bool) -> Self {
+        self.leading_colon_keywords = enabled;
+        self
+    }
+
+    /// Also lexes `foo:` as a `KeywordLit`. A lone `:` stays an identifier.
+    pub fn trailing_colon_keywords(mut self, enabled: bool) -> Self {
+        self.trailing_colon_keywords = enabled;
+        self
+    }
+
+    /// When disabled `#|` is an unknown dispatch.
+    pub fn block_comments(mut self, enabled: bool) -> Self {
+        self.block_comments = enabled;
+        self
+    }
+
+    /// When disabled `#<<` is an unknown dispatch.
+    pub fn heredocs(mut self, enabled: bool) -> Self {
+        self.heredocs = enabled;
+        self
+    }
+
+    /// Lexes `.5`, `-.5` and `+.5` as a `FloatLit`. When disabled they are
+    /// identifiers. A `.` not followed by a digit is never a number.
+    pub fn leading_dot_floats(mut self, enabled: bool) -> Self {
+        self.leading_dot_floats = enabled;
+        self
+    }
+
+    /// Whether identifiers are case folded before the first directive, for
+    /// sources written for a case-insensitive Scheme.
+    pub fn fold_case(mut self, enabled: bool) -> Self {
+        self.fold_case = enabled;
+        self
+    }
+
+    /// Merges runs of spaces, tabs and newlines into one `Trivia` lexeme
+    /// instead of reporting each kind separately.
+    pub fn compress_trivia(mut self, enabled: bool) -> Self {
+        self.compress_trivia = enabled;
+        self
+    }
+
+    /// Strict mode restricts non-ASCII characters in identifiers to the
+    /// Unicode `XID_Start`/`XID_Continue` classes, lexing violations as
+    /// `ScanErrorKind::InvalidIdentifier`.
+    pub fn strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+}
+
+impl Default for ScannerOptions {
+    fn default() -> Self {
+        ScannerOptions::new()
     }
 }
 
 pub struct ScanRes {
     kind: LexemeKind,
-    slice_end: *const u8,
+    end: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Scanner<'a> {
-    iter: Iter<'a, u8>,
-    mode: ScannerMode,
+    src: &'a [u8],
+    text: Option<&'a str>,
+    pos: usize,
+    modes: ModeStack,
+    heredoc_terminator: &'a [u8],
+    fold_case: bool,
+    options: ScannerOptions,
+    dispatch: Option<&'a DispatchTable>,
+    lookahead: VecDeque<(Checkpoint<'a>, Lexeme<'a>)>,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(src: &'a str) -> Self {
         Scanner {
-            iter: src.as_bytes().iter(),
-            mode: ScannerMode::Regular,
+            src: src.as_bytes(),
+            text: Some(src),
+            pos: 0,
+            modes: ModeStack::new(),
+            heredoc_terminator: &[],
+            fold_case: false,
+            options: ScannerOptions::new(),
+            dispatch: None,
+            lookahead: VecDeque::new(),
         }
     }
 
-    pub unsafe fn as_str(&self) -> &str {
-        std::str::from_utf8_unchecked(self.iter.as_slice())
+    pub fn with_options(mut self, options: ScannerOptions) -> Self {
+        self.options = options;
+        self.fold_case = options.fold_case;
+        self
     }
 
-    fn scan_whitespace(mut iter: Iter<u8>) -> ScanRes {
-        let mut peek_iter = iter.clone();
-        while let Some(ch) = peek_iter.next() {
-            if *ch != b' ' {
-                break;
+    pub fn options(&self) -> ScannerOptions {
+        self.options
+    }
+
+    pub fn with_dispatch(mut self, table: &'a DispatchTable) -> Self {
+        self.dispatch = Some(table);
+        self
+    }
+
+    /// Scans possibly malformed input. Every invalid UTF-8 sequence is
+    /// reported as a `ScanErrorKind::InvalidBytes` lexeme with an empty
+    /// `slice`; use its `span` to get at the raw bytes. A lexeme that would
+    /// otherwise contain invalid bytes is cut short in front of them.
+    pub fn from_bytes(src: &'a [u8]) -> Self {
+        Scanner {
+            src,
+            text: str::from_utf8(src).ok(),
+            pos: 0,
+            modes: ModeStack::new(),
+            heredoc_terminator: &[],
+            fold_case: false,
+            options: ScannerOptions::new(),
+            dispatch: None,
+            lookahead: VecDeque::new(),
+        }
+    }
+
+    /// Starts scanning at byte `offset` instead of the beginning, which is
+    /// moved forward to the next character boundary if it splits one. The
+    /// offset should be between lexemes of the regular syntax, such as
+    /// after a top-level datum.
+    pub fn starting_at(mut self, offset: usize) -> Self {
+        let mut pos = offset.min(self.src.len());
+        while self.src.get(pos).is_some_and(|b| b & 0xc0 == 0x80) {
+            pos += 1;
+        }
+
+        self.lookahead.clear();
+        self.pos = pos;
+        self
+    }
+
+    fn resume(src: &'a str, pos: usize, modes: ModeStack, options: ScannerOptions) -> Self {
+        debug_assert!(modes.current() != ScannerMode::Heredoc);
+
+        Scanner {
+            pos,
+            modes,
+            ..Scanner::new(src).with_options(options)
+        }
+    }
+
+    /// Replaces `range` of the source behind `old_tokens` with `new_text`
+    /// and rescans only as much of the result as the edit can affect.
+    ///
+    /// Scanning restarts in front of the damaged region at the closest token
+    /// that doesn't sit inside a heredoc, and stops as soon as it falls back
+    /// into step with the old tokens after the edit. It uses the options the
+    /// old tokens were scanned with. `None` if `range` is out of bounds or
+    /// doesn't fall on character boundaries.
+    pub fn relex(
+        range: Range<usize>,
+        new_text: &str,
+        old_tokens: &TokenBuffer,
+    ) -> Option<TokenBuffer> {
+        old_tokens.src().get(range.clone())?;
+        let mut src = old_tokens.src().to_string();
+        src.replace_range(range.clone(), new_text);
+
+        let old = old_tokens.tokens();
+        let edit_end = range.start + new_text.len();
+        let delta = new_text.len() as i64 - range.len() as i64;
+
+        let mut restart = old.partition_point(|token| (token.span.end as usize) < range.start);
+        restart = restart.saturating_sub(1);
+        while restart > 0 && old[restart].modes.current() == ScannerMode::Heredoc {
+            restart -= 1;
+        }
+
+        let mut tokens = old[..restart].to_vec();
+        let (pos, modes) = old.get(restart).map_or((0, ModeStack::new()), |token| {
+            (token.span.start as usize, token.modes)
+        });
+
+        let options = old_tokens.options();
+        let mut scanner = Scanner::resume(&src, pos, modes, options);
+        // old tokens starting before the edit are never candidates for resyncing
+        let mut old_index = old.partition_point(|token| (token.span.start as usize) < range.end);
+
+        loop {
+            let modes = scanner.modes();
+            let lex = match scanner.next() {
+                Some(lex) => lex,
+                None => break,
+            };
+            let start = lex.span.start as usize;
+
+            if start >= edit_end && modes.current() != ScannerMode::Heredoc {
+                let old_start = (start as i64 - delta) as u32;
+
+                while old_index < old.len() && old[old_index].span.start < old_start {
+                    old_index += 1;
+                }
+
+                if let Some(token) = old.get(old_index) {
+                    if token.span.start == old_start && token.modes == modes {
+                        tokens.extend(old[old_index..].iter().map(|token| Token {
+                            span: Span::new(
+                                (token.span.start as i64 + delta) as u32,
+                                (token.span.end as i64 + delta) as u32,
+                            ),
+                            ..*token
+                        }));
+                        break;
+                    }
+                }
             }
 
-            iter = peek_iter.clone();
+            tokens.push(Token {
+                kind: lex.kind,
+                span: lex.span,
+                modes,
+            });
         }
 
-        ScanRes {
-            kind: LexemeKind::Whitespace,
-            slice_end: iter.as_slice().as_ptr(),
+        Some(TokenBuffer::from_parts(
+            src,
+            tokens,
+            old_tokens.file(),
+            options,
+        ))
+    }
+
+    pub fn peek(&mut self) -> Option<Lexeme<'a>> {
+        self.peek_nth(0)
+    }
+
+    /// Returns the lexeme `n` positions ahead without consuming anything.
+    /// Lexemes scanned to answer this are cached until `next` reaches them.
+    pub fn peek_nth(&mut self, n: usize) -> Option<Lexeme<'a>> {
+        while self.lookahead.len() <= n {
+            let checkpoint = self.scan_checkpoint();
+            let lex = self.scan_lexeme()?;
+            self.lookahead.push_back((checkpoint, lex));
+        }
+
+        self.lookahead.get(n).map(|(_, lex)| *lex)
+    }
+
+    fn scan_checkpoint(&self) -> Checkpoint<'a> {
+        Checkpoint {
+            pos: self.pos,
+            modes: self.modes,
+            heredoc_terminator: self.heredoc_terminator,
+            fold_case: self.fold_case,
         }
     }
 
-    fn scan_tab(mut iter: Iter<u8>) -> ScanRes {
-        let mut peek_iter = iter.clone();
-        while let Some(ch) = peek_iter.next() {
-            if *ch != b'\t' {
+    pub fn checkpoint(&self) -> Checkpoint<'a> {
+        self.lookahead
+            .front()
+            .map_or_else(|| self.scan_checkpoint(), |(checkpoint, _)| *checkpoint)
+    }
+
+    pub fn rewind(&mut self, checkpoint: Checkpoint<'a>) {
+        debug_assert!(checkpoint.pos <= self.src.len());
+
+        self.lookahead.clear();
+        self.pos = checkpoint.pos;
+        self.modes = checkpoint.modes;
+        self.heredoc_terminator = checkpoint.heredoc_terminator;
+        self.fold_case = checkpoint.fold_case;
+    }
+
+    /// Whether identifiers from here on should be case folded, as switched
+    /// by the `#!fold-case` and `#!no-fold-case` directives.
+    pub fn fold_case(&self) -> bool {
+        self.checkpoint().fold_case
+    }
+
+    /// Byte offset of the next lexeme `next` will return.
+    pub fn position(&self) -> usize {
+        self.checkpoint().pos
+    }
+
+    pub fn mode(&self) -> ScannerMode {
+        self.modes().current()
+    }
+
+    pub fn modes(&self) -> ModeStack {
+        self.checkpoint().modes
+    }
+
+    pub fn mode_depth(&self) -> usize {
+        self.modes().depth()
+    }
+
+    /// The remaining input, up to the first invalid UTF-8 sequence.
+    pub fn remaining(&self) -> &'a str {
+        let rest = &self.src[self.position()..];
+
+        match str::from_utf8(rest) {
+            Ok(rest) => rest,
+            Err(err) => str::from_utf8(&rest[..err.valid_up_to()]).unwrap_or_default(),
+        }
+    }
+
+    fn bytes(&self) -> &'a [u8] {
+        self.src
+    }
+
+    fn lexeme_str(&self, start: usize, end: usize) -> Result<&'a str, str::Utf8Error> {
+        // dispatch handlers aren't trusted to end on a character boundary
+        match self.text.and_then(|text| text.get(start..end)) {
+            Some(slice) => Ok(slice),
+            None => str::from_utf8(&self.src[start..end]),
+        }
+    }
+
+    fn byte(&self, i: usize) -> Option<u8> {
+        self.bytes().get(i).copied()
+    }
+
+    fn advance_while(&self, mut i: usize, pred: impl Fn(u8) -> bool) -> usize {
+        while let Some(ch) = self.byte(i) {
+            if !pred(ch) {
                 break;
             }
 
-            iter = peek_iter.clone();
+            i += 1;
+        }
+
+        i
+    }
+
+    fn scan_whitespace(&self, i: usize) -> ScanRes {
+        ScanRes {
+            kind: LexemeKind::Whitespace,
+            end: i + run_len(&self.bytes()[i..], b' '),
         }
+    }
 
+    fn scan_tab(&self, i: usize) -> ScanRes {
         ScanRes {
             kind: LexemeKind::Tab,
-            slice_end: iter.as_slice().as_ptr(),
+            end: i + run_len(&self.bytes()[i..], b'\t'),
         }
     }
 
-    fn scan_cr(iter: Iter<u8>) -> ScanRes {
-        let mut peek_iter = iter.clone();
-
-        if let Some(ch) = peek_iter.next() {
-            if *ch == b'\n' {
-                return ScanRes {
-                    kind: LexemeKind::NewlineCrlf,
-                    slice_end: peek_iter.as_slice().as_ptr(),
-                };
-            }
+    fn scan_cr(&self, i: usize) -> ScanRes {
+        if self.byte(i) == Some(b'\n') {
+            return ScanRes {
+                kind: LexemeKind::NewlineCrlf,
+                end: i + 1,
+            };
         }
 
         ScanRes {
             kind: LexemeKind::NewlineCr,
-            slice_end: iter.as_slice().as_ptr(),
+            end: i,
         }
     }
 
-    fn scan_comment(mut iter: Iter<u8>) -> ScanRes {
-        let mut peek_iter = iter.clone();
-
-        while let Some(ch) = peek_iter.next() {
-            if is_newline_start(*ch) {
-                break;
+    fn is_compressed_trivia(&self, ch: u8) -> bool {
+        self.options.compress_trivia
+            && match ch {
+                b' ' | b'\t' | b'\n' => true,
+                b'\r' => self.options.newlines == NewlinePolicy::Any,
+                _ => false,
             }
+    }
 
-            iter = peek_iter.clone();
+    fn scan_trivia(&self, i: usize) -> ScanRes {
+        ScanRes {
+            kind: LexemeKind::Trivia,
+            end: self.advance_while(i, |ch| self.is_compressed_trivia(ch)),
         }
+    }
+
+    fn find_newline(&self, i: usize) -> usize {
+        let rest = &self.bytes()[i..];
+        i + memchr2(b'\r', b'\n', rest).unwrap_or(rest.len())
+    }
+
+    fn scan_comment(&self, i: usize) -> ScanRes {
+        let kind = if self.bytes()[i..].starts_with(b";;") {
+            LexemeKind::DocComment
+        } else {
+            LexemeKind::Comment
+        };
 
         ScanRes {
-            kind: LexemeKind::Comment,
-            slice_end: iter.as_slice().as_ptr(),
+            kind,
+            end: self.find_newline(i),
         }
     }
 
-    fn advance_to_delimiter(mut iter: Iter<u8>) -> *const u8 {
-        let mut peek_iter = iter.clone();
+    fn scan_block_comment(&self, mut i: usize) -> ScanRes {
+        let mut depth = 1;
 
-        while let Some(ch) = peek_iter.next() {
-            if is_delimiter(*ch) {
-                break;
+        while let Some(offset) = memchr2(b'|', b'#', &self.bytes()[i..]) {
+            i += offset;
+
+            let ch = self.bytes()[i];
+            let next = self.byte(i + 1);
+            i += 1;
+
+            if ch == b'|' && next == Some(b'#') {
+                i += 1;
+                depth -= 1;
+
+                if depth == 0 {
+                    return ScanRes {
+                        kind: LexemeKind::BlockComment,
+                        end: i,
+                    };
+                }
+            } else if ch == b'#' && next == Some(b'|') {
+                i += 1;
+                depth += 1;
             }
+        }
 
-            iter = peek_iter.clone();
+        ScanRes {
+            kind: LexemeKind::Error(ScanErrorKind::UnterminatedBlockComment),
+            end: self.src.len(),
         }
+    }
 
-        iter.as_slice().as_ptr()
+    fn advance_to_delimiter(&self, i: usize) -> usize {
+        self.advance_while(i, |ch| !is_delimiter(ch))
     }
 
-    fn scan_identifier_continue(iter: Iter<u8>) -> ScanRes {
-        let slice_end = Scanner::advance_to_delimiter(iter);
+    fn scan_identifier(&self, i: usize) -> ScanRes {
+        let bytes = &self.bytes()[i..];
+
+        if decode_char(bytes).is_none() {
+            let len = 1 + bytes[1..]
+                .iter()
+                .take(3)
+                .take_while(|ch| is_utf8_continuation(**ch))
+                .count();
+
+            return ScanRes {
+                kind: LexemeKind::Error(ScanErrorKind::InvalidBytes),
+                end: i + len,
+            };
+        }
+
+        self.scan_identifier_continue(i)
+    }
+
+    fn scan_identifier_continue(&self, i: usize) -> ScanRes {
+        // callers may hand over a position inside the character that ended
+        // their own scan
+        let mut i = self.advance_while(i, is_utf8_continuation);
+
+        while let Some(ch) = self.byte(i) {
+            if ch.is_ascii() {
+                if is_delimiter(ch) {
+                    break;
+                }
+
+                i += 1;
+                continue;
+            }
+
+            match decode_char(&self.bytes()[i..]) {
+                Some(ch) => i += ch.len_utf8(),
+                None => break,
+            }
+        }
+
         ScanRes {
             kind: LexemeKind::Identifier,
-            slice_end,
+            end: i,
         }
     }
 
-    fn scan_float(mut iter: Iter<u8>) -> ScanRes {
-        let mut peek_iter = iter.clone();
+    fn scan_quoted_identifier(&self, mut i: usize) -> ScanRes {
+        let mut escaping = false;
+
+        while let Some(ch) = self.byte(i) {
+            i += 1;
+
+            if escaping {
+                escaping = false;
+            } else if ch == b'\\' {
+                escaping = true;
+            } else if ch == b'|' {
+                return ScanRes {
+                    kind: LexemeKind::QuotedIdentifier,
+                    end: i,
+                };
+            }
+        }
+
+        ScanRes {
+            kind: LexemeKind::Error(ScanErrorKind::UnterminatedIdentifier),
+            end: i,
+        }
+    }
 
-        while let Some(ch) = peek_iter.next() {
-            if is_delimiter(*ch) {
+    fn scan_float(&self, mut i: usize) -> ScanRes {
+        while let Some(ch) = self.byte(i) {
+            if is_delimiter(ch) {
                 break;
             } else if !ch.is_ascii_digit() {
-                return Scanner::scan_identifier_continue(peek_iter);
+                return self.scan_identifier_continue(i + 1);
             }
 
-            iter = peek_iter.clone();
+            i += 1;
         }
 
         ScanRes {
             kind: LexemeKind::FloatLit,
-            slice_end: iter.as_slice().as_ptr(),
+            end: i,
         }
     }
 
-    fn scan_number_continue(mut iter: Iter<u8>) -> ScanRes {
-        let mut peek_iter = iter.clone();
+    fn scan_rational(&self, start: usize) -> ScanRes {
+        let mut i = start;
 
-        while let Some(ch) = peek_iter.next() {
-            if !ch.is_ascii_digit() {
+        while let Some(ch) = self.byte(i) {
+            if is_delimiter(ch) {
                 break;
+            } else if !ch.is_ascii_digit() {
+                return self.scan_identifier_continue(i + 1);
             }
 
-            iter = peek_iter.clone();
+            i += 1;
         }
 
-        peek_iter = iter.clone();
+        ScanRes {
+            kind: if i == start {
+                LexemeKind::Identifier
+            } else {
+                LexemeKind::RationalLit
+            },
+            end: i,
+        }
+    }
+
+    fn scan_number_continue(&self, i: usize) -> ScanRes {
+        let i = self.advance_while(i, |ch| ch.is_ascii_digit());
 
-        if let Some(ch) = peek_iter.next() {
-            if *ch == b'.' {
-                return Scanner::scan_float(peek_iter);
-            } else if !is_delimiter(*ch) {
-                return Scanner::scan_identifier_continue(peek_iter);
+        match self.byte(i) {
+            Some(b'.') => self.scan_float(i + 1),
+            Some(b'/') => self.scan_rational(i + 1),
+            Some(ch) if !is_delimiter(ch) => self.scan_identifier_continue(i + 1),
+            _ => ScanRes {
+                kind: LexemeKind::IntLit,
+                end: i,
+            },
+        }
+    }
+
+    fn scan_dot(&self, i: usize) -> ScanRes {
+        match self.byte(i) {
+            Some(ch) if ch.is_ascii_digit() && self.options.leading_dot_floats => {
+                self.scan_float(i + 1)
             }
+            Some(ch) if !is_delimiter(ch) => self.scan_identifier_continue(i),
+            _ => ScanRes {
+                kind: LexemeKind::Dot,
+                end: i,
+            },
+        }
+    }
+
+    fn scan_unquote(&self, i: usize) -> ScanRes {
+        if self.byte(i) == Some(b'@') {
+            return ScanRes {
+                kind: LexemeKind::UnquoteSplicing,
+                end: i + 1,
+            };
+        }
 
-            // fallthrough for delimiter
+        ScanRes {
+            kind: LexemeKind::Unquote,
+            end: i,
         }
+    }
 
+    fn scan_keyword(&self, i: usize) -> ScanRes {
         ScanRes {
-            kind: LexemeKind::IntLit,
-            slice_end: iter.as_slice().as_ptr(),
+            kind: LexemeKind::KeywordLit,
+            end: self.advance_to_delimiter(i),
         }
     }
 
-    fn scan_keyword(iter: Iter<u8>) -> ScanRes {
+    fn scan_colon(&self, i: usize) -> ScanRes {
+        let res = self.scan_identifier_continue(i);
+
+        if res.end == i {
+            return res;
+        }
+
         ScanRes {
             kind: LexemeKind::KeywordLit,
-            slice_end: Scanner::advance_to_delimiter(iter),
+            end: res.end,
         }
     }
 
-    fn scan_char(iter: Iter<u8>) -> ScanRes {
+    fn scan_char(&self, i: usize) -> ScanRes {
+        let first = match self.byte(i) {
+            Some(ch) => ch,
+            None => {
+                return ScanRes {
+                    kind: LexemeKind::Error(ScanErrorKind::InvalidChar),
+                    end: i,
+                }
+            }
+        };
+
+        // the first character is taken as is, so `#\(` and `#\ ` work
+        // a lead byte cut short at the end of the source is invalid too
+        let end = self.advance_to_delimiter((i + utf8_len(first)).min(self.src.len()));
+        let name = &self.bytes()[i..end];
+
+        let valid = (name.len() == utf8_len(first) && decode_char(name).is_some())
+            || CHAR_NAMES.iter().any(|(known, _)| known.as_bytes() == name)
+            || (name[0] == b'x' && str::from_utf8(&name[1..]).ok().and_then(hex_char).is_some());
+
         ScanRes {
-            kind: LexemeKind::CharLit,
-            slice_end: Scanner::advance_to_delimiter(iter),
+            kind: if valid {
+                LexemeKind::CharLit
+            } else {
+                LexemeKind::Error(ScanErrorKind::InvalidChar)
+            },
+            end,
         }
     }
 
-    fn scan_string_start(&mut self, iter: Iter<u8>) -> ScanRes {
-        self.mode = ScannerMode::String;
+    fn scan_string_start(&mut self, i: usize) -> ScanRes {
+        self.modes.push(ScannerMode::String);
 
         ScanRes {
             kind: LexemeKind::LString,
-            slice_end: iter.as_slice().as_ptr(),
+            end: i,
         }
     }
 
-    fn scan_string_continue(&mut self, ch: u8, mut iter: Iter<u8>) -> ScanRes {
-        let mut escaping = false;
+    fn scan_string_escape(&self, i: usize) -> ScanRes {
+        if let Some(res) = self.scan_line_continuation(i) {
+            return res;
+        }
+
+        let (valid, end) = match self.byte(i) {
+            Some(b'x') | Some(b'X') => {
+                let j = self.advance_while(i + 1, |ch| ch.is_ascii_hexdigit());
 
+                if j > i + 1 && self.byte(j) == Some(b';') {
+                    (true, j + 1)
+                } else {
+                    (false, j)
+                }
+            }
+            Some(b'u') => {
+                if self.byte(i + 1) == Some(b'{') {
+                    let j = self.advance_while(i + 2, |ch| ch.is_ascii_hexdigit());
+
+                    if j > i + 2 && self.byte(j) == Some(b'}') {
+                        (true, j + 1)
+                    } else {
+                        (false, j)
+                    }
+                } else {
+                    (false, i + 1)
+                }
+            }
+            Some(b'n') | Some(b't') | Some(b'r') | Some(b'a') | Some(b'b') | Some(b'0')
+            | Some(b'\\') | Some(b'"') | Some(b'|') => (true, i + 1),
+            Some(ch) if !is_newline_start(ch) => (false, i + utf8_len(ch)),
+            _ => (false, i),
+        };
+
+        let kind = if valid {
+            LexemeKind::StringEscape
+        } else {
+            LexemeKind::Error(ScanErrorKind::InvalidEscape)
+        };
+
+        ScanRes { kind, end }
+    }
+
+    /// `\\` followed by intraline whitespace, a line ending and more
+    /// intraline whitespace, all of which the string leaves out.
+    fn scan_line_continuation(&self, i: usize) -> Option<ScanRes> {
+        let is_intraline = |ch| ch == b' ' || ch == b'\t';
+
+        let i = self.advance_while(i, is_intraline);
+        let i = match self.byte(i)? {
+            b'\n' => i + 1,
+            b'\r' => self.scan_cr(i + 1).end,
+            _ => return None,
+        };
+
+        Some(ScanRes {
+            kind: LexemeKind::StringLineContinuation,
+            end: self.advance_while(i, is_intraline),
+        })
+    }
+
+    fn scan_string_continue(&mut self, ch: u8, i: usize) -> ScanRes {
         match ch {
             b'"' => {
-                self.mode = ScannerMode::Regular;
+                self.modes.pop();
                 return ScanRes {
                     kind: LexemeKind::RString,
-                    slice_end: iter.as_slice().as_ptr(),
+                    end: i,
                 };
             }
-            b'\r' => return Scanner::scan_cr(iter),
+            b'\\' => return self.scan_string_escape(i),
+            b'\r' => return self.scan_cr(i),
             b'\n' => {
                 return ScanRes {
                     kind: LexemeKind::NewlineLf,
-                    slice_end: iter.as_slice().as_ptr(),
+                    end: i,
                 }
             }
             _ => {}
         }
 
-        let mut peek_iter = iter.clone();
-        while let Some(ch) = peek_iter.next() {
-            if *ch == b'\\' {
-                escaping = true;
-                iter = peek_iter.clone();
-                continue;
-            }
+        let rest = &self.bytes()[i..];
+        let mut len = memchr3(b'"', b'\\', b'\n', rest).unwrap_or(rest.len());
+        if let Some(cr) = memchr(b'\r', &rest[..len]) {
+            len = cr;
+        }
 
-            if *ch == b'"' && !escaping {
+        ScanRes {
+            kind: LexemeKind::StringContent,
+            end: i + len,
+        }
+    }
+
+    fn scan_heredoc_start(&mut self, i: usize) -> ScanRes {
+        let terminator_end = self.find_newline(i);
+
+        if terminator_end == i {
+            return ScanRes {
+                kind: LexemeKind::Error(ScanErrorKind::MissingHeredocTerminator),
+                end: i,
+            };
+        }
+
+        let end = match self.byte(terminator_end) {
+            Some(b'\r') => self.scan_cr(terminator_end + 1).end,
+            Some(b'\n') => terminator_end + 1,
+            _ => {
                 return ScanRes {
-                    kind: LexemeKind::StringContent,
-                    slice_end: iter.as_slice().as_ptr(),
-                };
+                    kind: LexemeKind::Error(ScanErrorKind::UnterminatedHeredoc),
+                    end: terminator_end,
+                }
+            }
+        };
+
+        self.modes.push(ScannerMode::Heredoc);
+        self.heredoc_terminator = &self.bytes()[i..terminator_end];
+
+        ScanRes {
+            kind: LexemeKind::HeredocStart,
+            end,
+        }
+    }
+
+    fn at_heredoc_terminator(&self, i: usize) -> bool {
+        let rest = &self.bytes()[i..];
+
+        rest.starts_with(self.heredoc_terminator)
+            && rest[self.heredoc_terminator.len()..]
+                .first()
+                .is_none_or(|ch| is_newline_start(*ch))
+    }
+
+    fn scan_heredoc_continue(&mut self, mut i: usize) -> ScanRes {
+        if self.at_heredoc_terminator(i) {
+            self.modes.pop();
+
+            return ScanRes {
+                kind: LexemeKind::HeredocEnd,
+                end: i + self.heredoc_terminator.len(),
+            };
+        }
+
+        while i < self.src.len() {
+            i = self.find_newline(i);
+
+            let ch = match self.byte(i) {
+                Some(ch) => ch,
+                None => break,
+            };
+            i += 1;
+
+            if ch == b'\r' && self.byte(i) == Some(b'\n') {
+                i += 1;
             }
 
-            if is_newline_start(*ch) {
+            if self.at_heredoc_terminator(i) {
                 return ScanRes {
-                    kind: LexemeKind::StringContent,
-                    slice_end: iter.as_slice().as_ptr(),
+                    kind: LexemeKind::HeredocContent,
+                    end: i,
                 };
             }
-
-            escaping = false;
-            iter = peek_iter.clone();
         }
 
+        self.modes.pop();
+
         ScanRes {
-            kind: LexemeKind::StringContent,
-            slice_end: iter.as_slice().as_ptr(),
+            kind: LexemeKind::Error(ScanErrorKind::UnterminatedHeredoc),
+            end: i,
         }
     }
 
-    fn scan_string(mut iter: Iter<u8>) -> ScanRes {
-        let mut escaping = false;
+    fn scan_special_float(&self, i: usize) -> Option<ScanRes> {
+        let rest = &self.bytes()[i..];
 
-        while let Some(ch) = iter.next() {
-            if *ch == b'\\' {
-                escaping = true;
-                continue;
+        if !rest.starts_with(b"inf.0") && !rest.starts_with(b"nan.0") {
+            return None;
+        }
+
+        match self.byte(i + 5) {
+            Some(ch) if !is_delimiter(ch) => None,
+            _ => Some(ScanRes {
+                kind: LexemeKind::FloatLit,
+                end: i + 5,
+            }),
+        }
+    }
+
+    fn scan_sign(&self, i: usize) -> ScanRes {
+        if let Some(res) = self.scan_special_float(i) {
+            return res;
+        }
+
+        match self.byte(i) {
+            Some(ch) if ch.is_ascii_digit() => self.scan_number_continue(i + 1),
+            Some(b'.')
+                if self.options.leading_dot_floats
+                    && self.byte(i + 1).is_some_and(|ch| ch.is_ascii_digit()) =>
+            {
+                self.scan_float(i + 2)
             }
+            Some(ch) if !is_delimiter(ch) => self.scan_identifier_continue(i + 1),
+            _ => ScanRes {
+                kind: LexemeKind::Identifier,
+                end: i,
+            },
+        }
+    }
 
-            if *ch == b'"' && !escaping {
-                return ScanRes {
-                    kind: LexemeKind::StringLit,
-                    slice_end: iter.as_slice().as_ptr(),
-                };
+    /// The rest of a number literal after `#e` or `#i`, which may have a
+    /// radix prefix as well.
+    fn scan_exactness(&self, i: usize) -> ScanRes {
+        if self.byte(i) == Some(b'#') {
+            match self.byte(i + 1) {
+                Some(b'x') | Some(b'X') => return self.scan_radix(16, i + 2),
+                Some(b'o') | Some(b'O') => return self.scan_radix(8, i + 2),
+                Some(b'b') | Some(b'B') => return self.scan_radix(2, i + 2),
+                Some(b'd') | Some(b'D') => return self.scan_radix(10, i + 2),
+                _ => {}
             }
+        }
+
+        self.scan_radix(10, i)
+    }
 
-            escaping = false;
+    fn scan_radix(&self, radix: u32, mut i: usize) -> ScanRes {
+        if self.byte(i) == Some(b'#') {
+            if let Some(b'e') | Some(b'E') | Some(b'i') | Some(b'I') = self.byte(i + 1) {
+                i += 2;
+            }
+        }
+
+        if let Some(b'+') | Some(b'-') = self.byte(i) {
+            i += 1;
+        }
+
+        let mut kind = LexemeKind::IntLit;
+        let mut digits = 0;
+
+        while let Some(ch) = self.byte(i) {
+            if is_delimiter(ch) {
+                break;
+            }
+
+            if ch == b'.' && (radix == 10 || radix == 16) && kind == LexemeKind::IntLit {
+                kind = LexemeKind::FloatLit;
+            } else if ch == b'/' && kind == LexemeKind::IntLit && digits > 0 {
+                // the denominator needs digits of its own
+                kind = LexemeKind::RationalLit;
+                digits = 0;
+            } else if (ch == b'p' || ch == b'P') && radix == 16 && digits > 0 && !kind.is_error() {
+                return self.scan_hex_exponent(i + 1);
+            } else if (ch as char).is_digit(radix) {
+                digits += 1;
+            } else {
+                kind = LexemeKind::Error(ScanErrorKind::BadRadixDigit);
+            }
+
+            i += 1;
         }
 
         ScanRes {
-            kind: LexemeKind::UnterminatedString,
-            slice_end: iter.as_slice().as_ptr(),
+            kind: if digits == 0 {
+                LexemeKind::Error(ScanErrorKind::MissingDigits)
+            } else {
+                kind
+            },
+            end: i,
+        }
+    }
+
+    /// The binary exponent of a hex float, right after the `p`. It is
+    /// written in decimal.
+    fn scan_hex_exponent(&self, mut i: usize) -> ScanRes {
+        if let Some(b'+') | Some(b'-') = self.byte(i) {
+            i += 1;
+        }
+
+        let digits_end = self.advance_while(i, |ch| ch.is_ascii_digit());
+        let end = self.advance_to_delimiter(digits_end);
+
+        ScanRes {
+            kind: if digits_end > i && end == digits_end {
+                LexemeKind::FloatLit
+            } else {
+                LexemeKind::Error(ScanErrorKind::BadRadixDigit)
+            },
+            end,
+        }
+    }
+
+    fn scan_directive(&mut self, i: usize) -> ScanRes {
+        let end = self.advance_to_delimiter(i);
+
+        let kind = match &self.bytes()[i..end] {
+            b"fold-case" => {
+                self.fold_case = true;
+                LexemeKind::FoldCaseDirective
+            }
+            b"no-fold-case" => {
+                self.fold_case = false;
+                LexemeKind::NoFoldCaseDirective
+            }
+            _ => LexemeKind::Error(ScanErrorKind::UnknownDispatch),
+        };
+
+        ScanRes { kind, end }
+    }
+
+    fn scan_bytevector_open(&self, i: usize) -> ScanRes {
+        if self.bytes()[i..].starts_with(b"8(") {
+            return ScanRes {
+                kind: LexemeKind::BytevectorOpen,
+                end: i + 2,
+            };
+        }
+
+        ScanRes {
+            kind: LexemeKind::Error(ScanErrorKind::UnknownDispatch),
+            end: self.advance_to_delimiter(i),
+        }
+    }
+
+    fn scan_datum_label(&self, i: usize) -> ScanRes {
+        let end = self.advance_while(i, |ch| ch.is_ascii_digit());
+
+        match self.byte(end) {
+            Some(b'=') => ScanRes {
+                kind: LexemeKind::DatumLabel,
+                end: end + 1,
+            },
+            Some(b'#') => ScanRes {
+                kind: LexemeKind::DatumRef,
+                end: end + 1,
+            },
+            _ => ScanRes {
+                kind: LexemeKind::Error(ScanErrorKind::UnknownDispatch),
+                end: self.advance_to_delimiter(end),
+            },
+        }
+    }
+
+    fn scan_number_sign(&mut self, i: usize) -> ScanRes {
+        let ch = match self.byte(i) {
+            Some(ch) => ch,
+            None => {
+                return ScanRes {
+                    kind: LexemeKind::Error(ScanErrorKind::UnknownDispatch),
+                    end: i,
+                }
+            }
+        };
+
+        if let Some(handler) = self.dispatch.and_then(|table| table.get(ch)) {
+            if let Some(found) = handler(&self.bytes()[i..]) {
+                return ScanRes {
+                    kind: LexemeKind::Dispatch(found.tag),
+                    end: i + found.len,
+                };
+            }
         }
-    }
 
-    fn scan_sign(mut iter: Iter<u8>) -> ScanRes {
-        let ch = iter.next();
+        match ch {
+            b't' | b'f' => {
+                let end = self.advance_to_delimiter(i + 1);
 
-        ch.map_or(
-            ScanRes {
-                kind: LexemeKind::Identifier,
-                slice_end: iter.as_slice().as_ptr(),
-            },
-            |ch| {
-                if ch.is_ascii_digit() {
-                    Scanner::scan_number_continue(iter)
-                } else if is_delimiter(*ch) {
-                    ScanRes {
-                        kind: LexemeKind::Identifier,
-                        slice_end: iter.as_slice().as_ptr(),
-                    }
-                } else {
-                    Scanner::scan_identifier_continue(iter)
+                ScanRes {
+                    kind: match &self.bytes()[i..end] {
+                        b"t" | b"f" | b"true" | b"false" => LexemeKind::BoolLit,
+                        _ => LexemeKind::Error(ScanErrorKind::InvalidBoolean),
+                    },
+                    end,
                 }
+            }
+            b'\\' => self.scan_char(i + 1),
+            b':' => self.scan_keyword(i + 1),
+            b'|' if self.options.block_comments => self.scan_block_comment(i + 1),
+            b'|' => ScanRes {
+                kind: LexemeKind::Error(ScanErrorKind::UnknownDispatch),
+                end: i + 1,
             },
-        )
+            b'x' | b'X' => self.scan_radix(16, i + 1),
+            b'o' | b'O' => self.scan_radix(8, i + 1),
+            b'b' | b'B' => self.scan_radix(2, i + 1),
+            b'd' | b'D' => self.scan_radix(10, i + 1),
+            b'e' | b'E' | b'i' | b'I' => self.scan_exactness(i + 1),
+            b'u' => self.scan_bytevector_open(i + 1),
+            b'<' if self.options.heredocs && self.byte(i + 1) == Some(b'<') => {
+                self.scan_heredoc_start(i + 2)
+            }
+            b'(' => ScanRes {
+                kind: LexemeKind::VectorOpen,
+                end: i + 1,
+            },
+            b'{' => ScanRes {
+                kind: LexemeKind::SetOpen,
+                end: i + 1,
+            },
+            b';' => ScanRes {
+                kind: LexemeKind::DatumComment,
+                end: i + 1,
+            },
+            b'!' => self.scan_directive(i + 1),
+            b'0'..=b'9' => self.scan_datum_label(i),
+            _ => ScanRes {
+                kind: LexemeKind::Error(ScanErrorKind::UnknownDispatch),
+                end: self.advance_to_delimiter(i + 1),
+            },
+        }
     }
+}
 
-    fn scan_number_sign(iter: Iter<u8>) -> ScanRes {
-        let mut peek_iter = iter.clone();
+impl<'a> Scanner<'a> {
+    fn scan_lexeme(&mut self) -> Option<Lexeme<'a>> {
+        let start = self.pos;
+        let ch = self.byte(start)?;
+        let i = start + 1;
+
+        let res = match self.modes.current() {
+            ScannerMode::String => self.scan_string_continue(ch, i),
+            ScannerMode::Heredoc => self.scan_heredoc_continue(start),
+            ScannerMode::Regular => match ch {
+                b' ' | b'\t' | b'\n' | b'\r' if self.is_compressed_trivia(ch) => {
+                    self.scan_trivia(i)
+                }
+                b' ' => self.scan_whitespace(i),
+                b'\t' => self.scan_tab(i),
+                b'\r' if self.options.newlines == NewlinePolicy::Lf => ScanRes {
+                    kind: LexemeKind::Unknown,
+                    end: i,
+                },
+                b'\r' => self.scan_cr(i),
+                b';' => self.scan_comment(i),
+                b'\n' => ScanRes {
+                    kind: LexemeKind::NewlineLf,
+                    end: i,
+                },
+                b'(' => ScanRes {
+                    kind: LexemeKind::LParen,
+                    end: i,
+                },
+                b')' => ScanRes {
+                    kind: LexemeKind::RParen,
+                    end: i,
+                },
+                b'[' => ScanRes {
+                    kind: LexemeKind::LBracket,
+                    end: i,
+                },
+                b']' => ScanRes {
+                    kind: LexemeKind::RBracket,
+                    end: i,
+                },
+                b'{' => ScanRes {
+                    kind: LexemeKind::LBrace,
+                    end: i,
+                },
+                b'}' => ScanRes {
+                    kind: LexemeKind::RBrace,
+                    end: i,
+                },
+                b'\'' => ScanRes {
+                    kind: LexemeKind::Quote,
+                    end: i,
+                },
+                b'`' => ScanRes {
+                    kind: LexemeKind::Quasiquote,
+                    end: i,
+                },
+                b',' => self.scan_unquote(i),
+                b'.' => self.scan_dot(i),
+                b'"' => self.scan_string_start(i),
+                b'|' => self.scan_quoted_identifier(i),
+                b'+' | b'-' => self.scan_sign(i),
+                b'#' => self.scan_number_sign(i),
+                x if x.is_ascii_digit() => self.scan_number_continue(i),
+                b':' if self.options.leading_colon_keywords => self.scan_colon(i),
+                x if x.is_ascii_control() => ScanRes {
+                    kind: LexemeKind::Unknown,
+                    end: i,
+                },
+                _ => self.scan_identifier(start),
+            },
+        };
 
-        if let Some(ch) = peek_iter.next() {
-            match *ch {
-                b't' | b'f' => {
-                    let potential_end = peek_iter.as_slice().as_ptr();
+        let mut end = res.end.min(self.src.len());
+        let mut kind = res.kind;
 
-                    if let Some(ch) = peek_iter.next() {
-                        if !is_delimiter(*ch) {
-                            let lex_end = Scanner::advance_to_delimiter(peek_iter);
+        // every call must consume input, otherwise bytes would be lost
+        if end <= start {
+            end = (start + utf8_len(ch)).min(self.src.len());
+            kind = LexemeKind::Unknown;
+        }
 
-                            return ScanRes {
-                                kind: LexemeKind::InvalidNumberSign,
-                                slice_end: lex_end,
-                            };
-                        }
-                    }
+        let slice = match self.lexeme_str(start, end) {
+            Ok(slice) => slice,
+            Err(err) if err.valid_up_to() > 0 => {
+                end = start + err.valid_up_to();
+                self.lexeme_str(start, end).unwrap_or_default()
+            }
+            Err(err) => {
+                end = start + err.error_len().unwrap_or(end - start);
+                kind = LexemeKind::Error(ScanErrorKind::InvalidBytes);
+                ""
+            }
+        };
+        self.pos = end;
 
-                    ScanRes {
-                        kind: LexemeKind::BoolLit,
-                        slice_end: potential_end,
-                    }
-                }
-                b'\\' => Scanner::scan_char(peek_iter),
-                b':' => Scanner::scan_keyword(peek_iter),
-                _ => ScanRes {
-                    kind: LexemeKind::InvalidNumberSign,
-                    slice_end: Scanner::advance_to_delimiter(peek_iter),
-                },
+        let kind = match kind {
+            LexemeKind::Identifier if self.options.strict && !is_xid_identifier(slice) => {
+                LexemeKind::Error(ScanErrorKind::InvalidIdentifier)
             }
-        } else {
-            ScanRes {
-                kind: LexemeKind::InvalidNumberSign,
-                slice_end: iter.as_slice().as_ptr(),
+            LexemeKind::Identifier
+                if self.options.trailing_colon_keywords
+                    && slice.len() > 1
+                    && slice.ends_with(':') =>
+            {
+                LexemeKind::KeywordLit
             }
-        }
+            kind => kind,
+        };
+
+        Some(Lexeme {
+            kind,
+            slice,
+            span: Span::new(start as u32, end as u32),
+        })
     }
 }
 
@@ -392,83 +1652,34 @@ impl<'a> Iterator for Scanner<'a> {
     type Item = Lexeme<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let res = match self.mode {
-            ScannerMode::String => {
-                let mut iter = self.iter.clone();
-
-                let ch = iter.next()?;
-                Some(self.scan_string_continue(*ch, iter))
-            }
-            ScannerMode::Regular => {
-                let mut iter = self.iter.clone();
-
-                let ch = iter.next()?;
-
-                let res = match *ch {
-                    b' ' => Scanner::scan_whitespace(iter),
-                    b'\t' => Scanner::scan_tab(iter),
-                    b'\r' => Scanner::scan_cr(iter),
-                    b';' => Scanner::scan_comment(iter),
-                    b'\n' => ScanRes {
-                        kind: LexemeKind::NewlineLf,
-                        slice_end: iter.as_slice().as_ptr(),
-                    },
-                    b'(' => ScanRes {
-                        kind: LexemeKind::LParen,
-                        slice_end: iter.as_slice().as_ptr(),
-                    },
-                    b')' => ScanRes {
-                        kind: LexemeKind::RParen,
-                        slice_end: iter.as_slice().as_ptr(),
-                    },
-                    b'[' => ScanRes {
-                        kind: LexemeKind::LBracket,
-                        slice_end: iter.as_slice().as_ptr(),
-                    },
-                    b']' => ScanRes {
-                        kind: LexemeKind::RBracket,
-                        slice_end: iter.as_slice().as_ptr(),
-                    },
-                    b'{' => ScanRes {
-                        kind: LexemeKind::LBrace,
-                        slice_end: iter.as_slice().as_ptr(),
-                    },
-                    b'}' => ScanRes {
-                        kind: LexemeKind::RBrace,
-                        slice_end: iter.as_slice().as_ptr(),
-                    },
-                    //b'"' => Scanner::scan_string(iter),
-                    b'"' => self.scan_string_start(iter),
-                    b'+' | b'-' => Scanner::scan_sign(iter),
-                    b'#' => Scanner::scan_number_sign(iter),
-                    x if x.is_ascii_digit() => Scanner::scan_number_continue(iter),
-                    _ => Scanner::scan_identifier_continue(iter),
-                };
-
-                Some(res)
-            }
-        }?;
+        match self.lookahead.pop_front() {
+            Some((_, lex)) => Some(lex),
+            None => self.scan_lexeme(),
+        }
+    }
+}
 
-        let ptrs = self.iter.as_slice().as_ptr_range();
-        let len = unsafe { res.slice_end.offset_from(ptrs.start) };
+/// Checks that the lexemes of `src` cover it without gaps or overlaps and
+/// that their slices concatenate back to `src`. On failure returns the span
+/// of the first lexeme that doesn't line up.
+pub fn verify_lossless(src: &str) -> Result<(), Span> {
+    let mut pos = 0;
 
-        let lexeme_bytes: &'a [u8] =
-            unsafe { std::slice::from_raw_parts(ptrs.start, len as usize) };
-        let lexeme_str = unsafe { std::str::from_utf8_unchecked(lexeme_bytes) };
+    for lex in Scanner::new(src) {
+        let start = lex.span.start as usize;
 
-        self.iter = unsafe {
-            std::slice::from_raw_parts(
-                res.slice_end,
-                self.iter.as_slice().len() - lexeme_bytes.len(),
-            )
+        if start != pos || src.get(lex.span.range()) != Some(lex.slice) {
+            return Err(lex.span);
         }
-        .iter();
 
-        Some(Lexeme {
-            kind: res.kind,
-            slice: lexeme_str,
-        })
+        pos = lex.span.end as usize;
+    }
+
+    if pos != src.len() {
+        return Err(Span::new(pos as u32, src.len() as u32));
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -566,7 +1777,7 @@ pub mod tests {
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::NewlineLf);
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::StringContent);
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::RString);
-        
+
         scanner.next();
 
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::LString);
@@ -575,6 +1786,9 @@ pub mod tests {
 
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::LString);
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::StringContent);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::StringEscape);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::StringContent);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::StringEscape);
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::RString);
 
         scanner.next();
@@ -584,6 +1798,116 @@ pub mod tests {
         assert_eq!(scanner.next(), None);
     }
 
+    #[test]
+    fn test_string_escape() {
+        let src = r#""a\nb\t\\\"\x41;\x41\u{3bb}\u{x}\λ\"#;
+
+        let scanner = Scanner::new(src);
+
+        let lexemes = scanner.map(|lex| (lex.kind, lex.slice)).collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::LString, "\""),
+                (LexemeKind::StringContent, "a"),
+                (LexemeKind::StringEscape, "\\n"),
+                (LexemeKind::StringContent, "b"),
+                (LexemeKind::StringEscape, "\\t"),
+                (LexemeKind::StringEscape, "\\\\"),
+                (LexemeKind::StringEscape, "\\\""),
+                (LexemeKind::StringEscape, "\\x41;"),
+                (LexemeKind::Error(ScanErrorKind::InvalidEscape), "\\x41"),
+                (LexemeKind::StringEscape, "\\u{3bb}"),
+                (LexemeKind::Error(ScanErrorKind::InvalidEscape), "\\u{"),
+                (LexemeKind::StringContent, "x}"),
+                (LexemeKind::Error(ScanErrorKind::InvalidEscape), "\\λ"),
+                (LexemeKind::Error(ScanErrorKind::InvalidEscape), "\\"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_special_float() {
+        let src = "+inf.0 -inf.0 +nan.0 -nan.0 inf.0 +inf.00 +infinity (+ 1)";
+
+        let scanner = Scanner::new(src);
+
+        let lexemes = scanner
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::FloatLit, "+inf.0"),
+                (LexemeKind::FloatLit, "-inf.0"),
+                (LexemeKind::FloatLit, "+nan.0"),
+                (LexemeKind::FloatLit, "-nan.0"),
+                (LexemeKind::Identifier, "inf.0"),
+                (LexemeKind::Identifier, "+inf.00"),
+                (LexemeKind::Identifier, "+infinity"),
+                (LexemeKind::LParen, "("),
+                (LexemeKind::Identifier, "+"),
+                (LexemeKind::IntLit, "1"),
+                (LexemeKind::RParen, ")"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rational() {
+        let src = "1/3 -7/2 +10/20 1/ 1/2/3 1/x";
+
+        let scanner = Scanner::new(src);
+
+        let lexemes = scanner
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::RationalLit, "1/3"),
+                (LexemeKind::RationalLit, "-7/2"),
+                (LexemeKind::RationalLit, "+10/20"),
+                (LexemeKind::Identifier, "1/"),
+                (LexemeKind::Identifier, "1/2/3"),
+                (LexemeKind::Identifier, "1/x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heredoc() {
+        let src = "#<<END\nwith \"quotes\"\r\n  END\nEND\n#<<EOF\nEOF\n(#<<X\nnever closed\n";
+
+        let scanner = Scanner::new(src);
+
+        let lexemes = scanner.map(|lex| (lex.kind, lex.slice)).collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::HeredocStart, "#<<END\n"),
+                (LexemeKind::HeredocContent, "with \"quotes\"\r\n  END\n"),
+                (LexemeKind::HeredocEnd, "END"),
+                (LexemeKind::NewlineLf, "\n"),
+                (LexemeKind::HeredocStart, "#<<EOF\n"),
+                (LexemeKind::HeredocEnd, "EOF"),
+                (LexemeKind::NewlineLf, "\n"),
+                (LexemeKind::LParen, "("),
+                (LexemeKind::HeredocStart, "#<<X\n"),
+                (
+                    LexemeKind::Error(ScanErrorKind::UnterminatedHeredoc),
+                    "never closed\n"
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn test_char() {
         let src = "#\\a #\\space #\\person-in-suit-levitating";
@@ -594,10 +1918,66 @@ pub mod tests {
         scanner.next();
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::CharLit);
         scanner.next();
-        assert_eq!(scanner.next().unwrap().kind, LexemeKind::CharLit);
+        assert_eq!(
+            scanner.next().unwrap().kind,
+            LexemeKind::Error(ScanErrorKind::InvalidChar)
+        );
         assert_eq!(scanner.next(), None);
     }
 
+    #[test]
+    fn test_char_validation() {
+        let src = "#\\newline #\\notachar #\\x41 #\\x #\\xZZ #\\x110000 #\\( #\\  #\\λ #\\λx #\\";
+
+        let lexemes = Scanner::new(src)
+            .map(|lex| (lex.kind, lex.slice))
+            .filter(|(kind, slice)| *kind != LexemeKind::Whitespace || slice.len() > 1)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::CharLit, "#\\newline"),
+                (LexemeKind::Error(ScanErrorKind::InvalidChar), "#\\notachar"),
+                (LexemeKind::CharLit, "#\\x41"),
+                (LexemeKind::CharLit, "#\\x"),
+                (LexemeKind::Error(ScanErrorKind::InvalidChar), "#\\xZZ"),
+                (LexemeKind::Error(ScanErrorKind::InvalidChar), "#\\x110000"),
+                (LexemeKind::CharLit, "#\\("),
+                (LexemeKind::CharLit, "#\\ "),
+                (LexemeKind::CharLit, "#\\λ"),
+                (LexemeKind::Error(ScanErrorKind::InvalidChar), "#\\λx"),
+                (LexemeKind::Error(ScanErrorKind::InvalidChar), "#\\"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bool() {
+        let src = "#t #f #true #false #trueish #fals #tru(#t)";
+
+        let lexemes = Scanner::new(src)
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::BoolLit, "#t"),
+                (LexemeKind::BoolLit, "#f"),
+                (LexemeKind::BoolLit, "#true"),
+                (LexemeKind::BoolLit, "#false"),
+                (LexemeKind::Error(ScanErrorKind::InvalidBoolean), "#trueish"),
+                (LexemeKind::Error(ScanErrorKind::InvalidBoolean), "#fals"),
+                (LexemeKind::Error(ScanErrorKind::InvalidBoolean), "#tru"),
+                (LexemeKind::LParen, "("),
+                (LexemeKind::BoolLit, "#t"),
+                (LexemeKind::RParen, ")"),
+            ]
+        );
+    }
+
     #[test]
     fn test_keyword() {
         let src = "#:hello-there #: #:that-was-an-empty-one";
@@ -606,4 +1986,977 @@ pub mod tests {
 
         assert_eq!(scanner.next().unwrap().kind, LexemeKind::KeywordLit);
     }
+
+    #[test]
+    fn test_colon_keywords() {
+        let src = ":foo bar: : baz";
+        let scan = |leading, trailing| {
+            Scanner::new(src)
+                .with_options(
+                    ScannerOptions::new()
+                        .leading_colon_keywords(leading)
+                        .trailing_colon_keywords(trailing),
+                )
+                .filter(|lex| lex.kind != LexemeKind::Whitespace)
+                .map(|lex| lex.kind)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            scan(false, false),
+            vec![
+                LexemeKind::Identifier,
+                LexemeKind::Identifier,
+                LexemeKind::Identifier,
+                LexemeKind::Identifier,
+            ]
+        );
+        assert_eq!(
+            scan(true, false),
+            vec![
+                LexemeKind::KeywordLit,
+                LexemeKind::Identifier,
+                LexemeKind::Identifier,
+                LexemeKind::Identifier,
+            ]
+        );
+        assert_eq!(
+            scan(true, true),
+            vec![
+                LexemeKind::KeywordLit,
+                LexemeKind::KeywordLit,
+                LexemeKind::Identifier,
+                LexemeKind::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_doc_comment() {
+        let src = ";;; Adds two numbers\n;; regular\n;;;\n; x";
+
+        let lexemes = Scanner::new(src)
+            .filter(|lex| !matches!(lex.kind, LexemeKind::NewlineLf))
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::DocComment, ";;; Adds two numbers"),
+                (LexemeKind::Comment, ";; regular"),
+                (LexemeKind::DocComment, ";;;"),
+                (LexemeKind::Comment, "; x"),
+            ]
+        );
+        assert!(LexemeKind::DocComment.is_trivia());
+    }
+
+    #[test]
+    fn test_block_comment() {
+        let src = "#| outer #| inner |# still outer |#(#||#)#| runaway #| |#";
+
+        let mut scanner = Scanner::new(src);
+
+        let lex = scanner.next().unwrap();
+        assert_eq!(lex.kind, LexemeKind::BlockComment);
+        assert_eq!(lex.slice, "#| outer #| inner |# still outer |#");
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::LParen);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::BlockComment);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::RParen);
+        let lex = scanner.next().unwrap();
+        assert_eq!(
+            lex.kind,
+            LexemeKind::Error(ScanErrorKind::UnterminatedBlockComment)
+        );
+        assert_eq!(lex.slice, "#| runaway #| |#");
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn test_datum_comment() {
+        let src = "#;(a) #;b";
+
+        let mut scanner = Scanner::new(src);
+
+        let lex = scanner.next().unwrap();
+        assert_eq!(lex.kind, LexemeKind::DatumComment);
+        assert_eq!(lex.slice, "#;");
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::LParen);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::Identifier);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::RParen);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::Whitespace);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::DatumComment);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::Identifier);
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn test_quote() {
+        let src = "'a `(b ,c ,@d)";
+
+        let scanner = Scanner::new(src);
+
+        let kinds = scanner
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| lex.kind)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            vec![
+                LexemeKind::Quote,
+                LexemeKind::Identifier,
+                LexemeKind::Quasiquote,
+                LexemeKind::LParen,
+                LexemeKind::Identifier,
+                LexemeKind::Unquote,
+                LexemeKind::Identifier,
+                LexemeKind::UnquoteSplicing,
+                LexemeKind::Identifier,
+                LexemeKind::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_datum_label() {
+        let src = "#0=(a . #0#) #12# #3 #4x";
+
+        let lexemes = Scanner::new(src)
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::DatumLabel, "#0="),
+                (LexemeKind::LParen, "("),
+                (LexemeKind::Identifier, "a"),
+                (LexemeKind::Dot, "."),
+                (LexemeKind::DatumRef, "#0#"),
+                (LexemeKind::RParen, ")"),
+                (LexemeKind::DatumRef, "#12#"),
+                (LexemeKind::Error(ScanErrorKind::UnknownDispatch), "#3"),
+                (LexemeKind::Error(ScanErrorKind::UnknownDispatch), "#4x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_char_at_end() {
+        let kinds = Scanner::from_bytes(b"#\\\xce")
+            .map(|lex| lex.kind)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            vec![
+                LexemeKind::Error(ScanErrorKind::InvalidChar),
+                LexemeKind::Error(ScanErrorKind::InvalidBytes),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_starting_at() {
+        fn slices(scanner: Scanner<'_>) -> Vec<&str> {
+            scanner.map(|lex| lex.slice).collect()
+        }
+
+        assert_eq!(slices(Scanner::new("(a) b").starting_at(3)), vec![" ", "b"]);
+        assert_eq!(slices(Scanner::new("λ x").starting_at(1)), vec![" ", "x"]);
+        assert!(slices(Scanner::new("a").starting_at(10)).is_empty());
+    }
+
+    #[test]
+    fn test_set_open() {
+        let kinds = Scanner::new("#{1}")
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            vec![
+                (LexemeKind::SetOpen, "#{"),
+                (LexemeKind::IntLit, "1"),
+                (LexemeKind::RBrace, "}"),
+            ]
+        );
+        assert_eq!(
+            LexemeKind::SetOpen.matching_close(),
+            Some(LexemeKind::RBrace)
+        );
+    }
+
+    #[test]
+    fn test_vector() {
+        let src = "#(1 2)";
+
+        let mut scanner = Scanner::new(src);
+
+        let lex = scanner.next().unwrap();
+        assert_eq!(lex.kind, LexemeKind::VectorOpen);
+        assert_eq!(lex.slice, "#(");
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::IntLit);
+        scanner.next();
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::IntLit);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::RParen);
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn test_bytevector() {
+        let src = "#u8(0 255) #u8 #u16(";
+
+        let mut scanner = Scanner::new(src);
+
+        let lex = scanner.next().unwrap();
+        assert_eq!(lex.kind, LexemeKind::BytevectorOpen);
+        assert_eq!(lex.slice, "#u8(");
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::IntLit);
+        scanner.next();
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::IntLit);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::RParen);
+        scanner.next();
+        let lex = scanner.next().unwrap();
+        assert_eq!(lex.kind, LexemeKind::Error(ScanErrorKind::UnknownDispatch));
+        assert_eq!(lex.slice, "#u8");
+        scanner.next();
+        let lex = scanner.next().unwrap();
+        assert_eq!(lex.kind, LexemeKind::Error(ScanErrorKind::UnknownDispatch));
+        assert_eq!(lex.slice, "#u16");
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::LParen);
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn test_radix() {
+        let src = "#x1F #b1010 #o755 #d42 #x-ff #d1.5 #b102 #o #xag";
+
+        let scanner = Scanner::new(src);
+
+        let lexemes = scanner
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::IntLit, "#x1F"),
+                (LexemeKind::IntLit, "#b1010"),
+                (LexemeKind::IntLit, "#o755"),
+                (LexemeKind::IntLit, "#d42"),
+                (LexemeKind::IntLit, "#x-ff"),
+                (LexemeKind::FloatLit, "#d1.5"),
+                (LexemeKind::Error(ScanErrorKind::BadRadixDigit), "#b102"),
+                (LexemeKind::Error(ScanErrorKind::MissingDigits), "#o"),
+                (LexemeKind::Error(ScanErrorKind::BadRadixDigit), "#xag"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exactness() {
+        let src = "#e1.5 #i1/3 #e#x10 #x#i10 #i#b2 #x1/f #e #b1/";
+
+        let lexemes = Scanner::new(src)
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::FloatLit, "#e1.5"),
+                (LexemeKind::RationalLit, "#i1/3"),
+                (LexemeKind::IntLit, "#e#x10"),
+                (LexemeKind::IntLit, "#x#i10"),
+                (LexemeKind::Error(ScanErrorKind::MissingDigits), "#i#b2"),
+                (LexemeKind::RationalLit, "#x1/f"),
+                (LexemeKind::Error(ScanErrorKind::MissingDigits), "#e"),
+                (LexemeKind::Error(ScanErrorKind::MissingDigits), "#b1/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quoted_identifier() {
+        let src = "|foo bar| |a\\|b|(|(|)|x| y|unterminated";
+
+        let scanner = Scanner::new(src);
+
+        let lexemes = scanner
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::QuotedIdentifier, "|foo bar|"),
+                (LexemeKind::QuotedIdentifier, "|a\\|b|"),
+                (LexemeKind::LParen, "("),
+                (LexemeKind::QuotedIdentifier, "|(|"),
+                (LexemeKind::RParen, ")"),
+                (LexemeKind::QuotedIdentifier, "|x|"),
+                (LexemeKind::Identifier, "y"),
+                (
+                    LexemeKind::Error(ScanErrorKind::UnterminatedIdentifier),
+                    "|unterminated"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unicode_identifier() {
+        let src = "λ 日本語 1λ a→b x²";
+
+        let lexemes = Scanner::new(src)
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::Identifier, "λ"),
+                (LexemeKind::Identifier, "日本語"),
+                (LexemeKind::Identifier, "1λ"),
+                (LexemeKind::Identifier, "a→b"),
+                (LexemeKind::Identifier, "x²"),
+            ]
+        );
+
+        let kinds = Scanner::new(src)
+            .with_options(ScannerOptions::new().strict(true))
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| lex.kind)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            vec![
+                LexemeKind::Identifier,
+                LexemeKind::Identifier,
+                LexemeKind::Identifier,
+                LexemeKind::Error(ScanErrorKind::InvalidIdentifier),
+                LexemeKind::Error(ScanErrorKind::InvalidIdentifier),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let src = b"(ab\xffc \"x\xc3y\" \xe6\x97; \xff\xfe\n\xf0";
+
+        let lexemes = Scanner::from_bytes(src)
+            .map(|lex| (lex.kind, lex.slice, lex.span.start, lex.span.end))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::LParen, "(", 0, 1),
+                (LexemeKind::Identifier, "ab", 1, 3),
+                (LexemeKind::Error(ScanErrorKind::InvalidBytes), "", 3, 4),
+                (LexemeKind::Identifier, "c", 4, 5),
+                (LexemeKind::Whitespace, " ", 5, 6),
+                (LexemeKind::LString, "\"", 6, 7),
+                (LexemeKind::StringContent, "x", 7, 8),
+                (LexemeKind::Error(ScanErrorKind::InvalidBytes), "", 8, 9),
+                (LexemeKind::StringContent, "y", 9, 10),
+                (LexemeKind::RString, "\"", 10, 11),
+                (LexemeKind::Whitespace, " ", 11, 12),
+                (LexemeKind::Error(ScanErrorKind::InvalidBytes), "", 12, 14),
+                (LexemeKind::Comment, "; ", 14, 16),
+                (LexemeKind::Error(ScanErrorKind::InvalidBytes), "", 16, 17),
+                (LexemeKind::Error(ScanErrorKind::InvalidBytes), "", 17, 18),
+                (LexemeKind::NewlineLf, "\n", 18, 19),
+                (LexemeKind::Error(ScanErrorKind::InvalidBytes), "", 19, 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relex() {
+        let src = "(def x \"a string\") ; comment\n#<<END\nbody\nEND\n(+ 1 2)";
+        let tokens = TokenBuffer::new(src.to_string());
+
+        let edits: &[(Range<usize>, &str)] = &[
+            (0..0, "  "),
+            (5..6, "longer-name"),
+            (9..10, "\" \""),
+            (8..9, ""),
+            (18..19, ""),
+            (src.len()..src.len(), ")"),
+            (
+                src.find("body").unwrap()..src.find("body").unwrap() + 1,
+                "END\nB",
+            ),
+            (
+                src.find("END\n(").unwrap()..src.find("END\n(").unwrap() + 1,
+                "X",
+            ),
+            (src.find("; c").unwrap()..src.find("; c").unwrap() + 1, "#|"),
+        ];
+
+        for (range, text) in edits {
+            let relexed = Scanner::relex(range.clone(), text, &tokens).unwrap();
+
+            let mut expected_src = src.to_string();
+            expected_src.replace_range(range.clone(), text);
+            let expected = TokenBuffer::new(expected_src);
+
+            assert_eq!(relexed, expected, "edit {:?} -> {:?}", range, text);
+        }
+
+        assert_eq!(Scanner::relex(0..src.len() + 1, "", &tokens), None);
+        #[allow(clippy::reversed_empty_ranges)]
+        let backwards = 3..2;
+        assert_eq!(Scanner::relex(backwards, "", &tokens), None);
+        let tokens = TokenBuffer::new("\"λ\"".to_string());
+        assert_eq!(Scanner::relex(2..3, "", &tokens), None);
+    }
+
+    #[test]
+    fn test_relex_options() {
+        let options = ScannerOptions::new().leading_colon_keywords(true);
+        let tokens = TokenBuffer::with_options("(a b)".to_string(), options);
+
+        let relexed = Scanner::relex(3..4, ":c", &tokens).unwrap();
+        assert_eq!(relexed.options(), options);
+        assert_eq!(
+            relexed,
+            TokenBuffer::with_options("(a :c)".to_string(), options)
+        );
+        assert_eq!(relexed.tokens()[3].kind, LexemeKind::KeywordLit);
+    }
+
+    #[test]
+    fn test_peek() {
+        let src = "(a \"b\")";
+
+        let mut scanner = Scanner::new(src);
+
+        assert_eq!(scanner.peek().unwrap().kind, LexemeKind::LParen);
+        assert_eq!(scanner.peek_nth(3).unwrap().kind, LexemeKind::LString);
+        assert_eq!(scanner.peek_nth(4).unwrap().kind, LexemeKind::StringContent);
+        assert_eq!(scanner.mode(), ScannerMode::Regular);
+        assert_eq!(scanner.remaining(), src);
+        assert_eq!(scanner.position(), 0);
+
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::LParen);
+        assert_eq!(scanner.remaining(), "a \"b\")");
+        assert_eq!(scanner.position(), 1);
+        scanner.nth(2);
+        assert_eq!(scanner.mode(), ScannerMode::String);
+        assert_eq!(scanner.mode_depth(), 2);
+        assert_eq!(scanner.peek().unwrap().slice, "b");
+        assert_eq!(scanner.peek_nth(10), None);
+        assert_eq!(scanner.next().unwrap().slice, "b");
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::RString);
+        assert_eq!(scanner.next().unwrap().kind, LexemeKind::RParen);
+        assert_eq!(scanner.peek(), None);
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let src = "a \"b\" #<<E\nx\nE\n#<<F\ny\nF";
+
+        let mut scanner = Scanner::new(src);
+        let start = scanner.checkpoint();
+        let all = scanner.by_ref().collect::<Vec<_>>();
+
+        scanner.rewind(start);
+        assert_eq!(scanner.by_ref().collect::<Vec<_>>(), all);
+
+        for i in 0..all.len() {
+            scanner.rewind(start);
+            scanner.nth(i);
+            let checkpoint = scanner.checkpoint();
+            let rest = scanner.by_ref().collect::<Vec<_>>();
+            assert_eq!(rest, &all[i + 1..]);
+
+            scanner.rewind(checkpoint);
+            scanner.peek_nth(all.len());
+            assert_eq!(scanner.checkpoint(), checkpoint);
+            scanner.rewind(checkpoint);
+            assert_eq!(scanner.by_ref().collect::<Vec<_>>(), rest);
+        }
+    }
+
+    #[test]
+    fn test_mode_stack() {
+        let mut modes = ModeStack::new();
+
+        assert_eq!(modes.depth(), 1);
+        assert_eq!(modes.pop(), None);
+        assert_eq!(modes.current(), ScannerMode::Regular);
+
+        assert!(modes.push(ScannerMode::String));
+        assert!(modes.push(ScannerMode::Regular));
+        assert_eq!(modes.depth(), 3);
+        assert_eq!(modes.pop(), Some(ScannerMode::Regular));
+        assert_eq!(modes.current(), ScannerMode::String);
+
+        while modes.push(ScannerMode::Heredoc) {}
+        assert_eq!(modes.depth(), MAX_MODE_DEPTH);
+        assert_eq!(modes.current(), ScannerMode::Heredoc);
+    }
+
+    #[test]
+    fn test_kind_classification() {
+        assert!(LexemeKind::Comment.is_trivia());
+        assert!(LexemeKind::NewlineCrlf.is_trivia());
+        assert!(!LexemeKind::DatumComment.is_trivia());
+        assert!(!LexemeKind::Identifier.is_trivia());
+
+        assert!(LexemeKind::VectorOpen.is_open_delimiter());
+        assert!(!LexemeKind::RParen.is_open_delimiter());
+        assert!(LexemeKind::RBrace.is_close_delimiter());
+        assert!(!LexemeKind::LString.is_close_delimiter());
+
+        assert_eq!(
+            LexemeKind::LParen.matching_close(),
+            Some(LexemeKind::RParen)
+        );
+        assert_eq!(
+            LexemeKind::BytevectorOpen.matching_close(),
+            Some(LexemeKind::RParen)
+        );
+        assert_eq!(
+            LexemeKind::LBracket.matching_close(),
+            Some(LexemeKind::RBracket)
+        );
+        assert_eq!(
+            LexemeKind::LBrace.matching_close(),
+            Some(LexemeKind::RBrace)
+        );
+        assert_eq!(LexemeKind::Quote.matching_close(), None);
+
+        assert!(LexemeKind::Error(ScanErrorKind::UnterminatedHeredoc).is_error());
+        assert!(LexemeKind::Error(ScanErrorKind::InvalidBytes).is_error());
+        assert!(!LexemeKind::StringEscape.is_error());
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let errors = Scanner::new("#q #b102 #x #\\bogus \"\\q\"")
+            .filter_map(|lex| lex.kind.error())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                ScanErrorKind::UnknownDispatch,
+                ScanErrorKind::BadRadixDigit,
+                ScanErrorKind::MissingDigits,
+                ScanErrorKind::InvalidChar,
+                ScanErrorKind::InvalidEscape,
+            ]
+        );
+
+        assert_eq!(
+            ScanErrorKind::UnterminatedString.to_string(),
+            "unterminated string"
+        );
+    }
+
+    #[test]
+    fn test_dispatch() {
+        fn regex(src: &[u8]) -> Option<DispatchMatch> {
+            if !src.starts_with(b"rx\"") {
+                return None;
+            }
+
+            let close = src[3..].iter().position(|ch| *ch == b'"')?;
+            Some(DispatchMatch {
+                len: close + 4,
+                tag: 1,
+            })
+        }
+
+        fn shadow_bool(_: &[u8]) -> Option<DispatchMatch> {
+            Some(DispatchMatch { len: 1, tag: 2 })
+        }
+
+        let mut table = DispatchTable::new();
+        table.register(b'r', regex).register(b't', shadow_bool);
+
+        let src = "#rx\"a+b\" #rest #t #f";
+
+        let lexemes = Scanner::new(src)
+            .with_dispatch(&table)
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::Dispatch(1), "#rx\"a+b\""),
+                (LexemeKind::Error(ScanErrorKind::UnknownDispatch), "#rest"),
+                (LexemeKind::Dispatch(2), "#t"),
+                (LexemeKind::BoolLit, "#f"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_len() {
+        assert_eq!(run_len(b"", b' '), 0);
+        assert_eq!(run_len(b"   x", b' '), 3);
+        assert_eq!(run_len(b"        ", b' '), 8);
+        assert_eq!(run_len(b"          \t  ", b' '), 10);
+        assert_eq!(run_len(&[b'\t'; 37], b'\t'), 37);
+        assert_eq!(run_len(b"x       ", b' '), 0);
+    }
+
+    #[test]
+    fn test_dot() {
+        let src = "(a . b) ... .foo (.)";
+
+        let lexemes = Scanner::new(src)
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::LParen, "("),
+                (LexemeKind::Identifier, "a"),
+                (LexemeKind::Dot, "."),
+                (LexemeKind::Identifier, "b"),
+                (LexemeKind::RParen, ")"),
+                (LexemeKind::Identifier, "..."),
+                (LexemeKind::Identifier, ".foo"),
+                (LexemeKind::LParen, "("),
+                (LexemeKind::Dot, "."),
+                (LexemeKind::RParen, ")"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_span() {
+        let src = "(def x \"hi\")";
+
+        let mut scanner = Scanner::new(src);
+
+        let lexemes = scanner.by_ref().collect::<Vec<_>>();
+        let spans = lexemes
+            .iter()
+            .map(|lex| (lex.span.start, lex.span.end))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            spans,
+            vec![
+                (0, 1),
+                (1, 4),
+                (4, 5),
+                (5, 6),
+                (6, 7),
+                (7, 8),
+                (8, 10),
+                (10, 11),
+                (11, 12)
+            ]
+        );
+
+        for lex in lexemes {
+            assert_eq!(&src[lex.span.range()], lex.slice);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let src = "(foo #\\x \"\\q\")";
+
+        let lexemes = Scanner::new(src).collect::<Vec<_>>();
+        let json = serde_json::to_string(&lexemes).unwrap();
+
+        assert!(json.starts_with(r#"[{"kind":"LParen","text":"(","span":{"start":0,"end":1}}"#));
+        assert!(json.contains(r#"{"kind":{"Error":"InvalidEscape"}"#));
+
+        let owned = Scanner::new("(foo bar)").collect::<Vec<_>>();
+        let json = serde_json::to_string(&owned).unwrap();
+        let back: Vec<Lexeme> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, owned);
+    }
+
+    #[test]
+    fn test_unknown() {
+        let lexemes = Scanner::new("a\u{0}b \u{7f}12\u{c}")
+            .map(|lex| (lex.kind, lex.slice))
+            .filter(|(kind, _)| *kind != LexemeKind::Whitespace)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::Identifier, "a"),
+                (LexemeKind::Unknown, "\u{0}"),
+                (LexemeKind::Identifier, "b"),
+                (LexemeKind::Unknown, "\u{7f}"),
+                (LexemeKind::IntLit, "12"),
+                (LexemeKind::Unknown, "\u{c}"),
+            ]
+        );
+        assert!(LexemeKind::Unknown.is_error());
+    }
+
+    #[test]
+    fn test_lossless() {
+        let sources = [
+            "",
+            "(define (f x) (+ x 1)) ; done\r\n",
+            "#(1 2.5 -3/4 +inf.0) #u8(0 255) #x-ff #b102",
+            "\"a\\n\\x41;\\u{3bb}\\q\" \"unterminated",
+            "#| nested #| block |# |# #;(skip) ;;; doc\n",
+            "#<<EOF\nbody\nEOF\n#<<",
+            "#\\space #\\x41 #\\λ #t #false #:kw |quoted id| `(a ,b ,@c) . x",
+            "\u{0}\u{1b}[0m\u{7f} λ→ #\\ #",
+        ];
+
+        for src in &sources {
+            assert_eq!(verify_lossless(src), Ok(()), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_options() {
+        let kinds = |src, options| {
+            Scanner::new(src)
+                .with_options(options)
+                .filter(|lex| lex.kind != LexemeKind::Whitespace)
+                .map(|lex| lex.kind)
+                .collect::<Vec<_>>()
+        };
+        let restricted = ScannerOptions::new()
+            .block_comments(false)
+            .heredocs(false)
+            .newlines(NewlinePolicy::Lf);
+
+        assert_eq!(
+            kinds("#| c |#", ScannerOptions::new()),
+            vec![LexemeKind::BlockComment]
+        );
+        assert_eq!(
+            kinds("#|c", restricted),
+            vec![
+                LexemeKind::Error(ScanErrorKind::UnknownDispatch),
+                LexemeKind::Identifier,
+            ]
+        );
+
+        assert_eq!(
+            kinds("#<<EOF\nEOF\r\n", ScannerOptions::new()),
+            vec![
+                LexemeKind::HeredocStart,
+                LexemeKind::HeredocEnd,
+                LexemeKind::NewlineCrlf,
+            ]
+        );
+        assert_eq!(
+            kinds("#<<EOF\nEOF\r\n", restricted),
+            vec![
+                LexemeKind::Error(ScanErrorKind::UnknownDispatch),
+                LexemeKind::NewlineLf,
+                LexemeKind::Identifier,
+                LexemeKind::Unknown,
+                LexemeKind::NewlineLf,
+            ]
+        );
+
+        assert_eq!(
+            Scanner::new("").with_options(restricted).options(),
+            restricted
+        );
+    }
+
+    #[test]
+    fn test_leading_dot_float() {
+        let src = ".5 -.25 +.0 .5x . .. -. .a";
+        let lexemes = |options| {
+            Scanner::new(src)
+                .with_options(options)
+                .filter(|lex| lex.kind != LexemeKind::Whitespace)
+                .map(|lex| (lex.kind, lex.slice))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            lexemes(ScannerOptions::new()),
+            vec![
+                (LexemeKind::FloatLit, ".5"),
+                (LexemeKind::FloatLit, "-.25"),
+                (LexemeKind::FloatLit, "+.0"),
+                (LexemeKind::Identifier, ".5x"),
+                (LexemeKind::Dot, "."),
+                (LexemeKind::Identifier, ".."),
+                (LexemeKind::Identifier, "-."),
+                (LexemeKind::Identifier, ".a"),
+            ]
+        );
+
+        let kinds = lexemes(ScannerOptions::new().leading_dot_floats(false))
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect::<Vec<_>>();
+        assert_eq!(&kinds[..4], &[LexemeKind::Identifier; 4]);
+    }
+
+    #[test]
+    fn test_hex_float() {
+        let src = "#x1.8p3 #x-1p-2 #X.8P+1 #x1.8 #x1.8p #x1p3x #xp3 #x1.2.3 #d1.5";
+
+        let lexemes = Scanner::new(src)
+            .filter(|lex| lex.kind != LexemeKind::Whitespace)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::FloatLit, "#x1.8p3"),
+                (LexemeKind::FloatLit, "#x-1p-2"),
+                (LexemeKind::FloatLit, "#X.8P+1"),
+                (LexemeKind::FloatLit, "#x1.8"),
+                (LexemeKind::Error(ScanErrorKind::BadRadixDigit), "#x1.8p"),
+                (LexemeKind::Error(ScanErrorKind::BadRadixDigit), "#x1p3x"),
+                (LexemeKind::Error(ScanErrorKind::BadRadixDigit), "#xp3"),
+                (LexemeKind::Error(ScanErrorKind::BadRadixDigit), "#x1.2.3"),
+                (LexemeKind::FloatLit, "#d1.5"),
+            ]
+        );
+
+        let radixes = lexemes
+            .iter()
+            .map(|(_, slice)| literal_radix(slice))
+            .collect::<Vec<_>>();
+        assert_eq!(radixes, vec![16, 16, 16, 16, 16, 16, 16, 16, 10]);
+        assert_eq!(literal_radix("#o17"), 8);
+        assert_eq!(literal_radix("#b1"), 2);
+        assert_eq!(literal_radix("-12"), 10);
+    }
+
+    #[test]
+    fn test_string_line_continuation() {
+        let src = "\"a\\\n   b\\  \r\n\tc\\\rd\\ e\"";
+
+        let lexemes = Scanner::new(src)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::LString, "\""),
+                (LexemeKind::StringContent, "a"),
+                (LexemeKind::StringLineContinuation, "\\\n   "),
+                (LexemeKind::StringContent, "b"),
+                (LexemeKind::StringLineContinuation, "\\  \r\n\t"),
+                (LexemeKind::StringContent, "c"),
+                (LexemeKind::StringLineContinuation, "\\\r"),
+                (LexemeKind::StringContent, "d"),
+                (LexemeKind::Error(ScanErrorKind::InvalidEscape), "\\ "),
+                (LexemeKind::StringContent, "e"),
+                (LexemeKind::RString, "\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_case() {
+        let src = "Foo #!fold-case Bar #!no-fold-case Baz #!other";
+
+        let mut scanner = Scanner::new(src);
+        let mut folded = Vec::new();
+
+        while let Some(lex) = scanner.next() {
+            match lex.kind {
+                LexemeKind::Identifier if scanner.fold_case() => {
+                    folded.push(fold_identifier(lex.slice).into_owned())
+                }
+                LexemeKind::Identifier => folded.push(lex.slice.to_string()),
+                LexemeKind::Whitespace => {}
+                kind => folded.push(format!("{:?}", kind)),
+            }
+        }
+
+        assert_eq!(
+            folded,
+            vec![
+                "Foo",
+                "FoldCaseDirective",
+                "bar",
+                "NoFoldCaseDirective",
+                "Baz",
+                "Error(UnknownDispatch)",
+            ]
+        );
+
+        let mut scanner =
+            Scanner::new("#!fold-case x").with_options(ScannerOptions::new().fold_case(false));
+        let start = scanner.checkpoint();
+        scanner.next();
+        assert!(scanner.fold_case());
+        assert!(scanner.peek().is_some());
+        scanner.rewind(start);
+        assert!(!scanner.fold_case());
+
+        let scanner = Scanner::new("").with_options(ScannerOptions::new().fold_case(true));
+        assert!(scanner.fold_case());
+        assert_eq!(fold_identifier("ΑΒΓ"), "αβγ");
+    }
+
+    #[test]
+    fn test_compress_trivia() {
+        let src = "(a  \t\r\n b) ;c\n\n d";
+        let options = ScannerOptions::new().compress_trivia(true);
+
+        let lexemes = Scanner::new(src)
+            .with_options(options)
+            .map(|lex| (lex.kind, lex.slice))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lexemes,
+            vec![
+                (LexemeKind::LParen, "("),
+                (LexemeKind::Identifier, "a"),
+                (LexemeKind::Trivia, "  \t\r\n "),
+                (LexemeKind::Identifier, "b"),
+                (LexemeKind::RParen, ")"),
+                (LexemeKind::Trivia, " "),
+                (LexemeKind::Comment, ";c"),
+                (LexemeKind::Trivia, "\n\n "),
+                (LexemeKind::Identifier, "d"),
+            ]
+        );
+        assert!(LexemeKind::Trivia.is_trivia());
+
+        let kinds = Scanner::new("a \r\nb")
+            .with_options(options.newlines(NewlinePolicy::Lf))
+            .map(|lex| lex.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                LexemeKind::Identifier,
+                LexemeKind::Trivia,
+                LexemeKind::Unknown,
+                LexemeKind::Trivia,
+                LexemeKind::Identifier,
+            ]
+        );
+    }
 }
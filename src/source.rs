@@ -0,0 +1,415 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::num::NonZeroU32;
+
+use crate::diagnostics::Diagnostic;
+use crate::error::ReadError;
+use crate::reader::Reader;
+use crate::span::{FileSpan, Span};
+
+/// Identifies one source file among several scanned or read together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineCol {
+    pub line: NonZeroU32,
+    pub column: NonZeroU32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WideChar {
+    offset: u32,
+    len: u32,
+}
+
+/// Maps byte offsets into a source text to 1-based line and column pairs.
+///
+/// `\n`, `\r\n` and a lone `\r` all terminate a line, matching the newline
+/// lexemes produced by the scanner. Columns count characters, not bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LineIndex {
+    line_starts: Vec<u32>,
+    wide_chars: Vec<WideChar>,
+    len: u32,
+}
+
+impl LineIndex {
+    pub fn new(src: &str) -> Self {
+        LineIndex::from_bytes(src.as_bytes())
+    }
+
+    pub fn from_bytes(src: &[u8]) -> Self {
+        let mut line_starts = vec![0];
+        let mut wide_chars = Vec::new();
+
+        let mut i = 0;
+        while i < src.len() {
+            let ch = src[i];
+            match ch {
+                b'\n' => line_starts.push(i as u32 + 1),
+                b'\r' => {
+                    if src.get(i + 1) == Some(&b'\n') {
+                        i += 1;
+                    }
+                    line_starts.push(i as u32 + 1);
+                }
+                x if x >= 0x80 => {
+                    let start = i;
+                    while src.get(i + 1).is_some_and(|b| b & 0xc0 == 0x80) {
+                        i += 1;
+                    }
+                    wide_chars.push(WideChar {
+                        offset: start as u32,
+                        len: (i - start + 1) as u32,
+                    });
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        LineIndex {
+            line_starts,
+            wide_chars,
+            len: src.len() as u32,
+        }
+    }
+
+    pub fn line_count(&self) -> u32 {
+        self.line_starts.len() as u32
+    }
+
+    /// Byte offset at which the 1-based `line` starts.
+    pub fn line_start(&self, line: NonZeroU32) -> Option<u32> {
+        self.line_starts.get(line.get() as usize - 1).copied()
+    }
+
+    /// Offsets past the end of the source are clamped to its length, and
+    /// offsets inside a multibyte character resolve to that character.
+    pub fn line_col(&self, offset: u32) -> LineCol {
+        let offset = offset.min(self.len);
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let line_start = self.line_starts[line];
+
+        let first = self
+            .wide_chars
+            .partition_point(|wide| wide.offset < line_start);
+        let mut column = offset - line_start;
+        for wide in &self.wide_chars[first..] {
+            if wide.offset >= offset {
+                break;
+            }
+            column -= (wide.len - 1).min(offset - wide.offset);
+        }
+
+        LineCol {
+            line: NonZeroU32::new(line as u32 + 1).unwrap(),
+            column: NonZeroU32::new(column + 1).unwrap(),
+        }
+    }
+}
+
+/// Owns the source texts read together and hands out the `FileId`s that
+/// tell their spans apart.
+#[derive(Debug, Clone, Default)]
+pub struct SourceManager {
+    files: Vec<SourceFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    name: String,
+    text: String,
+    line_index: LineIndex,
+    /// The parts of a virtual file, in order.
+    fragments: Vec<Fragment>,
+}
+
+/// Where one part of a virtual file starts in it, and the file it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fragment {
+    start: u32,
+    file: FileId,
+}
+
+impl SourceFile {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_index(&self) -> &LineIndex {
+        &self.line_index
+    }
+
+    /// Whether this was added with `SourceManager::add_virtual`.
+    pub fn is_virtual(&self) -> bool {
+        !self.fragments.is_empty()
+    }
+}
+
+impl SourceManager {
+    pub fn new() -> Self {
+        SourceManager::default()
+    }
+
+    /// Takes ownership of `text`. `name` is only used to describe locations,
+    /// so it needn't be a path or unique.
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> FileId {
+        let text = text.into();
+        let id = FileId(self.files.len() as u32);
+
+        self.files.push(SourceFile {
+            name: name.into(),
+            line_index: LineIndex::new(&text),
+            text,
+            fragments: Vec::new(),
+        });
+        id
+    }
+
+    /// Adds a file made of the texts of `parts` one after the other, like a
+    /// prelude and the code using it or the entries of a REPL session. Spans
+    /// in it resolve to the part they fall in, see `resolve`. Nothing is
+    /// put between the parts, so datums at their ends can run together.
+    ///
+    /// Returns `None` if a part isn't one of these files.
+    pub fn add_virtual(
+        &mut self,
+        name: impl Into<String>,
+        parts: impl IntoIterator<Item = FileId>,
+    ) -> Option<FileId> {
+        let mut text = String::new();
+        let mut fragments = Vec::new();
+
+        for file in parts {
+            let start = text.len() as u32;
+            text.push_str(&self.get(file)?.text);
+            fragments.push(Fragment { start, file });
+        }
+
+        let id = self.add(name, text);
+        self.files[id.0 as usize].fragments = fragments;
+        Some(id)
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file.0 as usize)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        (0..).map(FileId).zip(&self.files)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// A reader for `file` that marks its errors with it, or `None` if
+    /// `file` isn't one of these.
+    pub fn reader(&self, file: FileId) -> Option<Reader<'_>> {
+        let source = self.get(file)?;
+        Some(Reader::new(&source.text).with_file(file))
+    }
+
+    /// The span in the part of a virtual file that `span` falls in, through
+    /// as many virtual files as it takes to reach one that isn't. A span
+    /// running past the end of its part is cut short there.
+    pub fn resolve(&self, mut span: FileSpan) -> FileSpan {
+        while let Some(source) = self.get(span.file) {
+            let fragment = match source
+                .fragments
+                .iter()
+                .rposition(|fragment| fragment.start <= span.span.start)
+            {
+                Some(i) => source.fragments[i],
+                None => break,
+            };
+            let len = self
+                .get(fragment.file)
+                .map_or(0, |part| part.text.len() as u32);
+            let end = (span.span.end - fragment.start).min(len);
+            let start = (span.span.start - fragment.start).min(end);
+
+            span = FileSpan::new(fragment.file, Span::new(start, end));
+        }
+
+        span
+    }
+
+    /// Describes `err` as `name:line:column: message`, or without the
+    /// location if it isn't marked with one of these files.
+    pub fn error_message(&self, err: &ReadError) -> String {
+        match self.location(err.file, err.span) {
+            Some(location) => format!("{}: {}", location, err.kind),
+            None => format!("{}", err.kind),
+        }
+    }
+
+    /// Describes `diagnostic` like `error_message` does errors, as
+    /// `name:line:column: severity[code]: message`, with a line for each of
+    /// its labels after that.
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut out = String::new();
+
+        if let Some(location) = self.location(diagnostic.file, diagnostic.span) {
+            out.push_str(&location);
+            out.push_str(": ");
+        }
+        out.push_str(&format!(
+            "{}[{}]: {}",
+            diagnostic.severity, diagnostic.code, diagnostic.message
+        ));
+
+        for label in &diagnostic.labels {
+            out.push('\n');
+            if let Some(location) = self.location(diagnostic.file, label.span) {
+                out.push_str(&location);
+                out.push_str(": ");
+            }
+            out.push_str(&format!("note: {}", label.message));
+        }
+
+        out
+    }
+
+    /// `name:line:column` of the start of `span`, in the part of a virtual
+    /// file it falls in.
+    fn location(&self, file: Option<FileId>, span: Span) -> Option<String> {
+        let span = self.resolve(FileSpan::new(file?, span));
+        let source = self.get(span.file)?;
+        let pos = source.line_index.line_col(span.span.start);
+
+        Some(format!("{}:{}:{}", source.name, pos.line, pos.column))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn lc(index: &LineIndex, offset: u32) -> (u32, u32) {
+        let pos = index.line_col(offset);
+        (pos.line.get(), pos.column.get())
+    }
+
+    #[test]
+    fn test_line_col() {
+        let index = LineIndex::new("ab\ncd\r\nef\rg");
+
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(lc(&index, 0), (1, 1));
+        assert_eq!(lc(&index, 2), (1, 3));
+        assert_eq!(lc(&index, 3), (2, 1));
+        assert_eq!(lc(&index, 5), (2, 3));
+        assert_eq!(lc(&index, 6), (2, 4));
+        assert_eq!(lc(&index, 7), (3, 1));
+        assert_eq!(lc(&index, 10), (4, 1));
+        assert_eq!(lc(&index, 11), (4, 2));
+        assert_eq!(lc(&index, 100), (4, 2));
+    }
+
+    #[test]
+    fn test_source_manager() {
+        let mut sources = SourceManager::new();
+        let a = sources.add("a.scm", "(a b)");
+        let b = sources.add("b.scm", "x\n  (c");
+
+        assert_eq!((a, b), (FileId(0), FileId(1)));
+        assert_eq!(sources.get(b).unwrap().name(), "b.scm");
+        assert!(sources.get(FileId(2)).is_none());
+        assert_eq!(
+            sources.files().map(|(id, _)| id).collect::<Vec<_>>(),
+            [a, b]
+        );
+
+        assert!(sources.reader(a).unwrap().read_all().is_ok());
+
+        let mut reader = sources.reader(b).unwrap();
+        reader.read_one().unwrap();
+        let err = reader.read_one().unwrap_err();
+        assert_eq!(err.file, Some(b));
+        assert_eq!(
+            err.file_span().map(<(FileId, core::ops::Range<u32>)>::from),
+            Some((b, 4..5))
+        );
+        assert_eq!(sources.error_message(&err), "b.scm:2:3: unterminated list");
+
+        let (_, errors) = sources.reader(b).unwrap().read_all_with_recovery();
+        assert_eq!(errors[0].file, Some(b));
+    }
+
+    #[test]
+    fn test_virtual() {
+        let mut sources = SourceManager::new();
+        let prelude = sources.add("prelude.scm", "(define x 1)\n(a\n");
+        let user = sources.add("user.scm", "b\n  c]");
+        let all = sources.add_virtual("<all>", vec![prelude, user]).unwrap();
+
+        assert!(sources.get(all).unwrap().is_virtual());
+        assert_eq!(
+            sources.get(all).unwrap().text(),
+            "(define x 1)\n(a\nb\n  c]"
+        );
+        assert_eq!(
+            sources.resolve(FileSpan::new(all, Span::new(8, 9))),
+            FileSpan::new(prelude, Span::new(8, 9))
+        );
+        assert_eq!(
+            sources.resolve(FileSpan::new(all, Span::new(13, 24))),
+            FileSpan::new(prelude, Span::new(13, 16))
+        );
+        assert_eq!(
+            sources.resolve(FileSpan::new(all, Span::new(18, 19))),
+            FileSpan::new(user, Span::new(2, 3))
+        );
+
+        let mut reader = sources.reader(all).unwrap();
+        reader.read_one().unwrap();
+        let err = reader.read_one().unwrap_err();
+        assert_eq!(
+            sources.error_message(&err),
+            "user.scm:2:4: mismatched close delimiter"
+        );
+        assert_eq!(
+            sources.render(&err.into()),
+            "user.scm:2:4: error[R0003]: mismatched close delimiter\n\
+             prelude.scm:2:1: note: list opened here"
+        );
+
+        // virtual files can be parts too
+        let history = sources.add_virtual("<history>", vec![user, all]).unwrap();
+        assert_eq!(
+            sources.resolve(FileSpan::new(history, Span::new(24, 25))),
+            FileSpan::new(user, Span::new(2, 3))
+        );
+        assert!(sources.add_virtual("<bad>", vec![FileId(9)]).is_none());
+    }
+
+    #[test]
+    fn test_multibyte() {
+        let src = "λx\n日本語 y";
+        let index = LineIndex::new(src);
+
+        assert_eq!(lc(&index, 1), (1, 1));
+        assert_eq!(lc(&index, 2), (1, 2));
+        assert_eq!(lc(&index, 3), (1, 3));
+        assert_eq!(lc(&index, src.find('本').unwrap() as u32), (2, 2));
+        assert_eq!(lc(&index, src.find('y').unwrap() as u32), (2, 5));
+    }
+}
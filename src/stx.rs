@@ -1,3 +1,668 @@
-pub struct Stx {
-    
-}
\ No newline at end of file
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::rc::{Rc, Weak};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::hash::{Hash, Hasher};
+use core::mem;
+
+use crate::number::Number;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+pub mod arena;
+mod pretty;
+mod write;
+
+pub use self::arena::{ArenaKind, ArenaStx, StxArena, StxRef};
+pub use self::pretty::pretty;
+pub use self::write::Written;
+
+/// A datum read from source together with the span it was read from.
+#[derive(Debug, Clone)]
+pub struct Stx<'a> {
+    pub kind: StxKind<'a>,
+    pub span: Span,
+    /// Set on lists, vectors and bytevectors read from source, but not on
+    /// the lists quote sugar expands into.
+    pub delims: Option<Delims>,
+    /// The `;;;` comments right above the datum, if the reader was asked to
+    /// keep them with `Reader::with_doc_comments`.
+    pub doc: Option<Box<Doc<'a>>>,
+    /// Set on strings and lists that were never closed, which the reader
+    /// only stands in for the input when recovering from errors.
+    pub incomplete: bool,
+    /// The name of a symbol or keyword read from source, interned in the
+    /// reader's `SymbolTable`. Left out when comparing, since `kind` has the
+    /// name itself.
+    pub symbol: Option<Symbol>,
+}
+
+impl PartialEq for Stx<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.span == other.span
+            && self.delims == other.delims
+            && self.doc == other.doc
+            && self.incomplete == other.incomplete
+    }
+}
+
+/// A run of `;;;` comments on consecutive lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Doc<'a> {
+    /// Each comment, semicolons included.
+    pub lines: Vec<&'a str>,
+    pub span: Span,
+}
+
+impl Doc<'_> {
+    /// The comments without their semicolons and the space after them, one
+    /// per line.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                text.push('\n');
+            }
+            let line = line.trim_start_matches(';');
+            text.push_str(line.strip_prefix(' ').unwrap_or(line));
+        }
+
+        text
+    }
+}
+
+/// The delimiters of a list. A list that was never closed has an empty
+/// `close` where it ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Delims {
+    pub open: Span,
+    pub close: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StxKind<'a> {
+    Bool(bool),
+    Number(Number),
+    Char(char),
+    /// The text between the quotes with escapes decoded, borrowed from the
+    /// source unless it had any, or the body of a heredoc.
+    String(Cow<'a, str>),
+    Symbol(Cow<'a, str>),
+    /// `#:name`, `:name` or `name:`, which never equals a symbol.
+    Keyword(Keyword),
+    List(Vec<Stx<'a>>),
+    /// `(a b . c)`, the items before the dot and the tail after it.
+    DottedList(Vec<Stx<'a>>, Box<Stx<'a>>),
+    Vector(Vec<Stx<'a>>),
+    /// `{k v ...}`, read only when `ReaderOptions::maps` is enabled. The
+    /// entries are in source order and keys may repeat.
+    Map(Vec<(Stx<'a>, Stx<'a>)>),
+    /// `#{x ...}`, read only when `ReaderOptions::sets` is enabled. The
+    /// elements are in source order without duplicates.
+    Set(Vec<Stx<'a>>),
+    Bytevector(Vec<u8>),
+    /// `#N=datum`, shared with every `#N#` referring to it.
+    Labeled(u32, Rc<RefCell<Stx<'a>>>),
+    /// `#N#`, which may point back into the datum that contains it.
+    LabelRef(LabelRef<Stx<'a>>),
+    /// Stands in for input that failed to read, see
+    /// `Reader::read_all_with_recovery`.
+    Error,
+}
+
+impl StxKind<'_> {
+    /// The name of the variant, as `Stx::dump` shows it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StxKind::Bool(_) => "Bool",
+            StxKind::Number(_) => "Number",
+            StxKind::Char(_) => "Char",
+            StxKind::String(_) => "String",
+            StxKind::Symbol(_) => "Symbol",
+            StxKind::Keyword(_) => "Keyword",
+            StxKind::List(_) => "List",
+            StxKind::DottedList(..) => "DottedList",
+            StxKind::Vector(_) => "Vector",
+            StxKind::Map(_) => "Map",
+            StxKind::Set(_) => "Set",
+            StxKind::Bytevector(_) => "Bytevector",
+            StxKind::Labeled(..) => "Labeled",
+            StxKind::LabelRef(_) => "LabelRef",
+            StxKind::Error => "Error",
+        }
+    }
+}
+
+/// A datum as a plain value, without the spans of a `Stx`, see
+/// `Reader::read_datum`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Datum<'a> {
+    Bool(bool),
+    Number(Number),
+    Char(char),
+    String(Cow<'a, str>),
+    Symbol(Cow<'a, str>),
+    Keyword(Keyword),
+    List(Vec<Datum<'a>>),
+    DottedList(Vec<Datum<'a>>, Box<Datum<'a>>),
+    Vector(Vec<Datum<'a>>),
+    Map(Vec<(Datum<'a>, Datum<'a>)>),
+    Set(Vec<Datum<'a>>),
+    Bytevector(Vec<u8>),
+    Labeled(u32, Rc<RefCell<Datum<'a>>>),
+    LabelRef(LabelRef<Datum<'a>>),
+}
+
+impl<'a> Stx<'a> {
+    pub fn new(kind: StxKind<'a>, span: Span) -> Self {
+        Stx {
+            kind,
+            span,
+            delims: None,
+            doc: None,
+            incomplete: false,
+            symbol: None,
+        }
+    }
+
+    pub fn with_delims(mut self, open: Span, close: Span) -> Self {
+        self.delims = Some(Delims { open, close });
+        self
+    }
+
+    pub fn mark_incomplete(mut self) -> Self {
+        self.incomplete = true;
+        self
+    }
+
+    /// Whether both are the same datum, wherever they were read from, as
+    /// `equal?` compares them. Datum labels are looked through, so `#0=(a)`
+    /// equals `(a)` and references are compared by what they refer to, and
+    /// sets are equal in any order. Maps are compared in order, since their
+    /// keys may repeat. Floats are equal when their bits are, so `+nan.0`
+    /// equals itself and `0.0` doesn't equal `-0.0`.
+    pub fn datum_eq(&self, other: &Stx<'_>) -> bool {
+        self.eq_by(other, false, &mut Vec::new())
+    }
+
+    /// Whether both are the same datum read from the same spans, at every
+    /// depth. Unlike `==` it ignores doc comments and whether lists were
+    /// closed.
+    pub fn strict_eq(&self, other: &Stx<'_>) -> bool {
+        self.eq_by(other, true, &mut Vec::new())
+    }
+
+    /// `seen` holds the pairs of labelled datums being compared further up,
+    /// which are taken to be equal when cyclic structure reaches them again.
+    fn eq_by(&self, other: &Stx<'_>, spans: bool, seen: &mut Vec<(usize, usize)>) -> bool {
+        if spans && self.span != other.span {
+            return false;
+        }
+
+        let (a_cell, b_cell) = (self.label_target(), other.label_target());
+        if a_cell.is_some() || b_cell.is_some() {
+            let key = (cell_addr(&a_cell), cell_addr(&b_cell));
+            if a_cell.is_some() && b_cell.is_some() && seen.contains(&key) {
+                return true;
+            }
+
+            let a = a_cell.as_ref().map(|cell| cell.borrow());
+            let b = b_cell.as_ref().map(|cell| cell.borrow());
+            seen.push(key);
+            let eq = a
+                .as_deref()
+                .unwrap_or(self)
+                .eq_by(b.as_deref().unwrap_or(other), spans, seen);
+            seen.pop();
+            return eq;
+        }
+
+        let mut all_eq = |a: &[Stx<'_>], b: &[Stx<'_>]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_by(b, spans, seen))
+        };
+
+        match (&self.kind, &other.kind) {
+            (StxKind::Bool(a), StxKind::Bool(b)) => a == b,
+            (StxKind::Number(a), StxKind::Number(b)) => number_eq(a, b),
+            (StxKind::Char(a), StxKind::Char(b)) => a == b,
+            (StxKind::String(a), StxKind::String(b)) => a == b,
+            (StxKind::Symbol(a), StxKind::Symbol(b)) => a == b,
+            (StxKind::Keyword(a), StxKind::Keyword(b)) => a == b,
+            (StxKind::List(a), StxKind::List(b)) | (StxKind::Vector(a), StxKind::Vector(b)) => {
+                all_eq(a, b)
+            }
+            (StxKind::Set(a), StxKind::Set(b)) => {
+                let mut contains = |a: &[Stx<'_>], b: &[Stx<'_>]| {
+                    a.iter().all(|a| b.iter().any(|b| a.eq_by(b, spans, seen)))
+                };
+                a.len() == b.len() && contains(a, b) && contains(b, a)
+            }
+            (StxKind::DottedList(a, a_tail), StxKind::DottedList(b, b_tail)) => {
+                all_eq(a, b) && a_tail.eq_by(b_tail, spans, seen)
+            }
+            (StxKind::Map(a), StxKind::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b)
+                        .all(|(a, b)| a.0.eq_by(&b.0, spans, seen) && a.1.eq_by(&b.1, spans, seen))
+            }
+            (StxKind::Bytevector(a), StxKind::Bytevector(b)) => a == b,
+            // only labels without a datum are left
+            (StxKind::Labeled(..), _) | (StxKind::LabelRef(_), _) => {
+                other.is_label() && self.lost_label() == other.lost_label()
+            }
+            (StxKind::Error, StxKind::Error) => true,
+            _ => false,
+        }
+    }
+
+    /// The datum a label defines or refers to, past labels of labels, if it
+    /// is still alive. Labels that only lead to each other have none.
+    fn label_target(&self) -> Option<Rc<RefCell<Stx<'a>>>> {
+        let mut cell = self.label_cell()?;
+        let mut visited = Vec::new();
+        loop {
+            let next = cell.borrow().label_cell();
+            match next {
+                Some(next) if visited.contains(&Rc::as_ptr(&next)) => return None,
+                Some(next) => {
+                    visited.push(Rc::as_ptr(&cell));
+                    cell = next;
+                }
+                None => return Some(cell),
+            }
+        }
+    }
+
+    fn label_cell(&self) -> Option<Rc<RefCell<Stx<'a>>>> {
+        match &self.kind {
+            StxKind::Labeled(_, datum) => Some(Rc::clone(datum)),
+            StxKind::LabelRef(target) => target.get(),
+            _ => None,
+        }
+    }
+
+    /// For a label without a datum, the label of a reference whose datum is
+    /// gone, or `None` for labels that only lead to each other.
+    fn lost_label(&self) -> Option<u32> {
+        match &self.kind {
+            StxKind::LabelRef(target) if target.get().is_none() => Some(target.label),
+            _ => None,
+        }
+    }
+
+    fn is_label(&self) -> bool {
+        matches!(self.kind, StxKind::Labeled(..) | StxKind::LabelRef(_))
+    }
+
+    /// Hashes the datum consistently with `datum_eq`, leaving spans out.
+    /// Only the first `HASH_BUDGET` datums are hashed, counted in an order
+    /// that datum labels and the order of set elements don't change, so
+    /// cyclic datums hash too.
+    pub fn datum_hash<H: Hasher>(&self, state: &mut H) {
+        self.hash_by(state, &mut { HASH_BUDGET });
+    }
+
+    fn hash_by<H: Hasher>(&self, state: &mut H, budget: &mut usize) {
+        if let Some(cell) = self.label_target() {
+            return cell.borrow().hash_by(state, budget);
+        }
+        if self.is_label() {
+            return self.lost_label().hash(state);
+        }
+        if *budget == 0 {
+            return;
+        }
+        *budget -= 1;
+
+        mem::discriminant(&self.kind).hash(state);
+        match &self.kind {
+            StxKind::Bool(value) => value.hash(state),
+            StxKind::Number(value) => match value {
+                Number::Int(value) => value.hash(state),
+                Number::BigInt(value) => value.hash(state),
+                Number::Float(value) => value.to_bits().hash(state),
+                Number::Rational(numer, denom) => {
+                    numer.hash(state);
+                    denom.hash(state);
+                }
+            },
+            StxKind::Char(ch) => ch.hash(state),
+            StxKind::String(text) | StxKind::Symbol(text) => text.hash(state),
+            StxKind::Keyword(keyword) => keyword.name().hash(state),
+            StxKind::List(items) | StxKind::Vector(items) => hash_all(items, state, budget),
+            StxKind::Set(items) => {
+                // every element gets the same budget and the hashes are
+                // summed, so the order of the elements doesn't matter
+                let (mut sum, mut used) = (0u64, 0);
+                for item in items {
+                    let mut hasher = Fnv::default();
+                    let mut left = *budget;
+                    item.hash_by(&mut hasher, &mut left);
+                    sum = sum.wrapping_add(hasher.finish());
+                    used += *budget - left;
+                }
+                items.len().hash(state);
+                sum.hash(state);
+                *budget = budget.saturating_sub(used);
+            }
+            StxKind::DottedList(items, tail) => {
+                hash_all(items, state, budget);
+                tail.hash_by(state, budget);
+            }
+            StxKind::Map(entries) => {
+                entries.len().hash(state);
+                for (key, value) in entries {
+                    key.hash_by(state, budget);
+                    value.hash_by(state, budget);
+                }
+            }
+            StxKind::Bytevector(bytes) => bytes.hash(state),
+            StxKind::Labeled(..) | StxKind::LabelRef(_) | StxKind::Error => {}
+        }
+    }
+
+    /// The tree for reading while debugging or writing tests, one datum per
+    /// line with its kind and span, indented under the datum holding it.
+    /// Atoms show the text of `src` they were read from.
+    pub fn dump(&self, src: &str) -> String {
+        let mut out = String::new();
+        self.dump_to(src, 0, &mut out);
+        out
+    }
+
+    fn dump_to(&self, src: &str, depth: usize, out: &mut String) {
+        for _ in 0..depth {
+            out.push_str("  ");
+        }
+        let _ = write!(
+            out,
+            "{}@{}..{}",
+            self.kind.name(),
+            self.span.start,
+            self.span.end
+        );
+        if self.incomplete {
+            out.push_str(" incomplete");
+        }
+
+        let mut children = Vec::new();
+        match &self.kind {
+            StxKind::List(items) | StxKind::Vector(items) | StxKind::Set(items) => {
+                children.extend(items)
+            }
+            StxKind::DottedList(items, tail) => {
+                children.extend(items);
+                children.push(&**tail);
+            }
+            StxKind::Map(entries) => {
+                for (key, value) in entries {
+                    children.push(key);
+                    children.push(value);
+                }
+            }
+            StxKind::Labeled(label, datum) => {
+                let _ = writeln!(out, " #{}=", label);
+                return datum.borrow().dump_to(src, depth + 1, out);
+            }
+            StxKind::LabelRef(target) => {
+                let _ = write!(out, " #{}#", target.label);
+            }
+            _ => {
+                if let Some(text) = src.get(self.span.range()) {
+                    let _ = write!(out, " {:?}", text);
+                }
+            }
+        }
+        out.push('\n');
+
+        for child in children {
+            child.dump_to(src, depth + 1, out);
+        }
+    }
+}
+
+fn number_eq(a: &Number, b: &Number) -> bool {
+    match (a, b) {
+        (Number::Float(a), Number::Float(b)) => a.to_bits() == b.to_bits(),
+        (a, b) => a == b,
+    }
+}
+
+fn hash_all<H: Hasher>(items: &[Stx<'_>], state: &mut H, budget: &mut usize) {
+    items.len().hash(state);
+    for item in items {
+        item.hash_by(state, budget);
+    }
+}
+
+fn cell_addr<T>(cell: &Option<Rc<T>>) -> usize {
+    cell.as_ref()
+        .map_or(0, |cell| Rc::as_ptr(cell) as *const () as usize)
+}
+
+/// How many datums `Stx::datum_hash` hashes at most.
+const HASH_BUDGET: usize = 1024;
+
+/// FNV-1a, for hashing set elements apart from the caller's hasher.
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+/// A `Stx` compared and hashed as a datum, with `Stx::datum_eq` and
+/// `Stx::datum_hash`, for deduplicating datums or keying maps with them.
+/// Changing a labelled datum inside one that is a key changes its hash.
+#[derive(Debug, Clone)]
+pub struct Structural<'a>(pub Stx<'a>);
+
+impl PartialEq for Structural<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.datum_eq(&other.0)
+    }
+}
+
+impl Eq for Structural<'_> {}
+
+impl Hash for Structural<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.datum_hash(state)
+    }
+}
+
+/// The name of a keyword, without its colons. The keywords one reader reads
+/// share their names with its `SymbolTable`, so comparing equal ones is a
+/// pointer check.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Keyword(pub(crate) Rc<str>);
+
+impl Keyword {
+    pub fn new(name: &str) -> Self {
+        Keyword(name.into())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether both were interned as the same name by one reader.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#:{}", self.0)
+    }
+}
+
+/// A reference to a labelled datum. It holds the datum weakly, so cyclic
+/// structure doesn't leak, and compares and prints by label alone so cycles
+/// don't recurse forever.
+#[derive(Clone)]
+pub struct LabelRef<T> {
+    pub label: u32,
+    target: Weak<RefCell<T>>,
+}
+
+impl<T> LabelRef<T> {
+    pub fn new(label: u32, target: &Rc<RefCell<T>>) -> Self {
+        LabelRef {
+            label,
+            target: Rc::downgrade(target),
+        }
+    }
+
+    /// The labelled datum, as long as the `Labeled` node owning it is alive.
+    pub fn get(&self) -> Option<Rc<RefCell<T>>> {
+        self.target.upgrade()
+    }
+}
+
+impl<T> fmt::Debug for LabelRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}#", self.label)
+    }
+}
+
+impl<T> PartialEq for LabelRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.label == other.label
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::reader::{Reader, ReaderOptions};
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+
+    fn dump(src: &str) -> String {
+        let options = ReaderOptions::new().maps(true);
+        let (forms, _) = Reader::new(src)
+            .with_options(options)
+            .read_all_with_recovery();
+        forms.iter().map(|stx| stx.dump(src)).collect()
+    }
+
+    #[test]
+    fn test_dump() {
+        assert_eq!(
+            dump("(a \"b\" . #(1)) {k #t} 'x"),
+            "DottedList@0..14
+  Symbol@1..2 \"a\"
+  String@3..6 \"\\\"b\\\"\"
+  Vector@9..13
+    Number@11..12 \"1\"
+Map@15..21
+  Symbol@16..17 \"k\"
+  Bool@18..20 \"#t\"
+List@22..24
+  Symbol@22..23 \"'\"
+  Symbol@23..24 \"x\"
+"
+        );
+        assert_eq!(
+            dump("#0=(a #0#) (b"),
+            "Labeled@0..10 #0=
+  List@3..10
+    Symbol@4..5 \"a\"
+    LabelRef@6..9 #0#
+List@11..13 incomplete
+  Symbol@12..13 \"b\"
+"
+        );
+    }
+
+    fn read(src: &str) -> Vec<Stx<'_>> {
+        let options = ReaderOptions::new().maps(true).sets(true);
+        Reader::new(src).with_options(options).read_all().unwrap()
+    }
+
+    fn hash(stx: &Stx<'_>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        stx.datum_hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_datum_eq() {
+        let a = read("(a \"b\" . #(1 +nan.0)) {k 0.0} #0=(x . #0#)");
+        let b = read("  ( a \"b\" . #( 1 +nan.0 ) ) {k 0.0} #0=(x . #0#)");
+
+        for (a, b) in a.iter().zip(&b) {
+            assert!(a.datum_eq(b));
+            assert!(!a.strict_eq(b));
+            assert!(a.strict_eq(a));
+            assert_eq!(hash(a), hash(b));
+        }
+
+        let differ = |a: &str, b: &str| !read(a)[0].datum_eq(&read(b)[0]);
+        assert!(differ("0.0", "-0.0"));
+        assert!(differ("1", "1.0"));
+        assert!(differ("(a b)", "(a . b)"));
+        assert!(differ("(a b)", "#(a b)"));
+        assert!(differ("a", "\"a\""));
+        assert!(differ("a", "#:a"));
+        assert!(differ("{a b}", "{b a}"));
+    }
+
+    #[test]
+    fn test_datum_eq_labels_and_sets() {
+        let same = |a: &str, b: &str| {
+            let (a, b) = (&read(a)[0], &read(b)[0]);
+            assert!(a.datum_eq(b), "{} {}", a, b);
+            assert!(b.datum_eq(a), "{} {}", b, a);
+            assert_eq!(hash(a), hash(b), "{} {}", a, b);
+        };
+        same("#0=(a)", "(a)");
+        same("#0=(a . #0#)", "#1=(a . #1#)");
+        same("(#0=(a) #0#)", "((a) (a))");
+        same("#{1 2 (3)}", "#{(3) 2 1}");
+        same("#{#0=(a) b}", "#{b (a)}");
+
+        let differ = |a: &str, b: &str| !read(a)[0].datum_eq(&read(b)[0]);
+        assert!(differ("#0=(a . #0#)", "#0=(a a . #0#)"));
+        assert!(differ("(#0=(a) #0#)", "((a) (b))"));
+        assert!(differ("#{1 2}", "#{1 3}"));
+        assert!(differ("#{1 2}", "#{1}"));
+
+        let loops = Reader::new("#0=#0# #1=#2=#1# a").read_all().unwrap();
+        assert!(loops[0].datum_eq(&loops[1]));
+        assert!(!loops[0].datum_eq(&loops[2]));
+        assert_eq!(hash(&loops[0]), hash(&loops[1]));
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_structural() {
+        let forms: HashSet<_> = read("(a 1) ( a 1 ) \"a\" (a 1.0) \"a\"")
+            .into_iter()
+            .map(Structural)
+            .collect();
+        assert_eq!(forms.len(), 3);
+        assert!(forms.contains(&Structural(read("(a\n1)").remove(0))));
+    }
+}
@@ -0,0 +1,264 @@
+//! Syntax trees kept in one arena, for reading large inputs without a heap
+//! allocation per list.
+
+use alloc::vec::Vec;
+
+use crate::number::Number;
+use crate::reader::events::Delim;
+use crate::reader::sink::{Flow, ReadSink};
+use crate::span::Span;
+use crate::stx::{Delims, Stx, StxKind};
+use crate::symbol::Symbol;
+
+/// A datum in a `StxArena`, valid only for the arena that handed it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StxRef(u32);
+
+impl StxRef {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArenaKind<'a> {
+    /// Any datum that isn't a list, as the reader decoded it. A reader
+    /// macro's datum is one atom, even if it is a list.
+    Atom(StxKind<'a>),
+    /// A list, vector, bytevector or set, whose items are in
+    /// `StxArena::items`. Map literals are `Brace` lists of their keys and
+    /// values.
+    List(Delim),
+    /// A list whose last item is the tail after its dot.
+    DottedList(Delim),
+}
+
+/// A datum stored in a `StxArena`, like a `Stx` with its items left in the
+/// arena.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArenaStx<'a> {
+    pub kind: ArenaKind<'a>,
+    pub span: Span,
+    /// Set on lists read from source, but not on quote sugar.
+    pub delims: Option<Delims>,
+    pub symbol: Option<Symbol>,
+    /// Where the items start in `StxArena::items`, and how many there are.
+    items: (u32, u32),
+}
+
+/// Datums read with `Reader::read_with`, stored in a few large buffers
+/// instead of a tree of `Stx`. Datums are numbered in the order they were
+/// opened, so a list comes before its items.
+#[derive(Debug, Clone, Default)]
+pub struct StxArena<'a> {
+    nodes: Vec<ArenaStx<'a>>,
+    items: Vec<StxRef>,
+    roots: Vec<StxRef>,
+    /// The lists still open, innermost last, and where their items start in
+    /// `pending`.
+    open: Vec<(StxRef, usize)>,
+    pending: Vec<StxRef>,
+}
+
+impl<'a> StxArena<'a> {
+    pub fn new() -> Self {
+        StxArena::default()
+    }
+
+    /// An arena with room for `datums` datums before it grows.
+    pub fn with_capacity(datums: usize) -> Self {
+        StxArena {
+            nodes: Vec::with_capacity(datums),
+            items: Vec::with_capacity(datums),
+            ..StxArena::default()
+        }
+    }
+
+    /// The top-level datums, in source order.
+    pub fn roots(&self) -> &[StxRef] {
+        &self.roots
+    }
+
+    pub fn get(&self, stx: StxRef) -> &ArenaStx<'a> {
+        &self.nodes[stx.0 as usize]
+    }
+
+    /// The items of a list, or nothing for an atom.
+    pub fn items(&self, stx: StxRef) -> &[StxRef] {
+        let (start, len) = self.get(stx).items;
+        &self.items[start as usize..(start + len) as usize]
+    }
+
+    /// How many datums the arena holds, at every depth.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Copies `stx` out of the arena into the tree `Reader::read_one` would
+    /// have read, except that maps are lists.
+    pub fn to_stx(&self, stx: StxRef) -> Stx<'a> {
+        let node = self.get(stx);
+        let items = || self.items(stx).iter().map(|&item| self.to_stx(item));
+
+        let kind = match &node.kind {
+            ArenaKind::Atom(kind) => kind.clone(),
+            ArenaKind::List(Delim::Vector) => StxKind::Vector(items().collect()),
+            ArenaKind::List(Delim::Set) => StxKind::Set(items().collect()),
+            ArenaKind::List(Delim::Bytevector) => StxKind::Bytevector(
+                items()
+                    .filter_map(|item| match item.kind {
+                        StxKind::Number(Number::Int(byte)) => Some(byte as u8),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            ArenaKind::List(_) => StxKind::List(items().collect()),
+            ArenaKind::DottedList(_) => {
+                let mut items: Vec<_> = items().collect();
+                let tail = items.pop().map(Into::into);
+                match tail {
+                    Some(tail) => StxKind::DottedList(items, tail),
+                    None => StxKind::List(items),
+                }
+            }
+        };
+
+        let mut out = Stx::new(kind, node.span);
+        out.delims = node.delims;
+        out.symbol = node.symbol;
+        out
+    }
+
+    fn push(&mut self, node: ArenaStx<'a>) -> StxRef {
+        let stx = StxRef(self.nodes.len() as u32);
+        self.nodes.push(node);
+        stx
+    }
+
+    /// Adds `stx` to the innermost open list, or as a top-level datum.
+    fn push_item(&mut self, stx: StxRef) {
+        match self.open.is_empty() {
+            true => self.roots.push(stx),
+            false => self.pending.push(stx),
+        }
+    }
+}
+
+impl<'a> ReadSink<'a> for StxArena<'a> {
+    fn open(&mut self, delim: Delim, span: Span) -> Flow {
+        let delims = Some(Delims {
+            open: span,
+            close: span,
+        });
+        let stx = self.push(ArenaStx {
+            kind: ArenaKind::List(delim),
+            span,
+            delims: delims.filter(|_| !span.is_empty()),
+            symbol: None,
+            items: (0, 0),
+        });
+        self.open.push((stx, self.pending.len()));
+        Flow::Continue
+    }
+
+    fn atom(&mut self, atom: Stx<'a>) -> Flow {
+        let stx = self.push(ArenaStx {
+            kind: ArenaKind::Atom(atom.kind),
+            span: atom.span,
+            delims: atom.delims,
+            symbol: atom.symbol,
+            items: (0, 0),
+        });
+        self.push_item(stx);
+        Flow::Continue
+    }
+
+    fn dot(&mut self, _: Span) -> Flow {
+        if let Some(&(stx, _)) = self.open.last() {
+            let node = &mut self.nodes[stx.0 as usize];
+            if let ArenaKind::List(delim) = node.kind {
+                node.kind = ArenaKind::DottedList(delim);
+            }
+        }
+        Flow::Continue
+    }
+
+    fn close(&mut self, span: Span) -> Flow {
+        let (stx, start) = match self.open.pop() {
+            Some(open) => open,
+            None => return Flow::Continue,
+        };
+
+        let items = (self.items.len() as u32, (self.pending.len() - start) as u32);
+        self.items.extend(self.pending.drain(start..));
+
+        let node = &mut self.nodes[stx.0 as usize];
+        node.items = items;
+        node.span.end = span.end;
+        if let Some(delims) = &mut node.delims {
+            delims.close = span;
+        }
+
+        self.push_item(stx);
+        Flow::Continue
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::reader::{Reader, ReaderOptions};
+
+    #[test]
+    fn test_arena() {
+        let src = "(a [b] . c) #(1 \"s\") #u8(3 4) 'x `(,@y #:k) (#;z) {p q}";
+        let mut arena = StxArena::new();
+        Reader::new(src).read_with(&mut arena).unwrap();
+
+        let forms = Reader::new(src).read_all().unwrap();
+        let copied: Vec<_> = arena.roots().iter().map(|&stx| arena.to_stx(stx)).collect();
+        assert_eq!(copied, forms);
+
+        let list = arena.roots()[0];
+        assert_eq!(arena.get(list).kind, ArenaKind::DottedList(Delim::Paren));
+        assert_eq!(arena.get(list).span, Span::new(0, 11));
+        assert_eq!(arena.items(list).len(), 3);
+        let b = arena.items(arena.items(list)[1])[0];
+        assert_eq!(
+            arena.get(b).kind,
+            ArenaKind::Atom(StxKind::Symbol("b".into()))
+        );
+        assert_eq!(arena.items(b), &[]);
+
+        let quote = arena.roots()[3];
+        assert_eq!(arena.get(quote).delims, None);
+        assert_eq!(arena.get(quote).span, Span::new(30, 32));
+        assert_eq!(arena.len(), 25);
+    }
+
+    #[test]
+    fn test_arena_sets() {
+        let src = "#{1 2}";
+        let options = ReaderOptions::new().sets(true);
+        let mut arena = StxArena::with_capacity(3);
+        Reader::new(src)
+            .with_options(options)
+            .read_with(&mut arena)
+            .unwrap();
+
+        let set = arena.roots()[0];
+        assert_eq!(arena.get(set).kind, ArenaKind::List(Delim::Set));
+        assert_eq!(
+            arena.to_stx(set),
+            Reader::new(src)
+                .with_options(options)
+                .read_one()
+                .unwrap()
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Writing datums across lines to fit a width, in the style of Wadler's
+//! "A prettier printer".
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::stx::{Stx, StxKind};
+
+/// Layout of the text, before deciding where lines break.
+enum Doc {
+    Text(String),
+    /// A space, or a line break if the group holding it doesn't fit.
+    Line,
+    Concat(Vec<Doc>),
+    /// Indents the lines broken inside by more than the enclosing lines.
+    Nest(usize, Box<Doc>),
+    /// Indents the lines broken inside to the column it starts at.
+    Align(Box<Doc>),
+    /// Breaks either every line inside or none of them, those of nested
+    /// groups aside.
+    Group(Box<Doc>),
+}
+
+/// How much deeper than their open delimiter the items after the first
+/// argument of a form like `(define ...)` are indented.
+const FORM_INDENT: usize = 2;
+
+/// `stx` as `Stx::write` writes it, but with lists that don't fit in
+/// `width` columns broken across lines. Items of a broken list line up
+/// under its first item, or are indented under the name of a form.
+pub fn pretty(stx: &Stx<'_>, width: usize) -> String {
+    render(&doc(stx), width)
+}
+
+fn doc(stx: &Stx<'_>) -> Doc {
+    match &stx.kind {
+        StxKind::List(items) => list("(", items, None, ")"),
+        StxKind::DottedList(items, tail) => list("(", items, Some(tail), ")"),
+        StxKind::Vector(items) => list("#(", items, None, ")"),
+        StxKind::Set(items) => list("#{", items, None, "}"),
+        StxKind::Map(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| Doc::Concat(vec![doc(key), text(" "), doc(value)]))
+                .collect();
+            delimited("{", entries, "}")
+        }
+        StxKind::Bytevector(bytes) => {
+            let bytes = bytes.iter().map(|byte| text(&byte.to_string())).collect();
+            delimited("#u8(", bytes, ")")
+        }
+        StxKind::Labeled(label, datum) => Doc::Concat(vec![
+            Doc::Text(alloc::format!("#{}=", label)),
+            doc(&datum.borrow()),
+        ]),
+        _ => Doc::Text(stx.to_string()),
+    }
+}
+
+fn text(text: &str) -> Doc {
+    Doc::Text(text.into())
+}
+
+/// A list whose items line up under the first, or a form whose name and
+/// first argument stay on the first line.
+fn list(open: &str, items: &[Stx<'_>], tail: Option<&Stx<'_>>, close: &str) -> Doc {
+    let mut docs: Vec<Doc> = items.iter().map(doc).collect();
+    if let Some(tail) = tail {
+        docs.push(Doc::Concat(vec![text(". "), doc(tail)]));
+    }
+
+    let head = items.first().map(|item| &item.kind);
+    let is_form = open == "(" && matches!(head, Some(StxKind::Symbol(_)));
+    if !is_form || docs.len() < 2 {
+        return delimited(open, docs, close);
+    }
+
+    let rest: Vec<Doc> = docs.split_off(2);
+    let rest = rest.into_iter().flat_map(|doc| vec![Doc::Line, doc]);
+    let mut parts = vec![text(open)];
+    let mut docs = docs.into_iter();
+    parts.extend(docs.next());
+    parts.push(text(" "));
+    parts.extend(docs.next());
+    parts.push(Doc::Nest(
+        FORM_INDENT,
+        Box::new(Doc::Concat(rest.collect())),
+    ));
+    parts.push(text(close));
+
+    Doc::Group(Box::new(Doc::Align(Box::new(Doc::Concat(parts)))))
+}
+
+fn delimited(open: &str, items: Vec<Doc>, close: &str) -> Doc {
+    let mut inner = Vec::new();
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            inner.push(Doc::Line);
+        }
+        inner.push(item);
+    }
+
+    Doc::Group(Box::new(Doc::Concat(vec![
+        text(open),
+        Doc::Align(Box::new(Doc::Concat(inner))),
+        text(close),
+    ])))
+}
+
+/// Lays `doc` out, deciding for each group in turn whether it fits on the
+/// rest of its line.
+fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    // the indent and whether lines are flat, for each part left to lay out
+    let mut stack: Vec<(usize, bool, &Doc)> = vec![(0, false, doc)];
+
+    while let Some((indent, flat, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => {
+                out.push_str(text);
+                column += text.chars().count();
+            }
+            Doc::Line if flat => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::Line => {
+                out.push('\n');
+                for _ in 0..indent {
+                    out.push(' ');
+                }
+                column = indent;
+            }
+            Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev().map(|doc| (indent, flat, doc)));
+            }
+            Doc::Nest(by, doc) => stack.push((indent + by, flat, doc)),
+            Doc::Align(doc) => stack.push((column, flat, doc)),
+            Doc::Group(doc) => {
+                let flat = flat || fits(width.saturating_sub(column), doc, &stack);
+                stack.push((indent, flat, doc));
+            }
+        }
+    }
+
+    out
+}
+
+/// Whether `doc` laid out flat, and what follows it up to the next line
+/// break, takes at most `width` columns.
+fn fits(width: usize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut width = width as isize;
+    let mut work = vec![(true, doc)];
+    let mut rest = rest.iter().rev();
+
+    loop {
+        let (flat, doc) = match work.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, flat, doc)) => (flat, doc),
+                None => return true,
+            },
+        };
+
+        match doc {
+            Doc::Text(text) => width -= text.chars().count() as isize,
+            Doc::Line if flat => width -= 1,
+            Doc::Line => return true,
+            Doc::Concat(docs) => work.extend(docs.iter().rev().map(|doc| (flat, doc))),
+            Doc::Nest(_, doc) | Doc::Align(doc) | Doc::Group(doc) => work.push((flat, doc)),
+        }
+
+        if width < 0 {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::reader::{Reader, ReaderOptions};
+
+    fn pretty_src(src: &str, width: usize) -> String {
+        let options = ReaderOptions::new().maps(true);
+        let stx = Reader::new(src)
+            .with_options(options)
+            .read_one()
+            .unwrap()
+            .unwrap();
+        pretty(&stx, width)
+    }
+
+    #[test]
+    fn test_pretty() {
+        assert_eq!(pretty_src("(a   b\n c)", 20), "(a b c)");
+        assert_eq!(
+            pretty_src("(define (square x) (* x x))", 20),
+            "(define (square x)\n  (* x x))"
+        );
+        assert_eq!(
+            pretty_src("(if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))", 24),
+            "(if (< n 2)\n  n\n  (+ (fib (- n 1))\n    (fib (- n 2))))"
+        );
+        assert_eq!(
+            pretty_src("#(1 2 3 (4 5) \"six\")", 10),
+            "#(1\n  2\n  3\n  (4 5)\n  \"six\")"
+        );
+        assert_eq!(
+            pretty_src("((lambda (x) x) . rest)", 12),
+            "((lambda (x)\n   x)\n . rest)"
+        );
+        assert_eq!(
+            pretty_src("{name \"x\" size 1}", 12),
+            "{name \"x\"\n size 1}"
+        );
+    }
+
+    #[test]
+    fn test_pretty_reads_back() {
+        let src = "(let loop ((i 0) (acc '())) (if (= i 10) (reverse acc) \
+                   (loop (+ i 1) (cons (* i i) acc))))";
+        let stx = Reader::new(src).read_one().unwrap().unwrap();
+
+        for width in 0..80 {
+            let text = pretty(&stx, width);
+            let again = Reader::new(&text).read_one().unwrap().unwrap();
+            assert!(stx.datum_eq(&again), "{}", text);
+            if width >= 30 {
+                assert!(text.lines().all(|line| line.chars().count() <= width));
+            }
+        }
+    }
+}
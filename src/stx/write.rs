@@ -0,0 +1,243 @@
+//! Writing datums back out as text.
+
+use core::fmt::{self, Display, Write as _};
+
+use crate::scanner::{LexemeKind, Scanner};
+use crate::stx::{Stx, StxKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Write,
+    Display,
+}
+
+/// A datum written as text, see `Stx::write` and `Stx::display`.
+#[derive(Debug, Clone, Copy)]
+pub struct Written<'s, 'a> {
+    stx: &'s Stx<'a>,
+    mode: Mode,
+}
+
+impl<'a> Stx<'a> {
+    /// The datum as text the reader reads back as the same datum, with
+    /// strings quoted and escaped, characters as `#\a` and symbols in bars
+    /// when they need them.
+    pub fn write(&self) -> Written<'_, 'a> {
+        Written {
+            stx: self,
+            mode: Mode::Write,
+        }
+    }
+
+    /// The datum as text for people to read, with strings, characters and
+    /// symbols written as they are.
+    pub fn display(&self) -> Written<'_, 'a> {
+        Written {
+            stx: self,
+            mode: Mode::Display,
+        }
+    }
+}
+
+/// Writes the datum as `Stx::write` does.
+impl Display for Stx<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write().fmt(f)
+    }
+}
+
+impl Display for Written<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_stx(self.stx, self.mode, f)
+    }
+}
+
+fn write_stx(stx: &Stx<'_>, mode: Mode, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &stx.kind {
+        StxKind::Bool(true) => f.write_str("#t"),
+        StxKind::Bool(false) => f.write_str("#f"),
+        StxKind::Number(value) => write!(f, "{}", value),
+        StxKind::Char(ch) if mode == Mode::Display => f.write_char(*ch),
+        StxKind::Char(ch) => write_char(*ch, f),
+        StxKind::String(text) if mode == Mode::Display => f.write_str(text),
+        StxKind::String(text) => write_string(text, f),
+        StxKind::Symbol(name) if mode == Mode::Write && !is_bare_symbol(name) => {
+            write_bars(name, f)
+        }
+        StxKind::Symbol(name) => f.write_str(name),
+        StxKind::Keyword(keyword) => write!(f, "#:{}", keyword.name()),
+        StxKind::List(items) => write_items("(", items, ")", mode, f),
+        StxKind::DottedList(items, tail) => {
+            write_items("(", items, " . ", mode, f)?;
+            write_stx(tail, mode, f)?;
+            f.write_str(")")
+        }
+        StxKind::Vector(items) => write_items("#(", items, ")", mode, f),
+        StxKind::Map(entries) => {
+            f.write_str("{")?;
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+                write_stx(key, mode, f)?;
+                f.write_str(" ")?;
+                write_stx(value, mode, f)?;
+            }
+            f.write_str("}")
+        }
+        StxKind::Set(items) => write_items("#{", items, "}", mode, f),
+        StxKind::Bytevector(bytes) => {
+            f.write_str("#u8(")?;
+            for (i, byte) in bytes.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+                write!(f, "{}", byte)?;
+            }
+            f.write_str(")")
+        }
+        StxKind::Labeled(label, datum) => {
+            write!(f, "#{}=", label)?;
+            write_stx(&datum.borrow(), mode, f)
+        }
+        StxKind::LabelRef(target) => write!(f, "#{}#", target.label),
+        StxKind::Error => f.write_str("#<error>"),
+    }
+}
+
+fn write_items(
+    open: &str,
+    items: &[Stx<'_>],
+    close: &str,
+    mode: Mode,
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    f.write_str(open)?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(" ")?;
+        }
+        write_stx(item, mode, f)?;
+    }
+    f.write_str(close)
+}
+
+fn write_char(ch: char, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match ch {
+        '\u{7}' => "alarm",
+        '\u{8}' => "backspace",
+        '\u{7f}' => "delete",
+        '\u{1b}' => "escape",
+        '\n' => "newline",
+        '\0' => "null",
+        '\u{c}' => "page",
+        '\r' => "return",
+        ' ' => "space",
+        '\t' => "tab",
+        ch if ch.is_control() => return write!(f, "#\\x{:x}", ch as u32),
+        ch => return write!(f, "#\\{}", ch),
+    };
+
+    write!(f, "#\\{}", name)
+}
+
+fn write_string(text: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_char('"')?;
+    for ch in text.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            '\u{7}' => f.write_str("\\a")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\0' => f.write_str("\\0")?,
+            ch if ch.is_control() => write!(f, "\\x{:x};", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char('"')
+}
+
+fn write_bars(name: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_char('|')?;
+    for ch in name.chars() {
+        match ch {
+            '|' => f.write_str("\\|")?,
+            '\\' => f.write_str("\\\\")?,
+            ch if ch.is_control() => write!(f, "\\x{:x};", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char('|')
+}
+
+/// Whether `name` reads back as the same symbol without bars around it.
+fn is_bare_symbol(name: &str) -> bool {
+    let mut lexemes = Scanner::new(name);
+
+    match (lexemes.next(), lexemes.next()) {
+        (Some(lex), None) => lex.kind == LexemeKind::Identifier && lex.slice == name,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::reader::{Reader, ReaderOptions};
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    fn write(src: &str) -> String {
+        let options = ReaderOptions::new().maps(true).sets(true);
+        let forms = Reader::new(src).with_options(options).read_all().unwrap();
+        let written: Vec<_> = forms.iter().map(ToString::to_string).collect();
+        written.join(" ")
+    }
+
+    fn display(src: &str) -> String {
+        let stx = Reader::new(src).read_one().unwrap().unwrap();
+        stx.display().to_string()
+    }
+
+    #[test]
+    fn test_write() {
+        assert_eq!(
+            write("( a  . b) #(1 2.5 -0.0 1/2) 'x #t #false {k #:v} #{1} #u8(0 255)"),
+            "(a . b) #(1 2.5 -0.0 1/2) (quote x) #t #f {k #:v} #{1} #u8(0 255)"
+        );
+        assert_eq!(
+            write(r#""a\"b\\c\nd\x7;" #\a #\space #\x0 #\x1 #\λ"#),
+            r#""a\"b\\c\nd\a" #\a #\space #\null #\x1 #\λ"#
+        );
+        assert_eq!(write("|a b| |foo| || |1| abc"), "|a b| foo || |1| abc");
+        assert_eq!(write(r"|a\|b| |a b\\| |\n|"), r"|a\|b| |a b\\| |\xa;|");
+        assert_eq!(
+            write("#0=(a . #0#) +inf.0 +nan.0 1e3"),
+            "#0=(a . #0#) +inf.0 +nan.0 1e3"
+        );
+        assert_eq!(
+            write("100000000000000000000000"),
+            "100000000000000000000000"
+        );
+    }
+
+    #[test]
+    fn test_write_reads_back() {
+        let src = r#"(define (f x) "tab\there" #\newline |odd sym| |a\|b\\| 12.0 #(#u8()) #:k)"#;
+        let stx = Reader::new(src).read_one().unwrap().unwrap();
+        let written = stx.to_string();
+        let again = Reader::new(&written).read_one().unwrap().unwrap();
+        assert!(stx.datum_eq(&again));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            display(r#"("a\"b" #\c |x y| (#(1)) #:k)"#),
+            r#"(a"b c x y (#(1)) #:k)"#
+        );
+        assert_eq!(display("#\\space"), " ");
+    }
+}
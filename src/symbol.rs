@@ -0,0 +1,99 @@
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+
+/// A name interned in a `SymbolTable`. Two symbols from the same table are
+/// equal exactly when their names are, so comparing them is an integer
+/// compare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Symbol(pub(crate) u32);
+
+impl Symbol {
+    /// Numbered from 0 in the order the table first saw each name.
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+/// Stores each name once and hands out a `Symbol` for it.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    names: Vec<Rc<str>>,
+    symbols: BTreeMap<Rc<str>, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// The symbol for `name`, added to the table if it isn't there yet.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+
+        let symbol = Symbol(self.names.len() as u32);
+        let name: Rc<str> = name.into();
+        self.names.push(Rc::clone(&name));
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+
+    /// The symbol for `name` if it was interned, without adding it.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    /// The name of `symbol`, or `None` if another table gave it out.
+    pub fn name(&self, symbol: Symbol) -> Option<&str> {
+        self.names.get(symbol.0 as usize).map(|name| &**name)
+    }
+
+    /// The name of `symbol` itself, for values that keep it, like `Keyword`.
+    pub(crate) fn shared_name(&self, symbol: Symbol) -> Rc<str> {
+        Rc::clone(&self.names[symbol.0 as usize])
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Every symbol with its name, in the order they were interned.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> + '_ {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (Symbol(i as u32), &**name))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_intern() {
+        let mut symbols = SymbolTable::new();
+        let foo = symbols.intern("foo");
+        let bar = symbols.intern("bar");
+
+        assert_eq!(symbols.intern("foo"), foo);
+        assert_ne!(foo, bar);
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.get("bar"), Some(bar));
+        assert_eq!(symbols.get("baz"), None);
+        assert_eq!(symbols.name(foo), Some("foo"));
+        assert_eq!(symbols.name(Symbol(2)), None);
+        assert_eq!(
+            symbols.iter().collect::<Vec<_>>(),
+            vec![(foo, "foo"), (bar, "bar")]
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::scanner::{Lexeme, LexemeKind, ModeStack, Scanner, ScannerOptions};
+use crate::source::FileId;
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: LexemeKind,
+    pub span: Span,
+    /// The scanner modes in effect when this token was scanned, which is
+    /// what allows rescanning to resume at the token.
+    pub modes: ModeStack,
+}
+
+/// An owned source text together with all of its tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBuffer {
+    src: String,
+    tokens: Vec<Token>,
+    file: Option<FileId>,
+    /// What the tokens were scanned with, so rescanning matches them.
+    options: ScannerOptions,
+}
+
+impl TokenBuffer {
+    pub fn new(src: String) -> Self {
+        TokenBuffer::with_options(src, ScannerOptions::new())
+    }
+
+    pub fn with_options(src: String, options: ScannerOptions) -> Self {
+        let mut tokens = Vec::new();
+        push_tokens(&mut tokens, Scanner::new(&src).with_options(options));
+
+        TokenBuffer::from_parts(src, tokens, None, options)
+    }
+
+    pub(crate) fn from_parts(
+        src: String,
+        tokens: Vec<Token>,
+        file: Option<FileId>,
+        options: ScannerOptions,
+    ) -> Self {
+        TokenBuffer {
+            src,
+            tokens,
+            file,
+            options,
+        }
+    }
+
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    /// The file this buffer was scanned from, if it was given one.
+    pub fn file(&self) -> Option<FileId> {
+        self.file
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    pub fn options(&self) -> ScannerOptions {
+        self.options
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn lexeme(&self, index: usize) -> Option<Lexeme<'_>> {
+        self.tokens.get(index).map(|token| Lexeme {
+            kind: token.kind,
+            slice: &self.src[token.span.range()],
+            span: token.span,
+        })
+    }
+
+    pub fn lexemes(&self) -> impl Iterator<Item = Lexeme<'_>> + '_ {
+        (0..self.tokens.len()).filter_map(move |index| self.lexeme(index))
+    }
+}
+
+fn push_tokens(tokens: &mut Vec<Token>, mut scanner: Scanner) {
+    loop {
+        let modes = scanner.modes();
+
+        match scanner.next() {
+            Some(lex) => tokens.push(Token {
+                kind: lex.kind,
+                span: lex.span,
+                modes,
+            }),
+            None => break,
+        }
+    }
+}
+
+/// The lexemes of a borrowed source, stored as parallel arrays of kinds and
+/// start offsets. Since lexing is lossless each token ends where the next
+/// one starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenStream<'a> {
+    src: &'a str,
+    kinds: Vec<LexemeKind>,
+    starts: Vec<u32>,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(src: &'a str) -> Self {
+        TokenStream::with_options(src, ScannerOptions::new())
+    }
+
+    pub fn with_options(src: &'a str, options: ScannerOptions) -> Self {
+        let mut kinds = Vec::new();
+        let mut starts = Vec::new();
+
+        for lex in Scanner::new(src).with_options(options) {
+            kinds.push(lex.kind);
+            starts.push(lex.span.start);
+        }
+
+        TokenStream { src, kinds, starts }
+    }
+
+    pub fn src(&self) -> &'a str {
+        self.src
+    }
+
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    pub fn kinds(&self) -> &[LexemeKind] {
+        &self.kinds
+    }
+
+    pub fn starts(&self) -> &[u32] {
+        &self.starts
+    }
+
+    pub fn kind(&self, index: usize) -> Option<LexemeKind> {
+        self.kinds.get(index).copied()
+    }
+
+    pub fn span(&self, index: usize) -> Option<Span> {
+        let start = *self.starts.get(index)?;
+        let end = self
+            .starts
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.src.len() as u32);
+
+        Some(Span::new(start, end))
+    }
+
+    pub fn lexeme(&self, index: usize) -> Option<Lexeme<'a>> {
+        let span = self.span(index)?;
+
+        Some(Lexeme {
+            kind: self.kinds[index],
+            slice: &self.src[span.range()],
+            span,
+        })
+    }
+
+    /// Index of the token covering the byte at `offset`, or `None` past the
+    /// end of the source.
+    pub fn token_at_offset(&self, offset: usize) -> Option<usize> {
+        if offset >= self.src.len() {
+            return None;
+        }
+
+        match self.starts.binary_search(&(offset as u32)) {
+            Ok(index) => Some(index),
+            Err(index) => Some(index - 1),
+        }
+    }
+
+    pub fn cursor(&self) -> Cursor<'_, 'a> {
+        Cursor {
+            stream: self,
+            index: 0,
+        }
+    }
+}
+
+/// Walks a `TokenStream` from any position, yielding its lexemes.
+#[derive(Debug, Clone)]
+pub struct Cursor<'s, 'a> {
+    stream: &'s TokenStream<'a>,
+    index: usize,
+}
+
+impl<'s, 'a> Cursor<'s, 'a> {
+    /// Index of the token `next` will return.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn seek(&mut self, index: usize) {
+        self.index = index.min(self.stream.len());
+    }
+
+    /// Moves to the token covering `offset`, or to the end past the source.
+    pub fn seek_offset(&mut self, offset: usize) {
+        let index = self
+            .stream
+            .token_at_offset(offset)
+            .unwrap_or(self.stream.len());
+        self.seek(index);
+    }
+
+    pub fn peek(&self) -> Option<Lexeme<'a>> {
+        self.peek_nth(0)
+    }
+
+    pub fn peek_nth(&self, n: usize) -> Option<Lexeme<'a>> {
+        self.stream.lexeme(self.index + n)
+    }
+}
+
+impl<'s, 'a> Iterator for Cursor<'s, 'a> {
+    type Item = Lexeme<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lex = self.peek()?;
+        self.index += 1;
+        Some(lex)
+    }
+}
+
+/// Tokenizes every source into its own buffer, on the rayon thread pool
+/// when the `parallel` feature is enabled. Spans are relative to the start
+/// of each file and buffers come back in the order of `sources`.
+pub fn scan_many(sources: &[(FileId, &str)]) -> Vec<TokenBuffer> {
+    let scan = |(file, src): &(FileId, &str)| TokenBuffer::new(String::from(*src)).with_file(*file);
+
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+
+        sources.par_iter().map(scan).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        sources.iter().map(scan).collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_many() {
+        let sources = [
+            (FileId(0), "(a b)"),
+            (FileId(1), "\"unterminated"),
+            (FileId(2), ""),
+            (FileId(3), "; only a comment"),
+        ];
+
+        let buffers = scan_many(&sources);
+
+        assert_eq!(buffers.len(), sources.len());
+        for (buffer, (file, src)) in buffers.iter().zip(&sources) {
+            assert_eq!(buffer.file(), Some(*file));
+            assert_eq!(
+                buffer,
+                &TokenBuffer::new(String::from(*src)).with_file(*file)
+            );
+        }
+        assert_eq!(buffers[0].lexeme(1).unwrap().span, Span::new(1, 2));
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let src = "(foo \"bar\") ; baz";
+
+        let stream = TokenStream::new(src);
+
+        assert_eq!(
+            stream.cursor().collect::<Vec<_>>(),
+            Scanner::new(src).collect::<Vec<_>>()
+        );
+        assert_eq!(stream.token_at_offset(0), Some(0));
+        assert_eq!(stream.token_at_offset(2), Some(1));
+        assert_eq!(stream.lexeme(1).unwrap().slice, "foo");
+        assert_eq!(stream.token_at_offset(7), Some(4));
+        assert_eq!(stream.lexeme(4).unwrap().slice, "bar");
+        assert_eq!(
+            stream.token_at_offset(src.len() - 1),
+            Some(stream.len() - 1)
+        );
+        assert_eq!(stream.token_at_offset(src.len()), None);
+
+        let mut cursor = stream.cursor();
+        cursor.seek_offset(5);
+        assert_eq!(cursor.index(), 3);
+        assert_eq!(cursor.peek().unwrap().kind, LexemeKind::LString);
+        assert_eq!(cursor.peek_nth(1).unwrap().slice, "bar");
+        assert_eq!(
+            cursor.map(|lex| lex.slice).collect::<Vec<_>>(),
+            vec!["\"", "bar", "\"", ")", " ", "; baz"]
+        );
+
+        assert!(TokenStream::new("").is_empty());
+        assert_eq!(TokenStream::new("").token_at_offset(0), None);
+    }
+}